@@ -0,0 +1,368 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A very simple thread-safe cache where entries expire a set time after they're inserted. When 
+/// full, the oldest entry is thrown out. Values are cloned on the way out, so keep them reasonable
+pub struct TimedCache<K, V> {
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+    lifetime: Duration,
+    capacity: usize
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TimedCache<K, V> 
+{
+    pub fn new(lifetime: Duration, capacity: usize) -> Self {
+        TimedCache { 
+            entries: Mutex::new(HashMap::new()), 
+            lifetime, 
+            capacity 
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        //A panic while holding the lock can't leave the map in a bad state, so just keep going
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.lifetime)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < self.lifetime);
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            if let Some(oldest) = entries.iter().min_by_key(|(_, (inserted, _))| *inserted).map(|(k, _)| k.clone()) {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// What a piece of rendered markup was made from. The revision is whatever changes when the source
+/// text does (a content's lastRevisionId, a message's edit date), so stale renders are never handed out
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderKey {
+    pub kind: &'static str,
+    pub id: i64,
+    pub revision: String,
+    pub markup: String
+}
+
+impl RenderKey {
+    /// The name of this item's file in the disk cache. The revision is left out on purpose: a new
+    /// revision overwrites the old file instead of leaving it around forever
+    fn disk_name(&self) -> String {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (self.kind, &self.markup).hash(&mut hasher);
+        format!("{}-{}-{:016x}.html", self.kind, self.id, std::hash::Hasher::finish(&hasher))
+    }
+
+    /// The first line of a disk cache file, so we know whether it's for the revision we want
+    fn disk_header(&self) -> String {
+        format!("{}:{}:{}:{}\n", self.kind, self.id, self.revision, self.markup)
+    }
+}
+
+/// Point in time counts for the render cache, for the admin page
+#[derive(Clone, Debug, Default)]
+pub struct RenderCacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub disk_enabled: bool
+}
+
+struct RenderCacheEntries {
+    /// Each entry holds the tick it was last used on, which is its key in `order`
+    entries: HashMap<RenderKey, (u64, Arc<str>)>,
+    order: BTreeMap<u64, RenderKey>,
+    tick: u64
+}
+
+impl RenderCacheEntries {
+    fn touch(&mut self, key: &RenderKey) -> Option<Arc<str>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (used, value) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        self.order.insert(tick, key.clone());
+        *used = tick;
+        Some(value.clone())
+    }
+}
+
+/// A bounded least-recently-used cache of rendered markup (bbcode, 12y, etc), since parsing every post
+/// on every view adds up. Optionally backed by a directory so renders survive restarts; if anything
+/// changes how markup is rendered, that directory should be emptied.
+pub struct RenderCache {
+    entries: Mutex<RenderCacheEntries>,
+    capacity: usize,
+    disk_root: Option<PathBuf>,
+    hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64
+}
+
+impl RenderCache 
+{
+    /// A capacity of 0 disables the cache entirely (everything is rendered every time)
+    pub fn new(capacity: usize, disk_root: Option<PathBuf>) -> Self {
+        if let Some(ref root) = disk_root {
+            if let Err(error) = std::fs::create_dir_all(root) {
+                println!("WARN: couldn't create render cache directory {}: {}", root.display(), error);
+            }
+        }
+        RenderCache {
+            entries: Mutex::new(RenderCacheEntries { entries: HashMap::new(), order: BTreeMap::new(), tick: 0 }),
+            capacity,
+            disk_root,
+            hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0)
+        }
+    }
+
+    /// Get the rendered markup for the given key, calling `render` only if we don't already have it
+    pub fn get_or_render<F: FnOnce() -> String>(&self, key: RenderKey, render: F) -> Arc<str> {
+        if self.capacity == 0 {
+            return Arc::from(render());
+        }
+
+        if let Some(value) = self.lock().touch(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return value;
+        }
+
+        let value : Arc<str> = 
+            if let Some(value) = self.read_disk(&key) {
+                self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                Arc::from(value)
+            }
+            else {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                let value = render();
+                self.write_disk(&key, &value);
+                Arc::from(value)
+            };
+
+        self.insert(key, value.clone());
+        value
+    }
+
+    pub fn stats(&self) -> RenderCacheStats {
+        RenderCacheStats {
+            entries: self.lock().entries.len(),
+            capacity: self.capacity,
+            hits: self.hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            disk_enabled: self.disk_root.is_some()
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RenderCacheEntries> {
+        //Same as the timed cache: nothing in here can be left half done by a panic
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn insert(&self, key: RenderKey, value: Arc<str>) {
+        let mut entries = self.lock();
+        entries.tick += 1;
+        let tick = entries.tick;
+        if let Some((used, _)) = entries.entries.insert(key.clone(), (tick, value)) {
+            entries.order.remove(&used);
+        }
+        entries.order.insert(tick, key);
+
+        while entries.entries.len() > self.capacity {
+            match entries.order.pop_first() {
+                Some((_, oldest)) => {
+                    entries.entries.remove(&oldest);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                },
+                None => break
+            }
+        }
+    }
+
+    fn read_disk(&self, key: &RenderKey) -> Option<String> {
+        let path = self.disk_root.as_ref()?.join(key.disk_name());
+        let mut data = std::fs::read_to_string(path).ok()?;
+        let header = key.disk_header();
+        if data.starts_with(&header) {
+            Some(data.split_off(header.len()))
+        }
+        else {
+            None
+        }
+    }
+
+    fn write_disk(&self, key: &RenderKey, value: &str) {
+        if let Some(ref root) = self.disk_root {
+            //Write to the side then move into place, so a reader never sees half a file
+            let path = root.join(key.disk_name());
+            let temp = root.join(format!("{}.{}.tmp", key.disk_name(), fastrand::u32(..)));
+            let result = std::fs::write(&temp, format!("{}{}", key.disk_header(), value))
+                .and_then(|_| std::fs::rename(&temp, &path));
+            if let Err(error) = result {
+                println!("WARN: couldn't write render cache file {}: {}", path.display(), error);
+                let _ = std::fs::remove_file(&temp);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn key(id: i64, revision: &str) -> RenderKey {
+        RenderKey { kind: "post", id, revision: revision.to_string(), markup: String::from("12y2") }
+    }
+
+    /// Render through the cache, returning what came back and whether the render actually ran
+    fn render(cache: &RenderCache, key: RenderKey, text: &str) -> (String, bool) {
+        let rendered = Cell::new(false);
+        let value = cache.get_or_render(key, || { rendered.set(true); text.to_string() });
+        (value.to_string(), rendered.get())
+    }
+
+    /// A fresh directory for one test, gone when it's dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("sbs-rendercache-{}-{}-{}", name, std::process::id(), fastrand::u32(..)));
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn timed_cache_expires_and_evicts() {
+        let cache = TimedCache::new(Duration::from_millis(50), 2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.insert(3, "three");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&1), None, "the oldest goes when it's full");
+        assert_eq!(cache.get(&3), Some("three"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&3), None);
+        cache.insert(4, "four");
+        assert_eq!(cache.len(), 1, "expired entries are cleared out on insert");
+    }
+
+    #[test]
+    fn renders_once_until_the_revision_changes() {
+        let cache = RenderCache::new(10, None);
+        assert_eq!(render(&cache, key(1, "a"), "first"), (String::from("first"), true));
+        assert_eq!(render(&cache, key(1, "a"), "ignored"), (String::from("first"), false));
+        assert_eq!(render(&cache, key(1, "b"), "second"), (String::from("second"), true));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.disk_hits), (1, 2, 0));
+    }
+
+    #[test]
+    fn least_recently_used_goes_first() {
+        let cache = RenderCache::new(2, None);
+        render(&cache, key(1, "a"), "one");
+        render(&cache, key(2, "a"), "two");
+        //Using 1 again makes 2 the oldest
+        assert!(!render(&cache, key(1, "a"), "one").1);
+        render(&cache, key(3, "a"), "three");
+
+        assert!(!render(&cache, key(1, "a"), "one").1);
+        assert!(!render(&cache, key(3, "a"), "three").1);
+        assert!(render(&cache, key(2, "a"), "two").1, "2 should have been evicted");
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 2);
+    }
+
+    #[test]
+    fn capacity_zero_never_caches() {
+        let cache = RenderCache::new(0, None);
+        for _ in 0..3 {
+            assert!(render(&cache, key(1, "a"), "one").1);
+        }
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (0, 0, 0));
+    }
+
+    #[test]
+    fn disk_survives_a_restart() {
+        let dir = TempDir::new("restart");
+        {
+            let cache = RenderCache::new(10, Some(dir.0.clone()));
+            assert!(render(&cache, key(1, "a"), "from disk").1);
+        }
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        assert_eq!(render(&cache, key(1, "a"), "rendered again"), (String::from("from disk"), false));
+        assert_eq!(cache.stats().disk_hits, 1);
+        //No temp files left behind
+        assert_eq!(std::fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn disk_with_another_revision_is_rendered_again() {
+        let dir = TempDir::new("revision");
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        render(&cache, key(1, "a"), "old");
+
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        assert_eq!(render(&cache, key(1, "b"), "new"), (String::from("new"), true));
+        //...and that replaced the old file instead of adding another
+        assert_eq!(std::fs::read_dir(&dir.0).unwrap().count(), 1);
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        assert_eq!(render(&cache, key(1, "a"), "old again"), (String::from("old again"), true));
+    }
+
+    #[test]
+    fn disk_with_a_bad_header_is_rendered_again() {
+        let dir = TempDir::new("header");
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        let key = key(1, "a");
+        std::fs::write(dir.0.join(key.disk_name()), "post:1:a:bbcode\nwrong markup").unwrap();
+        assert_eq!(render(&cache, key.clone(), "right"), (String::from("right"), true));
+
+        std::fs::write(dir.0.join(key.disk_name()), "garbage with no header").unwrap();
+        let cache = RenderCache::new(10, Some(dir.0.clone()));
+        assert_eq!(render(&cache, key, "right"), (String::from("right"), true));
+        assert_eq!(cache.stats().disk_hits, 0);
+    }
+}
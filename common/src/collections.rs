@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use contentapi::*;
+use serde::{Serialize, Deserialize};
+
+use crate::constants::*;
+use crate::response::Error;
+
+/// A named list of pages a user put together (their "favorites"), stored in a user variable. Nobody else can
+/// read those, so public ones are also published as a page of their own (see publish_into) for everyone else
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Collection {
+    #[serde(skip)]
+    pub key: String,
+    pub name: String,
+    pub public: bool,
+    pub pages: Vec<i64>
+}
+
+impl Collection {
+    pub fn from_variable(variable: UserVariable) -> Result<Self, Error> {
+        let key = variable.key.ok_or_else(|| Error::Other(String::from("User variable missing key!")))?;
+        let value = variable.value.ok_or_else(|| Error::Other(format!("User variable {} missing value!", key)))?;
+        let mut collection = serde_json::from_str::<Collection>(&value)?;
+        collection.key = key;
+        Ok(collection)
+    }
+
+    pub fn new_key() -> String {
+        format!("{}{}", COLLECTIONPREFIX, crate::random_id("collection"))
+    }
+
+    /// The collection as published on the given page, if that's what it is
+    pub fn from_published(content: &Content) -> Option<Self> {
+        if content.literalType.as_deref() != Some(SBSPageType::COLLECTION) {
+            return None;
+        }
+        let values = content.values.as_ref()?;
+        Some(Collection {
+            key: values.get(SBSValue::COLLECTIONKEY)?.as_str()?.to_string(),
+            name: content.name.clone().unwrap_or_default(),
+            public: true,
+            pages: values.get(SBSValue::PAGES).and_then(|p| serde_json::from_value(p.clone()).ok()).unwrap_or_default()
+        })
+    }
+
+    /// Write the collection onto its published page (a new one is just `Content::default()`), returning
+    /// whether anything changed. Everyone can read the page, only the owner can change it
+    pub fn publish_into(&self, content: &mut Content) -> Result<bool, Error> {
+        let original = (content.name.clone(), content.values.clone(), content.permissions.clone());
+        if content.id.is_none() {
+            content.id = Some(0);
+            content.text = Some(String::new());
+        }
+        content.contentType = Some(ContentType::PAGE);
+        content.literalType = Some(SBSPageType::COLLECTION.to_string());
+        content.name = Some(self.name.clone());
+        content.permissions = Some(make_permissions! { "0": "R" });
+        let values = content.values.get_or_insert_with(HashMap::new);
+        values.insert(SBSValue::COLLECTIONKEY.to_string(), self.key.clone().into());
+        values.insert(SBSValue::PAGES.to_string(), serde_json::to_value(&self.pages)?);
+        Ok((content.name.clone(), content.values.clone(), content.permissions.clone()) != original)
+    }
+}
+
+/// How many users have this page in at least one of their collections
+pub fn get_favorite_count(content: &Content) -> i64 {
+    content.engagement.as_ref()
+        .and_then(|e| e.get(FAVORITETYPE))
+        .and_then(|f| f.get(FAVORITED))
+        .copied()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection() -> Collection {
+        Collection { key: String::from("collection_abc"), name: String::from("Games"), public: true, pages: vec![5, 3, 9] }
+    }
+
+    #[test]
+    fn published_page_round_trips() {
+        let mut page = Content::default();
+        assert!(collection().publish_into(&mut page).unwrap());
+        assert_eq!(page.id, Some(0));
+        assert_eq!(page.literalType.as_deref(), Some(SBSPageType::COLLECTION));
+
+        let published = Collection::from_published(&page).unwrap();
+        assert_eq!(published.key, "collection_abc");
+        assert_eq!(published.name, "Games");
+        assert!(published.public);
+        assert_eq!(published.pages, vec![5, 3, 9]);
+    }
+
+    #[test]
+    fn other_users_can_read_published_collections() {
+        //Group 0 is everyone, so a second user reading the owner's collection gets it from the page itself
+        let mut page = Content::default();
+        collection().publish_into(&mut page).unwrap();
+        assert_eq!(page.permissions.as_ref().and_then(|p| p.get("0")).map(|p| p.as_str()), Some("R"));
+
+        //...and finds it with a content search on the owner, never their user variables (which only the owner can read)
+        let request = crate::prefab::published_collections_request(12);
+        assert!(request.requests.iter().all(|r| r.r#type == RequestType::content.to_string()));
+        assert_eq!(request.values.get("uid"), Some(&serde_json::Value::from(12)));
+        assert!(request.requests[0].query.as_deref().unwrap().contains("createUserId = @uid"));
+    }
+
+    #[test]
+    fn republishing_only_changes_what_changed() {
+        let mut page = Content::default();
+        let mut collection = collection();
+        collection.publish_into(&mut page).unwrap();
+        page.id = Some(44);
+
+        assert!(!collection.publish_into(&mut page).unwrap(), "nothing changed, so no new revision");
+        collection.pages.push(10);
+        assert!(collection.publish_into(&mut page).unwrap());
+        assert_eq!(page.id, Some(44), "the same page is updated");
+        collection.name = String::from("Best games");
+        assert!(collection.publish_into(&mut page).unwrap());
+        assert_eq!(Collection::from_published(&page).unwrap().name, "Best games");
+    }
+
+    #[test]
+    fn other_pages_arent_collections() {
+        let mut page = Content::default();
+        collection().publish_into(&mut page).unwrap();
+        page.literalType = Some(SBSPageType::PROGRAM.to_string());
+        assert!(Collection::from_published(&page).is_none());
+        assert!(Collection::from_published(&Content::default()).is_none());
+    }
+}
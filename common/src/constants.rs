@@ -14,17 +14,25 @@ macro_rules! string_const {
     };
 }
 
+/// Name of the hidden field holding the csrf token in every POST form (see render::csrf_input)
+pub const CSRFFIELD : &str = "csrf";
+
 pub const CONTENT_CHEAPFIELDS : &str = "~keywordCount,popScore1,lastRevisionId,watchCount,commentCount,lastCommentId,engagement,keywords,permissions";
 
 string_const!{ SBSValue => {
     (DOWNLOADKEY:"dlkey"),
+    (KEYREMOVED:"dlkeyremoved"),
     (VERSION:"version"),
     (SIZE:"size"),
     (SYSTEMS:"systems"),
     (IMAGES:"images"),
     (FORCONTENT:"forcontent"),
     (MARKUP:"markup"),
-    (DOCPATH:"docpath")
+    (DOCPATH:"docpath"),
+    (ORDER:"order"),
+    (HIDDEN:"hidden"),
+    (COLLECTIONKEY:"collectionkey"),
+    (PAGES:"pages")
 }}
 
 string_const!{ SBSPageType => {
@@ -41,7 +49,8 @@ string_const!{ SBSPageType => {
     (SUBMISSIONS:"submissions"),
     (PTCFILES:"ptcfiles"),
     (DOCPARENT:"docparent"),
-    (DOCUMENTATION:"documentation")
+    (DOCUMENTATION:"documentation"),
+    (COLLECTION:"collection")
 }}
 
 
@@ -56,6 +65,8 @@ pub const USERTHEMES: &[(&str,&str)] = &[
 pub const UPVOTE: &str = "+";
 pub const DOWNVOTE: &str = "-";
 pub const VOTETYPE: &str = "vote";
+pub const FAVORITETYPE: &str = "favorite";  //Engagement type for "in one of my collections"
+pub const FAVORITED: &str = "+";
 
 pub const POPSCORE1SORT: &str = "popScore1_desc";
 pub const ANYSYSTEM: &str = "any";
@@ -76,8 +87,8 @@ pub const SBSSYSTEMS: &[(&str,&str)] = &[
 
 pub const SBSMARKUPS: &[(&str,&str)] = &[
     (MARKUPBBCODE, "BBCode (static rendered!)"),
-    ("12y", "12y original (js rendered!)"),
-    ("12y2", "12y2 (unstable? js rendered!)")
+    ("12y", "12y original (static rendered!)"),
+    ("12y2", "12y2 (unstable? static rendered!)")
 ];
 
 pub fn get_sbs_system_title(key: &str) -> Option<&str> {
@@ -127,4 +138,6 @@ pub const SEARCHPAGEORDERS: &[(&str,&str)] = &[
     ("random", "Random")
 ];
 
-pub const CATEGORYPREFIX: &str = "tag:";
\ No newline at end of file
+pub const CATEGORYPREFIX: &str = "tag:";
+pub const SAVEDSEARCHPREFIX: &str = "savedsearch_"; //User variable key prefix for saved searches
+pub const COLLECTIONPREFIX: &str = "collection_"; //User variable key prefix for collections
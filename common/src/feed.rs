@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc, SecondsFormat};
+use contentapi::*;
+use maud::*;
+
+use crate::{LinkConfig, user_or_default};
+
+//Atom feeds. Maud is for html, but as long as every element gets a body (even an empty one) it makes
+//perfectly good xml, so we keep using it here
+
+pub const ATOMCONTENTTYPE: &str = "application/atom+xml; charset=utf-8";
+pub const FEEDAUTHOR: &str = "SmileBASIC Source";
+
+pub struct FeedAuthor {
+    pub name: String,
+    pub uri: Option<String>
+}
+
+impl FeedAuthor {
+    pub fn from_user(links: &LinkConfig, user: &User) -> Self {
+        FeedAuthor { name: user.username.clone(), uri: Some(links.absolute(&links.user(user))) }
+    }
+}
+
+pub struct FeedEntry {
+    pub id: String,         //Must be unique forever; we just use the full link (plus a fragment if needed)
+    pub title: String,
+    pub link: String,       //Already absolute
+    pub updated: DateTime<Utc>,
+    pub published: Option<DateTime<Utc>>,
+    pub author: Option<FeedAuthor>,
+    pub summary: Option<String>,    //Plain text
+    pub content: Option<String>     //Html, escaped on output like atom wants
+}
+
+pub struct Feed {
+    pub title: String,
+    pub link: String,       //The html page this feed is for, absolute
+    pub self_link: String,  //The feed itself, absolute. Also used as the id
+    pub entries: Vec<FeedEntry>
+}
+
+impl Feed {
+    /// Make a feed out of site links (relative is fine, they're made absolute here)
+    pub fn new(links: &LinkConfig, title: String, link: &str, self_link: &str, entries: Vec<FeedEntry>) -> Self {
+        Feed {
+            title,
+            link: links.absolute(link),
+            self_link: links.absolute(self_link),
+            entries
+        }
+    }
+
+    /// The feed is as new as its newest entry (or right now if there's nothing)
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.entries.iter().map(|e| e.updated).max().unwrap_or_else(Utc::now)
+    }
+
+    pub fn render(&self) -> String {
+        html! {
+            (PreEscaped("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"))
+            feed xmlns="http://www.w3.org/2005/Atom" {
+                id { (self.self_link) }
+                title { (self.title) }
+                updated { (atom_date(&self.updated())) }
+                link rel="self" type="application/atom+xml" href=(self.self_link) {}
+                link rel="alternate" type="text/html" href=(self.link) {}
+                author { name { (FEEDAUTHOR) } }
+                @for entry in &self.entries {
+                    entry {
+                        id { (entry.id) }
+                        title { (entry.title) }
+                        updated { (atom_date(&entry.updated)) }
+                        @if let Some(ref published) = entry.published {
+                            published { (atom_date(published)) }
+                        }
+                        link rel="alternate" type="text/html" href=(entry.link) {}
+                        @if let Some(ref author) = entry.author {
+                            author {
+                                name { (author.name) }
+                                @if let Some(ref uri) = author.uri {
+                                    uri { (uri) }
+                                }
+                            }
+                        }
+                        @if let Some(ref summary) = entry.summary {
+                            summary type="text" { (summary) }
+                        }
+                        @if let Some(ref content) = entry.content {
+                            content type="html" { (content) }
+                        }
+                    }
+                }
+            }
+        }.into_string()
+    }
+}
+
+pub fn atom_date(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// A feed entry for a program/resource/etc page, as given by the search requests. Pages are as new as
+/// their last action (edits, comments), falling back to when they were made
+pub fn page_entry(links: &LinkConfig, page: &Content, users: &HashMap<i64, User>) -> FeedEntry {
+    let link = links.absolute(&links.forum_thread(page));
+    let author = FeedAuthor::from_user(links, &user_or_default(users.get(&page.createUserId.unwrap_or(0))));
+    FeedEntry {
+        id: link.clone(),
+        title: page.name.clone().unwrap_or_default(),
+        link,
+        updated: page.lastActionDate.or(page.createDate).unwrap_or_default(),
+        published: page.createDate,
+        author: Some(author),
+        summary: page.description.clone(),
+        content: None
+    }
+}
@@ -42,6 +42,17 @@ pub struct BasicPage
     pub text: String
 }
 
+/// The frontpage, alert and documentation preamble forms on the admin page. Those are raw html, so they
+/// can be previewed to see what the sanitizer takes out before anything is saved
+#[derive(Deserialize, Debug)]
+pub struct SystemHtmlForm
+{
+    pub id: i64,
+    pub text: String,
+    #[serde(default)]
+    pub preview: bool
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ThreadForm
 {
@@ -80,6 +91,8 @@ pub struct PageForm
 
     //These are optional fields, for programs
     pub key: Option<String>,
+    pub key_removed: Option<bool>,          //Key is dead (taken down from the SB servers), only set if checked
+    pub key_removed_date: Option<String>,   //When the key died; defaults to the day it was marked
     pub version: Option<String>,
     pub size: Option<String>,
     pub systems: Option<String>,     //Same as keywords
@@ -103,6 +116,73 @@ pub struct VoteForm
     pub vote: String
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CollectionWidgetForm
+{
+    pub key: Option<String>,    //Not set when making a new collection
+    pub name: Option<String>,   //Only for new collections
+    #[serde(default)]
+    pub remove: bool            //Remove the page from the collection instead of adding
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CollectionForm
+{
+    pub key: String,
+    pub name: String,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default)]
+    pub delete: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CategoryForm
+{
+    pub id: i64,            //0 for a new category
+    pub name: String,
+    pub forcontent: String, //Which page type (program/resource) it's for
+    #[serde(default)]
+    pub order: i64,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub delete: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ForumCategoryForm
+{
+    pub id: i64,            //0 for a new category
+    pub name: String,
+    pub description: String,
+    pub order: String,      //Empty means use the config order
+    pub permissions: String //One of the admin page permission presets
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RetagForm
+{
+    pub from: i64,
+    pub to: i64             //0 means just remove the category from the pages
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SaveSearchForm
+{
+    pub name: String,
+    pub query: String   //The raw query string of the search, same as what /search gets
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SavedSearchForm
+{
+    pub key: String,
+    pub name: String,
+    #[serde(default)]
+    pub delete: bool    //Delete instead of rename
+}
+
 // ------------------------
 // *    QUERY PARAMS      *
 // ------------------------
@@ -113,8 +193,8 @@ pub struct PageSearch {
     pub search: Option<String>,
     pub order: String, 
     pub subtype: Option<String>, 
-    pub system: String,
-    pub category: Option<i64>,
+    pub system: String,             //Compound value (see parse_compound_value), "any" or empty means any system
+    pub category: Option<String>,   //Compound value of category ids, 0 or empty means any category
     pub user_id: Option<i64>,
     pub removed: bool,
     pub page: i32
@@ -135,6 +215,18 @@ impl Default for PageSearch {
     }
 }
 
+impl PageSearch {
+    /// All the systems selected in this search, which is empty if any system is allowed
+    pub fn get_systems(&self) -> Vec<String> {
+        crate::parse_compound_value(&self.system).into_iter().filter(|s| s != ANYSYSTEM).collect()
+    }
+    /// All the category ids selected in this search, which is empty if any category is allowed
+    pub fn get_categories(&self) -> Vec<i64> {
+        crate::parse_compound_value(self.category.as_deref().unwrap_or(""))
+            .into_iter().filter_map(|c| c.parse::<i64>().ok()).filter(|c| *c != 0).collect()
+    }
+}
+
 // Unfortunately need this in here so the post knows how to render the iframe
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
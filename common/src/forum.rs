@@ -107,7 +107,8 @@ pub struct CleanedPreCategory {
     pub category: Content,
     pub stickies: Vec<i64>,
     pub id: i64,
-    pub name: String
+    pub name: String,
+    pub order: Option<i64> //Set from the admin page; categories without it fall back to the config order
 }
 
 impl CleanedPreCategory {
@@ -133,8 +134,9 @@ impl CleanedPreCategory {
         else {
             stickies = Vec::new();
         }
+        let order = cvalues.get(SBSValue::ORDER).and_then(|o| o.as_i64());
         //let stickies = category.get_stickies()?;
-        Ok(CleanedPreCategory { category: category, stickies, id, name })
+        Ok(CleanedPreCategory { category, stickies, id, name, order })
     }
 
     pub fn from_many(categories: Vec<Content>) -> Result<Vec<CleanedPreCategory>, Error> {
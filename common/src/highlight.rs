@@ -0,0 +1,584 @@
+//! SmileBASIC syntax highlighting, a port of static/sb-highlight.js. The css classes (and the
+//! places they land) are the same as the js produces, so the themes don't need to know whether
+//! a code block was highlighted here or in the browser.
+
+const KEYWORDS: &[&str] = &["BREAK","COMMON","CONTINUE","ELSE","END","ENDIF","REM","REPEAT","THEN","WEND"];
+const KEYWORDS_SB3: &[&str] = &["STOP"];
+const KEYWORDS_SB4: &[&str] = &["OTHERWISE","ENDCASE","LOOP","ENDLOOP"];
+const ARGKEYWORDS: &[&str] = &["CALL","DATA","DEC","DIM","ELSEIF","EXEC","FOR","GOSUB","GOTO","IF","INC","INPUT","LINPUT","NEXT","ON","OUT","PRINT","READ","RESTORE","RETURN","SWAP","UNTIL","USE","VAR","WHILE"];
+const ARGKEYWORDS_SB4: &[&str] = &["CASE","WHEN","DEFOUT","TPRINT","CONST","ENUM"];
+const BUILTIN_FUNCTIONS: &[&str] = &["ABS","ACCEL","ACLS","ACOS","ARYOP","ASC","ASIN","ATAN","ATTR","BACKCOLOR","BEEP","BGMCHK","BGMCLEAR","BGMCONT","BGMPAUSE","BGMPLAY","BGMSET","BGMSETD","BGMSTOP","BGMVAR","BGMVOL","BIN$","BIQUAD","BQPARAM","BREPEAT","BUTTON","CEIL","CHKCALL","CHKCHR","CHKFILE","CHKLABEL","CHKMML","CHKVAR","CHR$","CLASSIFY","CLIPBOARD","CLS","COLOR","CONTROLLER","COPY","COS","COSH","DEG","DELETE","DIALOG","DTREAD","EFCSET","EFCWET","EXP","FADE","FADECHK","FFT","FFTWFN","FILES","FILL","FLOOR","FORMAT$","GBOX","GCIRCLE","GCLIP","GCLS","GCOLOR","GCOPY","GFILL","GLINE","GLOAD","GPAINT","GPSET","GPUTCHR","GSAVE","GTRI","GYROA","GYROSYNC","GYROV","HEX$","IFFT","INKEY$","INSTR","KEY","LEFT$","LEN","LOAD","LOCATE","LOG","MAX","MID$","MIN","OPTION","PCMCONT","PCMSTOP","PCMSTREAM","PCMVOL","POP","POW","PRGDEL","PRGEDIT","PRGGET$","PRGINS","PRGNAME$","PRGSET","PRGSIZE","PROJECT","PUSH","RAD","RANDOMIZE","RENAME","RGB","RIGHT$","RINGCOPY","RND","RNDF","ROUND","RSORT","SAVE","SCROLL","SGN","SHIFT","SIN","SINH","SNDSTOP","SORT","SPANIM","SPCHK","SPCHR","SPCLR","SPCOL","SPCOLOR","SPCOLVEC","SPDEF","SPFUNC","SPHIDE","SPHITINFO","SPHITRC","SPHITSP","SPHOME","SPLINK","SPOFS","SPPAGE","SPROT","SPSCALE","SPSET","SPSHOW","SPSTART","SPSTOP","SPUNLINK","SPUSED","SPVAR","SQR","STICK","STR$","SUBST$","TALK","TALKCHK","TALKSTOP","TAN","TANH","TMREAD","TOUCH","UNSHIFT","VAL","VSYNC","WAIT","WAVSET","WAVSETA","XSCREEN","VIBRATE","PI",
+    //ptc
+    "CLEAR","BGMGETV","BGMSETV","BGREAD","BTRIG","CHRINIT","CHRREAD","CHRSET","COLINIT","COLREAD","COLSET","GDRAWMD","ICONCHK","ICONCLR","ICONSET","PNLSTR","PNLTYPE","SENDFILE","RECVFILE","SPANGLE","SPGETV","SPSETV","SPREAD","VISIBLE"];
+const BUILTIN_FUNCTIONS_SB3: &[&str] = &["BACKTRACE","BGANIM","BGCHK","BGCLIP","BGCLR","BGCOLOR","BGCOORD","BGCOPY","BGFILL","BGFUNC","BGGET","BGHIDE","BGHOME","BGLOAD","BGOFS","BGPAGE","BGPUT","BGROT","BGSAVE","BGSCALE","BGSCREEN","BGSHOW","BGSTART","BGSTOP","BGVAR","BGMPRG","BGMPRGA","DISPLAY","DLCOPEN","EFCOFF","EFCON","FONTDEF","GOFS","GPAGE","GPRIO","GSPOIT","MICDATA","MICSAVE","MICSTART","MICSTOP","MPEND","MPGET","MPNAME$","MPRECV","MPSEND","MPSET","MPSTART","MPSTAT","STICKEX","RGBREAD","SPCLIP","VISIBLE","WIDTH","XOFF","XON","GPUTCHR16"];
+const BUILTIN_FUNCTIONS_SB4: &[&str] = &["PCMPOS","TYPEOF","ARRAY#","ARRAY%","ARRAY$","RESIZE","INSERT","REMOVE","FIND","INSPECT","DEFARGC","DEFARG","DEFOUTC","INT","FLOAT","LAST","FONTINFO","PERFBEGIN","PERFEND","SYSPARAM","METAEDIT","METALOAD","METASAVE","XCTRLSTYLE","MOUSE","MBUTTON","IRSTART","IRSTOP","IRSTATE","IRREAD","IRSPRITE","KEYBOARD","TCPIANO","TCHOUSE","TCROBOT","TCFISHING","TCBIKE","TCVISOR","TCCAR","TCPLANE","TCSUBM","TCVEHICLE","LOADG","LOADV","SAVEG","SAVEV","ANIMDEF","TSCREEN","TPAGE","TCOLOR","TLAYER","TPUT","TFILL","THOME","TOFS","TROT","TSCALE","TSHOW","THIDE","TBLEND","TANIM","TSTOP","TSTART","TCHK","TVAR","TCOPY","TSAVE","TLOAD","TARRAY","TUPDATE","TFUNC","TCOORD","GTARGET","RGBF","HSV","HSVF","GPGET","GARRAY","GUPDATE","GSAMPLE","GPUTCHRP","SPLAYER","STOP","LAYER","LMATRIX","LFILTER","LCLIP","BEEPPIT","BEEPPAN","BEEPVOL","BEEPSTOP","BGMPITCH","BGMWET","EFCEN","SNDMSBAL","SNDMVOL","PRGSEEK","XSUBSCREEN","ENVSTAT","ENVTYPE","ENVLOAD","ENVSAVE","ENVINPUT$","ENVFOCUS","ENVPROJECT","ENVLOCATE","PUSHKEY","HELPGET","HELPINFO","UISTATE","UIMASK","UIPUSHCMPL","DATE$","TIME$","RESULT","CALLIDX","FREEMEM","MILLISEC","MAINCNT"];
+const SYSTEM_VARIABLES: &[&str] = &["CALLIDX","CSRX","CSRY","CSRZ","DATE$","TIME$","ERRLINE","ERRNUM","ERRPRG","EXTFEATURE","FREEMEM","HARDWARE","MAINCNT","MICPOS","MICSIZE","MILLISEC","MPCOUNT","MPHOST","MPLOCAL","PCMPOS","PRGSLOT","RESULT","SYSBEEP","TABSTEP","VERSION",
+    //ptc
+    "ERR","ERL","MAINCNTH","MAINCNTL","TCHST","TCHTIME","TCHX","TCHY","ICONPUSE","ICONPAGE","ICONPMAX","FUNCNO","FREEVAR","KEYBOARD","SPHITNO","SPHITX","SPHITY","MEM$","PRGNAME$","PACKAGE$"];
+const WORD_CONST: &[&str] = &["TRUE","FALSE",
+    //ptc
+    "CANCEL"];
+const WORD_OPERATORS: &[&str] = &["AND","OR","XOR","NOT","DIV","MOD"];
+
+/// The token types the tokenizer tracks to decide what a word is. The js uses strings for these
+/// (and undefined for "no type"), which is where the odd names come from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Type {
+    None,
+    Start,
+    Word,
+    Number,
+    Operator,
+    Def,
+    Keyword,
+    ArgKeyword,
+    Name,
+    Function,
+    Variable,
+    Label,
+    String,
+    Comment,
+    Equals,
+    Expr,
+    NoExpr,
+    Linebreak,
+    Whitespace,
+    Eof
+}
+
+impl Type {
+    /// The class the js falls back to when push() isn't given one
+    fn default_class(self) -> Option<&'static str> {
+        match self {
+            Type::Number => Some("number"),
+            Type::Operator => Some("operator"),
+            Type::Label => Some("label"),
+            Type::String => Some("string"),
+            Type::Comment => Some("comment"),
+            Type::Equals => Some("equals"),
+            Type::Whitespace => Some("whitespace"),
+            Type::Eof => Some("eof"),
+            _ => None
+        }
+    }
+
+    fn is_in_expr(self) -> bool {
+        matches!(self, Type::ArgKeyword | Type::Function | Type::Operator | Type::Name | Type::Equals | Type::Expr)
+    }
+}
+
+/// The css class for a token, if the js would assign it one. "Given" means push() was called with an
+/// explicit class (or explicitly false), "Default" means it'd be figured out from the type
+enum Class {
+    Default,
+    Given(Option<&'static str>)
+}
+
+struct Tokenizer<'a, F: FnMut(&[char], Option<&'static str>)> {
+    code: &'a [char],
+    i: usize,
+    c: Option<char>,
+    prev: usize,
+    prev_type: Type,
+    /// Some(true) for sb4, Some(false) for sb3 and older, None for "don't know"
+    sb4: Option<bool>,
+    callback: F
+}
+
+fn is_digit(c: Option<char>) -> bool { matches!(c, Some(c) if c.is_ascii_digit()) }
+fn is_hex(c: Option<char>) -> bool { matches!(c, Some(c) if c.is_ascii_hexdigit()) }
+fn is_ident(c: Option<char>) -> bool { matches!(c, Some(c) if c.is_ascii_alphanumeric() || c == '_') }
+fn is_linebreak(c: Option<char>) -> bool { matches!(c, None | Some('\n') | Some('\r')) }
+
+impl<'a, F: FnMut(&[char], Option<&'static str>)> Tokenizer<'a, F> {
+
+    fn next(&mut self) {
+        self.i += 1;
+        self.c = self.code.get(self.i).copied();
+    }
+
+    fn jump(&mut self, pos: usize) {
+        self.i = pos;
+        self.c = self.code.get(self.i).copied();
+    }
+
+    fn not_sb3(&self) -> bool { self.sb4 != Some(false) }
+    fn not_sb4(&self) -> bool { self.sb4 != Some(true) }
+
+    /// Work out what the word just read is. This is where most of the "smarts" are: whether something
+    /// is a function call or a variable depends on what came before it and what comes after it
+    fn classify_word(&mut self, word: &str) -> (Type, Option<&'static str>) {
+        let upper = word.to_ascii_uppercase();
+        let upper = upper.as_str();
+        if WORD_CONST.contains(&upper) {
+            (Type::Number, Some("true-false number"))
+        }
+        else if WORD_OPERATORS.contains(&upper) {
+            (Type::Operator, Some("word-operator operator"))
+        }
+        else if upper == "DEF" {
+            (Type::Def, Some("def keyword"))
+        }
+        else if KEYWORDS.contains(&upper) || self.sb4 == Some(false) && KEYWORDS_SB3.contains(&upper) ||
+                self.not_sb3() && KEYWORDS_SB4.contains(&upper) {
+            (Type::Keyword, Some("keyword"))
+        }
+        else if ARGKEYWORDS.contains(&upper) || self.not_sb3() && ARGKEYWORDS_SB4.contains(&upper) {
+            (Type::ArgKeyword, Some("keyword"))
+        }
+        else if self.prev_type == Type::Def {
+            (Type::Name, Some("name"))
+        }
+        else {
+            //Peek past any spaces to see what's after the word, then come back
+            let word_end = self.i;
+            while matches!(self.c, Some(' ') | Some('\t')) {
+                self.next();
+            }
+            let is_func = 
+                if self.prev_type.is_in_expr() {
+                    self.c == Some('(')
+                }
+                else if self.c == Some('[') {
+                    false
+                }
+                else if self.c == Some('=') {
+                    //Assignment means variable, but == is a comparison
+                    self.next();
+                    self.c == Some('=')
+                }
+                else {
+                    true
+                };
+            self.jump(word_end);
+            if is_func {
+                let class =
+                    if BUILTIN_FUNCTIONS.contains(&upper) || self.not_sb4() && BUILTIN_FUNCTIONS_SB3.contains(&upper) ||
+                       self.not_sb3() && BUILTIN_FUNCTIONS_SB4.contains(&upper) {
+                        "statement function"
+                    }
+                    else if upper == "TO" || upper == "STEP" {
+                        "to-step keyword"
+                    }
+                    else {
+                        "statement"
+                    };
+                (Type::Function, Some(class))
+            }
+            else if self.not_sb4() && SYSTEM_VARIABLES.contains(&upper) {
+                (Type::Variable, Some("variable function"))
+            }
+            else {
+                (Type::Variable, Some("variable"))
+            }
+        }
+    }
+
+    /// Emit everything read since the last push as a single token
+    fn push(&mut self, mut typ: Type, class: Class) {
+        let end = self.i.min(self.code.len());
+        let start = self.prev.min(end);
+        let mut word_end = end;
+        self.prev = self.i;
+        let css = match typ {
+            Type::Word => {
+                let word : String = self.code[start..end].iter().collect();
+                if word.eq_ignore_ascii_case("T") && self.c == Some('?') {
+                    //T? is the same as TPRINT
+                    self.next();
+                    self.prev = self.i;
+                    word_end = self.i.min(self.code.len());
+                    typ = Type::Keyword;
+                    Some("keyword")
+                }
+                else {
+                    let (word_type, css) = self.classify_word(&word);
+                    typ = word_type;
+                    css
+                }
+            },
+            Type::Label => {
+                if self.prev_type.is_in_expr() {
+                    typ = Type::String;
+                    Some("label-string string")
+                }
+                else {
+                    Some("label")
+                }
+            },
+            _ => match class {
+                Class::Default => typ.default_class(),
+                Class::Given(css) => css
+            }
+        };
+        (self.callback)(&self.code[start..word_end], css);
+        if typ != Type::Whitespace {
+            self.prev_type = typ;
+        }
+    }
+
+    fn run(&mut self) {
+        self.jump(0);
+        while let Some(c) = self.c {
+            if c.is_ascii_alphabetic() || c == '_' {
+                self.next();
+                while is_ident(self.c) {
+                    self.next();
+                }
+                if matches!(self.c, Some('#') | Some('%') | Some('$')) {
+                    self.next();
+                }
+                self.push(Type::Word, Class::Default);
+            }
+            else if c.is_ascii_digit() || c == '.' {
+                while is_digit(self.c) {
+                    self.next();
+                }
+                if self.c == Some('.') {
+                    self.next();
+                    if is_digit(self.c) {
+                        while is_digit(self.c) {
+                            self.next();
+                        }
+                    }
+                    else {
+                        if self.c == Some('#') {
+                            self.next();
+                        }
+                        self.push(Type::Number, Class::Default);
+                        continue;
+                    }
+                }
+                if matches!(self.c, Some('E') | Some('e')) {
+                    let e_pos = self.i;
+                    self.next();
+                    if matches!(self.c, Some('+') | Some('-')) {
+                        self.next();
+                    }
+                    if is_digit(self.c) {
+                        while is_digit(self.c) {
+                            self.next();
+                        }
+                    }
+                    else {
+                        //Not actually an exponent; the js leaves what came before it unclassed
+                        self.jump(e_pos);
+                        self.push(Type::None, Class::Default);
+                        continue;
+                    }
+                }
+                if self.c == Some('#') {
+                    self.next();
+                }
+                self.push(Type::Number, Class::Default);
+            }
+            else {
+                self.next();
+                match c {
+                    '"' => {
+                        while !is_linebreak(self.c) && self.c != Some('"') {
+                            self.next();
+                        }
+                        if self.c == Some('"') {
+                            self.next();
+                        }
+                        self.push(Type::String, Class::Default);
+                    },
+                    '\'' => {
+                        while !is_linebreak(self.c) {
+                            self.next();
+                        }
+                        self.push(Type::Comment, Class::Default);
+                    },
+                    '&' => match self.c {
+                        Some('&') => {
+                            self.next();
+                            self.push(Type::Operator, Class::Default);
+                        },
+                        Some('H') | Some('h') => self.radix(|c, sb4| is_hex(c) || c == Some('_') && sb4),
+                        Some('B') | Some('b') => self.radix(|c, sb4| matches!(c, Some('0') | Some('1')) || c == Some('_') && sb4),
+                        _ => self.push(Type::None, Class::Default)
+                    },
+                    '@' => {
+                        while is_ident(self.c) {
+                            self.next();
+                        }
+                        self.push(Type::Label, Class::Default);
+                    },
+                    '#' => {
+                        if is_ident(self.c) {
+                            while is_ident(self.c) {
+                                self.next();
+                            }
+                            if matches!(self.c, Some('#') | Some('%') | Some('$')) {
+                                self.next();
+                            }
+                            self.push(Type::Number, Class::Given(Some("constant number")));
+                        }
+                        else if matches!(self.c, Some('#') | Some('%') | Some('$')) {
+                            self.next();
+                            self.push(Type::Number, Class::Given(Some("constant number")));
+                        }
+                        else {
+                            self.push(Type::None, Class::Default);
+                        }
+                    },
+                    '|' => {
+                        if self.c == Some('|') {
+                            self.next();
+                            self.push(Type::Operator, Class::Default);
+                        }
+                        else {
+                            self.push(Type::None, Class::Default);
+                        }
+                    },
+                    '<' | '>' => {
+                        if self.c == Some('=') || self.c == Some(c) {
+                            self.next();
+                        }
+                        self.push(Type::Operator, Class::Default);
+                    },
+                    '=' => {
+                        if self.c == Some('=') {
+                            self.next();
+                            self.push(Type::Operator, Class::Default);
+                        }
+                        else {
+                            self.push(Type::Equals, Class::Default);
+                        }
+                    },
+                    '!' => {
+                        if self.c == Some('=') {
+                            self.next();
+                        }
+                        self.push(Type::Operator, Class::Default);
+                    },
+                    '+' | '-' | '*' | '/' => self.push(Type::Operator, Class::Default),
+                    '\\' => {
+                        if self.sb4 == Some(false) {
+                            self.push(Type::None, Class::Given(None));
+                        }
+                        else {
+                            //Line continuation in sb4, which eats the newline too
+                            while !is_linebreak(self.c) {
+                                self.next();
+                            }
+                            self.next();
+                            self.push(Type::Whitespace, Class::Default);
+                        }
+                    },
+                    ';' | ',' | '[' | '(' => self.push(Type::Expr, Class::Given(None)),
+                    '\n' => self.push(Type::Linebreak, Class::Given(None)),
+                    ':' | ')' | ']' => self.push(Type::NoExpr, Class::Given(None)),
+                    ' ' | '\t' => self.push(Type::Whitespace, Class::Given(None)),
+                    '?' => self.push(Type::ArgKeyword, Class::Given(Some("question keyword"))),
+                    _ => self.push(Type::None, Class::Given(None))
+                }
+            }
+        }
+        self.push(Type::Eof, Class::Default);
+    }
+
+    /// &H and &B numbers. If there are no digits after the prefix, only the & is emitted (unclassed)
+    fn radix(&mut self, digit: fn(Option<char>, bool) -> bool) {
+        let prefix_pos = self.i;
+        self.next();
+        let underscores = self.not_sb3();
+        if digit(self.c, underscores) {
+            while digit(self.c, underscores) {
+                self.next();
+            }
+            self.push(Type::Number, Class::Default);
+        }
+        else {
+            self.jump(prefix_pos);
+            self.push(Type::None, Class::Default);
+        }
+    }
+}
+
+/// Split SmileBASIC code into tokens, calling `callback` with each token and its css class (if any).
+/// `sb4` is Some(true) for SB4, Some(false) for SB3 and older (PTC), and None when unknown.
+pub fn tokenize_smilebasic<F: FnMut(&[char], Option<&'static str>)>(code: &str, sb4: Option<bool>, callback: F) {
+    let chars : Vec<char> = code.chars().collect();
+    let mut tokenizer = Tokenizer {
+        code: &chars,
+        i: 0,
+        c: None,
+        prev: 0,
+        prev_type: Type::Start,
+        sb4,
+        callback
+    };
+    tokenizer.run();
+}
+
+fn escape_into(result: &mut String, text: &[char]) {
+    for c in text {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            _ => result.push(*c)
+        }
+    }
+}
+
+/// Which dialect (if any) a code block's language tag means. Blocks with no language are assumed
+/// to be SmileBASIC of some kind, and anything we don't know isn't highlighted at all, like the js
+/// does for [code=whatever] (markup code blocks are another story, see markup/render.rs).
+pub fn smilebasic_dialect(lang: Option<&str>) -> Option<Option<bool>> {
+    let lang = lang.map(|l| l.trim().to_ascii_lowercase()).unwrap_or_default();
+    match lang.as_str() {
+        "" | "sb" | "smilebasic" => Some(None),
+        "sb4" => Some(Some(true)),
+        "sb3" | "sb2" | "ptc" => Some(Some(false)),
+        _ => None
+    }
+}
+
+/// Produce the inner html for a code block: SmileBASIC is split into classed spans (neighboring
+/// tokens of the same class share a span), everything else is just escaped.
+pub fn highlight_code(code: &str, lang: Option<&str>) -> String {
+    let mut result = String::with_capacity(code.len() * 2);
+    match smilebasic_dialect(lang) {
+        Some(sb4) => {
+            let mut open : Option<&'static str> = None;
+            tokenize_smilebasic(code, sb4, |word, class| {
+                if word.is_empty() {
+                    return;
+                }
+                if class != open {
+                    if open.is_some() {
+                        result.push_str("</span>");
+                    }
+                    if let Some(class) = class {
+                        result.push_str(&format!("<span class=\"{}\">", class));
+                    }
+                    open = class;
+                }
+                escape_into(&mut result, word);
+            });
+            if open.is_some() {
+                result.push_str("</span>");
+            }
+        },
+        None => {
+            let chars : Vec<char> = code.chars().collect();
+            escape_into(&mut result, &chars);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (code, dialect, what static/sb-highlight.js makes of it). The js doesn't escape >, which is the same
+    /// html either way
+    const GOLDEN: &[(&str, Option<bool>, &str)] = &[
+        ("PRINT \"HELLO\"", None,
+            "<span class=\"keyword\">PRINT</span> <span class=\"string\">\"HELLO\"</span>"),
+        ("A=1+2*3 'comment", None,
+            "<span class=\"variable\">A</span><span class=\"equals\">=</span><span class=\"number\">1</span><span class=\"operator\">+</span><span class=\"number\">2</span><span class=\"operator\">*</span><span class=\"number\">3</span> <span class=\"comment\">'comment</span>"),
+        ("@LOOP\nGOTO @LOOP", None,
+            "<span class=\"label\">@LOOP</span>\n<span class=\"keyword\">GOTO</span> <span class=\"label-string string\">@LOOP</span>"),
+        ("X=&HFF+&B101+1.5E3", None,
+            "<span class=\"variable\">X</span><span class=\"equals\">=</span><span class=\"number\">&amp;HFF</span><span class=\"operator\">+</span><span class=\"number\">&amp;B101</span><span class=\"operator\">+</span><span class=\"number\">1.5E3</span>"),
+        ("STOP", Some(true),
+            "<span class=\"statement function\">STOP</span>"),
+        ("STOP", Some(false),
+            "<span class=\"keyword\">STOP</span>"),
+        ("STOP", None,
+            "<span class=\"statement function\">STOP</span>"),
+        ("LOOP:ENDLOOP", Some(true),
+            "<span class=\"keyword\">LOOP</span>:<span class=\"keyword\">ENDLOOP</span>"),
+        ("LOOP:ENDLOOP", Some(false),
+            "<span class=\"statement\">LOOP</span>:<span class=\"statement\">ENDLOOP</span>"),
+        ("BGPUT 0,1,2,3", Some(false),
+            "<span class=\"statement function\">BGPUT</span> <span class=\"number\">0</span>,<span class=\"number\">1</span>,<span class=\"number\">2</span>,<span class=\"number\">3</span>"),
+        ("BGPUT 0,1,2,3", Some(true),
+            "<span class=\"statement\">BGPUT</span> <span class=\"number\">0</span>,<span class=\"number\">1</span>,<span class=\"number\">2</span>,<span class=\"number\">3</span>"),
+        ("TPRINT 1", Some(true),
+            "<span class=\"keyword\">TPRINT</span> <span class=\"number\">1</span>"),
+        ("TPRINT 1", Some(false),
+            "<span class=\"statement\">TPRINT</span> <span class=\"number\">1</span>"),
+        ("DEF F(A) RETURN A*2 END", None,
+            "<span class=\"def keyword\">DEF</span> <span class=\"name\">F</span>(<span class=\"variable\">A</span>) <span class=\"keyword\">RETURN</span> <span class=\"variable\">A</span><span class=\"operator\">*</span><span class=\"number\">2</span> <span class=\"keyword\">END</span>"),
+        ("VAR A$=\"X\" AND B", None,
+            "<span class=\"keyword\">VAR</span> <span class=\"variable\">A$</span><span class=\"equals\">=</span><span class=\"string\">\"X\"</span> <span class=\"word-operator operator\">AND</span> <span class=\"variable\">B</span>"),
+        ("IF A<B && C>D THEN ?\"<&>\"", None,
+            "<span class=\"keyword\">IF</span> <span class=\"variable\">A</span><span class=\"operator\">&lt;</span><span class=\"variable\">B</span> <span class=\"operator\">&amp;&amp;</span> <span class=\"variable\">C</span><span class=\"operator\">></span><span class=\"variable\">D</span> <span class=\"keyword\">THEN</span> <span class=\"question keyword\">?</span><span class=\"string\">\"&lt;&amp;>\"</span>"),
+        ("ICONSET 0,1:PRINT CANCEL", Some(false),
+            "<span class=\"statement function\">ICONSET</span> <span class=\"number\">0</span>,<span class=\"number\">1</span>:<span class=\"keyword\">PRINT</span> <span class=\"true-false number\">CANCEL</span>"),
+        ("X=TRUE OR FALSE", None,
+            "<span class=\"variable\">X</span><span class=\"equals\">=</span><span class=\"true-false number\">TRUE</span> <span class=\"word-operator operator\">OR</span> <span class=\"true-false number\">FALSE</span>"),
+        ("PRINT MAINCNT, CSRX", Some(true),
+            "<span class=\"keyword\">PRINT</span> <span class=\"variable\">MAINCNT</span>, <span class=\"variable\">CSRX</span>"),
+        ("REM everything after", None,
+            "<span class=\"keyword\">REM</span> <span class=\"statement\">everything</span> <span class=\"variable\">after</span>"),
+        ("CASE X\nWHEN 1\nOTHERWISE\nENDCASE", Some(true),
+            "<span class=\"keyword\">CASE</span> <span class=\"variable\">X</span>\n<span class=\"keyword\">WHEN</span> <span class=\"number\">1</span>\n<span class=\"keyword\">OTHERWISE</span>\n<span class=\"keyword\">ENDCASE</span>"),
+        ("DIM A[10]:A[0]=#RED", None,
+            "<span class=\"keyword\">DIM</span> <span class=\"variable\">A</span>[<span class=\"number\">10</span>]:<span class=\"variable\">A</span>[<span class=\"number\">0</span>]<span class=\"equals\">=</span><span class=\"constant number\">#RED</span>"),
+        ("unterminated \"string", None,
+            "<span class=\"statement\">unterminated</span> <span class=\"string\">\"string</span>"),
+        ("A.B=0", Some(true),
+            "<span class=\"statement\">A</span><span class=\"number\">.</span><span class=\"variable\">B</span><span class=\"equals\">=</span><span class=\"number\">0</span>"),
+        ("PRINT 1 DIV 2 MOD 3", None,
+            "<span class=\"keyword\">PRINT</span> <span class=\"number\">1</span> <span class=\"word-operator operator\">DIV</span> <span class=\"number\">2</span> <span class=\"word-operator operator\">MOD</span> <span class=\"number\">3</span>"),
+    ];
+
+    fn dialect_name(sb4: Option<bool>) -> &'static str {
+        match sb4 { Some(true) => "sb4", Some(false) => "sb3", None => "sb" }
+    }
+
+    #[test]
+    fn matches_js_highlighter() {
+        for (code, sb4, expected) in GOLDEN {
+            let highlighted = highlight_code(code, Some(dialect_name(*sb4))).replace("&gt;", ">");
+            assert_eq!(&highlighted, expected, "{:?} as {}", code, dialect_name(*sb4));
+        }
+    }
+
+    #[test]
+    fn dialects() {
+        assert_eq!(smilebasic_dialect(None), Some(None));
+        assert_eq!(smilebasic_dialect(Some("")), Some(None));
+        assert_eq!(smilebasic_dialect(Some(" SmileBASIC ")), Some(None));
+        assert_eq!(smilebasic_dialect(Some("SB4")), Some(Some(true)));
+        for lang in ["sb3", "SB2", "ptc", "PTC"] {
+            assert_eq!(smilebasic_dialect(Some(lang)), Some(Some(false)), "{}", lang);
+        }
+        for lang in ["js", "c", "sb5", "text"] {
+            assert_eq!(smilebasic_dialect(Some(lang)), None, "{}", lang);
+        }
+    }
+
+    #[test]
+    fn dialect_changes_the_classes() {
+        let class_of = |code: &str, sb4: Option<bool>| {
+            let mut classes = Vec::new();
+            tokenize_smilebasic(code, sb4, |word, class| if !word.is_empty() { classes.push((word.iter().collect::<String>(), class)) });
+            classes[0].1
+        };
+        //STOP is a keyword in SB3 and PTC but a function in SB4; unknown means SB4 rules
+        assert_eq!(class_of("STOP", Some(false)), Some("keyword"));
+        assert_eq!(class_of("STOP", Some(true)), Some("statement function"));
+        assert_eq!(class_of("STOP", None), Some("statement function"));
+        //SB3 only
+        assert_eq!(class_of("BGPUT 0", Some(false)), Some("statement function"));
+        assert_eq!(class_of("BGPUT 0", Some(true)), Some("statement"));
+        //PTC words are in every dialect
+        assert_eq!(class_of("ICONSET 0", Some(false)), Some("statement function"));
+        assert_eq!(class_of("X=CANCEL", Some(false)), Some("variable"));
+    }
+
+    #[test]
+    fn unknown_languages_are_just_escaped() {
+        assert_eq!(highlight_code("if (a < b && c) PRINT", Some("js")), "if (a &lt; b &amp;&amp; c) PRINT");
+        assert!(highlight_code("PRINT 1", Some("sb")).starts_with("<span"));
+        assert!(highlight_code("PRINT 1", None).starts_with("<span"));
+    }
+}
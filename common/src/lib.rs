@@ -5,11 +5,20 @@ pub mod search;
 pub mod constants;
 pub mod forms;
 pub mod links;
+pub mod feed;
+pub mod structured;
+pub mod markup;
+pub mod highlight;
+pub mod sanitize;
 pub mod view;
 pub mod prefab;
 pub mod response;
+pub mod cache;
+pub mod ratelimit;
+pub mod collections;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use maud::*;
 use serde::{Serialize, Deserialize};
@@ -32,6 +41,7 @@ macro_rules! opt_s {
 #[derive(Clone, Debug)]
 pub struct LinkConfig {
     pub http_root: String,
+    pub public_root: String,    //The full scheme + host the site is publicly reachable at, for links that leave the site
     pub static_root: String,
     pub resource_root: String,
     pub file_root: String,
@@ -39,7 +49,7 @@ pub struct LinkConfig {
     pub cache_bust: String
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
 #[serde(default)]
 pub struct UserConfig {
     pub language: String,
@@ -70,6 +80,10 @@ pub struct MainLayoutData {
     pub override_nav_path: Option<&'static str>,
     pub user: Option<contentapi::User>,
     pub user_token: Option<String>,
+    /// Only set when there's a session to protect, goes in every POST form
+    pub csrf_token: Option<String>,
+    /// Goes on every inline script and style block, or the content security policy blocks them
+    pub csp_nonce: String,
     pub about_api: contentapi::About, 
     pub raw_alert: Option<String>,
 
@@ -77,13 +91,28 @@ pub struct MainLayoutData {
     pub profiler: onestop::OneList<onestop::OneDuration>
 }
 
+impl MainLayoutData {
+    /// Start an ETag with everything from the layout that changes a page's output: who's looking, their
+    /// settings, and the static file version. Pages add their own data on top of this
+    pub fn etag(&self) -> response::ETagBuilder {
+        response::ETag::builder()
+            .with(&self.user.as_ref().map(|u| u.id))
+            .with(&self.user_config)
+            .with(&self.links.cache_bust)
+            .with(&self.raw_alert)
+            .with(&self.csrf_token)
+    }
+}
+
 /// A basic context for use in page rendering. Even if a page doesn't strictly need all
 /// the items inside this context, it just makes it easier to pass them all to every page
 /// render consistently. However, do NOT use this on the baseline rendering functions!
 pub struct PageContext {
     pub layout_data: MainLayoutData,
     pub api_context: endpoints::ApiContext,
-    pub bbcode: BBCode
+    pub bbcode: BBCode,
+    pub render_cache: Arc<cache::RenderCache>,
+    pub rate_limiter: Arc<ratelimit::RateLimiter>
 }
 
 
@@ -147,6 +176,26 @@ pub fn parse_compound_value(original: &str) -> Vec<String>
     result
 }
 
+/// Parse a query string, but allow keys to be repeated (like from checkboxes or multi-selects). Repeated values 
+/// are joined with commas, so the field they go into should be a compound value (see parse_compound_value)
+pub fn parse_query_compound<T: serde::de::DeserializeOwned>(query: &str) -> Result<T, serde_urlencoded::de::Error>
+{
+    let mut merged : Vec<(String, String)> = Vec::new();
+
+    for (key, value) in serde_urlencoded::from_str::<Vec<(String, String)>>(query)? {
+        if let Some(existing) = merged.iter_mut().find(|(k,_)| *k == key) {
+            existing.1 = format!("{},{}", existing.1, value);
+        }
+        else {
+            merged.push((key, value));
+        }
+    }
+
+    //Round trip it back through the encoder so serde can do all the hard work of filling the struct
+    let requery = serde_urlencoded::to_string(&merged).map_err(|e| serde::de::Error::custom(e.to_string()))?;
+    serde_urlencoded::from_str::<T>(&requery)
+}
+
 pub fn random_id(postfix: &str) -> String {
     format!("{}_{}", fastrand::u32(..), postfix)
 }
\ No newline at end of file
@@ -19,6 +19,17 @@ impl LinkConfig {
         }
     }
 
+    /// Make a site link (from any of the other functions here) into a full url, for places that 
+    /// need it like feeds. Links that are already absolute are left alone
+    pub fn absolute(&self, link: &str) -> String {
+        if link.starts_with('/') {
+            format!("{}{}", self.public_root, link)
+        }
+        else {
+            link.to_string()
+        }
+    }
+
     pub fn user(&self, user: &User) -> String {
         format!("{}/user/{}", self.http_root, user.username)
     }
@@ -39,6 +50,35 @@ impl LinkConfig {
         format!("{}/activity", self.http_root)
     }
 
+    pub fn activity_feed(&self) -> String {
+        format!("{}/activity/feed", self.http_root)
+    }
+
+    pub fn forum_category_feed(&self, category: &Content) -> String {
+        format!("{}/forum/category/{}/feed", self.http_root, opt_s!(category.hash))
+    }
+
+    pub fn forum_thread_feed(&self, thread: &Content) -> String {
+        format!("{}/forum/thread/{}/feed", self.http_root, opt_s!(thread.hash))
+    }
+
+    pub fn user_feed(&self, user: &User) -> String {
+        format!("{}/user/{}/feed", self.http_root, user.username)
+    }
+
+    pub fn search_feed(&self, search: &crate::forms::PageSearch) -> String {
+        format!("{}/search/feed?{}", self.http_root, serde_urlencoded::to_string(search).unwrap_or_default())
+    }
+
+    pub fn sitemap(&self) -> String {
+        format!("{}/sitemap.xml", self.http_root)
+    }
+
+    /// One of the numbered sitemaps when there's too many urls for just one (1 based)
+    pub fn sitemap_page(&self, page: usize) -> String {
+        format!("{}/sitemap/{}", self.http_root, page)
+    }
+
     pub fn imagebrowser(&self) -> String {
         format!("{}/widget/imagebrowser", self.http_root)
     }
@@ -47,10 +87,42 @@ impl LinkConfig {
         format!("{}/widget/votes/{}", self.http_root, i(&content.id))
     }
 
+    pub fn collections_widget(&self, content: &Content) -> String {
+        format!("{}/widget/collections/{}", self.http_root, i(&content.id))
+    }
+
+    /// The current user's collection management page
+    pub fn collections(&self) -> String {
+        format!("{}/collections", self.http_root)
+    }
+
+    pub fn collection(&self, user: &User, key: &str) -> String {
+        format!("{}/user/{}/collection/{}", self.http_root, user.username, key)
+    }
+
+    pub fn card_widget(&self, content: &Content) -> String {
+        format!("{}/widget/card/{}", self.http_root, opt_s!(content.hash))
+    }
+
+    /// The oembed discovery link for the given (absolute) page url
+    pub fn oembed(&self, url: &str) -> String {
+        format!("{}/oembed?{}", self.http_root, serde_urlencoded::to_string([("url", url), ("format", "json")]).unwrap_or_default())
+    }
+
     pub fn qr_generator(&self, content: &Content) -> String {
         format!("{}/widget/qr/{}", self.http_root, opt_s!(content.hash))
     }
 
+    /// Link to download a single raw .PTC file; the index is the position in the page's ptc file list
+    pub fn ptc_download(&self, content: &Content, index: usize) -> String {
+        format!("{}/page/{}/ptc/{}", self.http_root, opt_s!(content.hash), index)
+    }
+
+    /// Link to download all .PTC files for the page as a zip
+    pub fn ptc_download_all(&self, content: &Content) -> String {
+        format!("{}/page/{}/ptc", self.http_root, opt_s!(content.hash))
+    }
+
     pub fn forum_category(&self, category: &Content) -> String {
         self.forum_category_unsafe(opt_s!(category.hash))
     }
@@ -135,6 +207,29 @@ impl LinkConfig {
         format!("{}/search?category={}", self.http_root, category)
     }
 
+    pub fn tags(&self) -> String {
+        format!("{}/tags", self.http_root)
+    }
+
+    pub fn tag(&self, keyword: &str) -> String {
+        //Keywords are split on whitespace so they never have spaces, which means form encoding is safe for a path
+        let encoded = serde_urlencoded::to_string([("", keyword)]).unwrap_or_default();
+        format!("{}/tags/{}", self.http_root, encoded.trim_start_matches('='))
+    }
+
+    pub fn search(&self, search: &crate::forms::PageSearch) -> String {
+        format!("{}/search?{}", self.http_root, serde_urlencoded::to_string(search).unwrap_or_default())
+    }
+
+    pub fn search_save(&self) -> String {
+        format!("{}/search/save", self.http_root)
+    }
+
+    /// POSTing to this marks the saved search visited (resetting its "new since last visit" count) and sends you to it
+    pub fn saved_search(&self, key: &str) -> String {
+        format!("{}/search/saved/{}", self.http_root, key)
+    }
+
 }
 
 impl MainLayoutData 
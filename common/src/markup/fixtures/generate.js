@@ -0,0 +1,231 @@
+// Regenerates the expected html for the markup golden tests (see ../tests.rs) by running the real
+// static/markup scripts, the way a browser showing a post would: the sbs: link override from layout.js,
+// then the syntax highlighting from sb-highlight.js. There's no browser here, so there's just enough of
+// a DOM below for those scripts to run and to write out what they built.
+//
+//   node common/src/markup/fixtures/generate.js
+//
+// Every file in this folder that's named <whatever>.<markup> (12y, 12y2, plaintext) gets a .html beside it.
+"use strict"
+
+const fs = require('fs')
+const path = require('path')
+const vm = require('vm')
+
+const FIXTURES = __dirname
+const STATIC = path.join(__dirname, '../../../../static')
+const VOID = new Set(['area', 'base', 'br', 'col', 'embed', 'hr', 'img', 'input', 'link', 'meta', 'source', 'track', 'wbr'])
+//What the browser keeps when a script sets these styles; anything else it throws out
+const VALID_STYLE = {
+	'text-align': v => ['left', 'right', 'center', 'justify', 'start', 'end', 'match-parent'].includes(v),
+	'background-color': v => /^#([0-9a-f]{3,4}|[0-9a-f]{6}|[0-9a-f]{8})$/i.test(v) || /^[a-z]+$/i.test(v),
+}
+//Properties scripts set that are really attributes
+const REFLECTED = {
+	href: 'href', target: 'target', alt: 'alt', title: 'title', src: 'src', name: 'name', id: 'id',
+	preload: 'preload', tabIndex: 'tabindex', width: 'width', height: 'height', colSpan: 'colspan',
+	rowSpan: 'rowspan', className: 'class', max: 'max', value: 'value',
+}
+const BOOLEAN = { controls: 'controls', autoplay: 'autoplay', disabled: 'disabled' }
+
+const kebab = name => name.replace(/[A-Z]/g, c => '-'+c.toLowerCase())
+const escape_text = text => text.replace(/&/g, '&amp;').replace(/\u00a0/g, '&nbsp;').replace(/</g, '&lt;').replace(/>/g, '&gt;')
+const escape_attr = text => text.replace(/&/g, '&amp;').replace(/\u00a0/g, '&nbsp;').replace(/"/g, '&quot;')
+const decode = text => text.replace(/&(#x[0-9a-f]+|#[0-9]+|amp|lt|gt|quot|apos|nbsp);/gi, (_, e) => {
+	e = e.toLowerCase()
+	if (e[0]=='#')
+		return String.fromCodePoint(e[1]=='x' ? parseInt(e.slice(2), 16) : parseInt(e.slice(1), 10))
+	return {amp: '&', lt: '<', gt: '>', quot: '"', apos: "'", nbsp: '\u00a0'}[e]
+})
+
+class Node {
+	constructor() { this.childNodes = []; this.parentNode = null }
+	get firstChild() { return this.childNodes[0] || null }
+	get lastChild() { return this.childNodes[this.childNodes.length-1] || null }
+	getRootNode() { let n = this; while (n.parentNode) n = n.parentNode; return n }
+	append(...nodes) {
+		for (let n of nodes) {
+			if ('string'==typeof n)
+				n = new Text(n)
+			if (n instanceof DocumentFragment) {
+				this.append(...n.childNodes)
+				n.childNodes = []
+				continue
+			}
+			if (n.parentNode)
+				n.parentNode.childNodes.splice(n.parentNode.childNodes.indexOf(n), 1)
+			n.parentNode = this
+			this.childNodes.push(n)
+		}
+	}
+	appendChild(node) { this.append(node); return node }
+	replaceChildren(...nodes) { this.childNodes = []; this.append(...nodes) }
+	get textContent() { return this.childNodes.map(n => n.textContent).join('') }
+	set textContent(text) { this.childNodes = []; if (text!=='') this.append(String(text)) }
+	get innerHTML() { return this.childNodes.map(serialize).join('') }
+	set innerHTML(html) { this.childNodes = []; this.append(...parse_html(html)) }
+}
+
+class Text extends Node {
+	constructor(data) { super(); this.data = data }
+	get textContent() { return this.data }
+	set textContent(text) { this.data = String(text) }
+}
+
+class DocumentFragment extends Node {}
+
+class Element extends Node {
+	constructor(tag) {
+		super()
+		this.tagName = tag.toLowerCase()
+		this.attributes = new Map()
+		this.naturalWidth = this.naturalHeight = 0
+		let element = this
+		let styles = new Map()
+		let write_style = ()=>{
+			if (styles.size)
+				element.attributes.set('style', [...styles].map(([k, v]) => k+': '+v+';').join(' '))
+		}
+		this.style = new Proxy({}, {
+			get(_, prop) {
+				if (prop=='setProperty')
+					return (name, value)=>{ styles.set(name, String(value)); write_style() }
+				return styles.get(kebab(prop)) || ''
+			},
+			set(_, prop, value) {
+				let name = kebab(prop)
+				value = String(value)
+				if (!VALID_STYLE[name] || VALID_STYLE[name](value)) {
+					styles.set(name, value)
+					write_style()
+				}
+				return true
+			},
+		})
+		this.dataset = new Proxy({}, {
+			get(_, prop) { return element.attributes.get('data-'+kebab(prop)) },
+			set(_, prop, value) { element.attributes.set('data-'+kebab(prop), String(value)); return true },
+		})
+		this.classList = {
+			add(...names) {
+				let list = (element.getAttribute('class') || '').split(/\s+/).filter(x => x)
+				for (let name of names)
+					if (!list.includes(name)) list.push(name)
+				element.setAttribute('class', list.join(' '))
+			},
+		}
+	}
+	getAttribute(name) { let v = this.attributes.get(name); return v===undefined ? null : v }
+	setAttribute(name, value) { this.attributes.set(name.toLowerCase(), String(value)) }
+	decode() { return new Promise(()=>{}) } //Never loads, like an image that's still on its way
+	play() {}
+	pause() {}
+}
+for (let [prop, attr] of Object.entries(REFLECTED))
+	Object.defineProperty(Element.prototype, prop, {
+		get() { return this.getAttribute(attr) || '' },
+		set(value) { this.setAttribute(attr, value) },
+	})
+for (let [prop, attr] of Object.entries(BOOLEAN))
+	Object.defineProperty(Element.prototype, prop, {
+		get() { return this.attributes.has(attr) },
+		set(value) { if (value) this.setAttribute(attr, ''); else this.attributes.delete(attr) },
+	})
+
+class Template extends Element {
+	constructor() { super('template'); this.content = new DocumentFragment() }
+	set innerHTML(html) { this.content = new DocumentFragment(); this.content.append(...parse_html(html)) }
+}
+
+function clone(node) {
+	let copy
+	if (node instanceof Text)
+		return new Text(node.data)
+	if (node instanceof Element) {
+		copy = new Element(node.tagName)
+		for (let [k, v] of node.attributes)
+			copy.attributes.set(k, v)
+	} else
+		copy = new DocumentFragment()
+	copy.append(...node.childNodes.map(clone))
+	return copy
+}
+
+//Only as much of an html parser as the scripts' own templates and the highlighter need
+function parse_html(html) {
+	let root = new DocumentFragment()
+	let current = root
+	let re = /<\/([a-z0-9-]+)\s*>|<([a-z0-9-]+)((?:\s+[^\s=>]+(?:\s*=\s*(?:"[^"]*"|'[^']*'|[^\s>]+))?)*)\s*\/?>|([^<]+)/gi
+	let m
+	while ((m = re.exec(html))) {
+		if (m[1]) {
+			let tag = m[1].toLowerCase()
+			while (current!=root && current.tagName!=tag)
+				current = current.parentNode
+			if (current!=root)
+				current = current.parentNode
+		} else if (m[2]) {
+			let e = new Element(m[2])
+			let are = /([^\s=>]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+)))?/g
+			let a
+			while ((a = are.exec(m[3])))
+				e.setAttribute(a[1], decode(a[2] ?? a[3] ?? a[4] ?? ''))
+			current.append(e)
+			if (!VOID.has(e.tagName))
+				current = e
+		} else
+			current.append(decode(m[4]))
+	}
+	return root.childNodes.splice(0)
+}
+
+function serialize(node) {
+	if (node instanceof Text)
+		return escape_text(node.data)
+	if (node instanceof DocumentFragment)
+		return node.innerHTML
+	let attrs = [...node.attributes].map(([k, v]) => ' '+k+'="'+escape_attr(v)+'"').join('')
+	if (VOID.has(node.tagName))
+		return '<'+node.tagName+attrs+'>'
+	let inner = node.innerHTML
+	//The parser eats a newline right after <pre>, so serializing puts one back
+	if (node.tagName=='pre' && node.firstChild instanceof Text && node.firstChild.data[0]=='\n')
+		inner = '\n'+inner
+	return '<'+node.tagName+attrs+'>'+inner+'</'+node.tagName+'>'
+}
+
+let document = {
+	createElement: tag => tag.toLowerCase()=='template' ? new Template() : new Element(tag),
+	createDocumentFragment: () => new DocumentFragment(),
+	createTextNode: text => new Text(text),
+	importNode: (node, deep) => clone(node),
+}
+
+let context = vm.createContext({document, Element, DocumentFragment, URL, console, SBSBASEURL: ''})
+for (let file of ['markup/langs.js', 'markup/parse.js', 'markup/legacy.js', 'markup/render.js', 'markup/helpers.js', 'sb-highlight.js'])
+	vm.runInContext(fs.readFileSync(path.join(STATIC, file), 'utf8'), context, {filename: file})
+
+//Just the sbs: handler from the top of layout.js; the rest of that file wants a whole page
+let layout = fs.readFileSync(path.join(STATIC, 'layout.js'), 'utf8')
+let start = layout.indexOf("Markup.renderer.url_scheme['sbs:']")
+vm.runInContext(layout.slice(start, layout.indexOf('};', start)+2), context)
+
+let render = vm.runInContext(`(function(text, lang) {
+	let root = document.createElement('div')
+	Markup.convert_lang(text, lang, root)
+	let codes = []
+	let find = e => { for (let c of e.childNodes) if (c instanceof Element) { if (c.tagName=='pre') codes.push(c); find(c) } }
+	find(root)
+	for (let code of codes)
+		applySyntaxHighlighting(code)
+	return root
+})`, context)
+
+for (let file of fs.readdirSync(FIXTURES).sort()) {
+	let lang = path.extname(file).slice(1)
+	if (!['12y', '12y2', 'plaintext'].includes(lang))
+		continue
+	let text = fs.readFileSync(path.join(FIXTURES, file), 'utf8')
+	fs.writeFileSync(path.join(FIXTURES, file+'.html'), render(text, lang).innerHTML)
+	console.log(file)
+}
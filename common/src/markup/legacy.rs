@@ -0,0 +1,1004 @@
+//! The legacy 12y parser and the plaintext autolinker, ported from static/markup/legacy.js. The 12y parser
+//! works on utf-16 units, same as the js, because it peeks backwards and forwards by index all over the
+//! place. It also keeps the js's habit of checking the *previous* character when moving around (see scan);
+//! that's a quirk, but it's a quirk people's posts depend on.
+
+use std::collections::HashMap;
+
+use super::*;
+use super::parse::{js_space, js_word, is_youtube};
+
+const MAXOPENBLOCKS: i64 = 10;
+
+fn is_block(kind: Kind) -> bool {
+    matches!(kind, Kind::Divider | Kind::Code | Kind::Audio | Kind::Video | Kind::Youtube | Kind::Heading | Kind::Quote |
+        Kind::List | Kind::ListItem | Kind::Table | Kind::TableRow | Kind::Image | Kind::Align | Kind::Spoiler)
+}
+
+/// A {...} property: either a plain string or "true" when there's no =value
+type Prop = Option<String>;
+
+fn prop_truthy(prop: Option<&Prop>) -> bool {
+    match prop {
+        Some(None) => true,
+        Some(Some(value)) => !value.is_empty(),
+        None => false
+    }
+}
+
+/// Javascript ToNumber for a property, NaN is None
+fn prop_number(prop: Option<&Prop>) -> Option<f64> {
+    match prop {
+        Some(None) => Some(1.0),
+        Some(Some(value)) => js_number(value),
+        None => None
+    }
+}
+
+/// Javascript String() of a property
+fn prop_string(prop: Option<&Prop>) -> String {
+    match prop {
+        Some(None) => String::from("true"),
+        Some(Some(value)) => value.clone(),
+        None => String::from("undefined")
+    }
+}
+
+fn convert_cell_args(props: &HashMap<String, Prop>, header: bool) -> Args {
+    let truthy = |key: &str| prop_truthy(props.get(key));
+    let number = |key: &str| if truthy(key) { Some(prop_number(props.get(key)).unwrap_or(0.0) as i64) } else { None };
+    let color = if truthy("c") { Some(prop_string(props.get("c"))) } else { None };
+    Args {
+        header: truthy("h") || header,
+        colspan: number("cs"),
+        rowspan: number("rs"),
+        align: props.get("align").map(|a| prop_string(Some(a))),
+        truecolor: color.clone().filter(|c| c.starts_with('#')),
+        color,
+        ..Default::default()
+    }
+}
+
+// audio, video, image, youtube
+fn url_type(url: &str) -> (Kind, Args) {
+    let lower = url.to_ascii_lowercase();
+    let has_extension = |ext: &str| lower.match_indices(ext).any(|(i, _)| !lower[i + ext.len()..].chars().next().map(js_word).unwrap_or(false));
+    let simple = |kind: Kind| (kind, Args { url: Some(url.to_string()), ..Default::default() });
+    if has_extension(".mp3") || has_extension(".ogg") || has_extension(".wav") || lower.ends_with("#audio") {
+        return simple(Kind::Audio);
+    }
+    if has_extension(".mp4") || has_extension(".mkv") || has_extension(".mov") || lower.ends_with("#video") {
+        return simple(Kind::Video);
+    }
+    if is_youtube(url) {
+        return simple(Kind::Youtube);
+    }
+    // ^([^#]*)#(\d+)x(\d+)$
+    if let Some((base, size)) = url.split_once('#') {
+        if let Some((w, h)) = size.split_once('x') {
+            let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+            if digits(w) && digits(h) {
+                let num = |s: &str| s.parse::<f64>().unwrap_or(0.0) as i64;
+                return (Kind::Image, Args { url: Some(base.to_string()), width: Some(num(w)), height: Some(num(h)), ..Default::default() });
+            }
+        }
+    }
+    simple(Kind::Image)
+}
+
+fn is_url_char(c: u16) -> bool {
+    char::from_u32(c as u32).map(|c| js_word(c) || "-$.+!*',;/?:@=&#%~".contains(c)).unwrap_or(false)
+}
+
+fn starts_with_url(text: &[u16]) -> bool {
+    ["http://", "https://", "sbs:"].iter().any(|s| s.encode_utf16().enumerate().all(|(i, c)| text.get(i) == Some(&c)))
+}
+
+/// A node while parsing; nodes refer to each other by index since they're modified after they're placed
+struct LNode {
+    kind: Kind,
+    args: Args,
+    content: Option<Vec<LLeaf>>
+}
+
+enum LLeaf {
+    Text(String),
+    Node(usize)
+}
+
+/// An entry on the parse stack. kind None is a {} environment with no node of its own
+#[derive(Default)]
+struct Entry {
+    kind: Option<Kind>,
+    node: Option<usize>,
+    is_block: bool,
+    level: i64,
+    big: bool,
+    in_brackets: bool,
+    //Table stuff: rows point at their table and cells at their row (by stack index)
+    table: usize,
+    row: usize,
+    cells: f64,
+    header: bool,
+    columns: Option<f64>,
+    rowspans: Vec<f64>
+}
+
+struct Legacy {
+    code: Vec<u16>,
+    i: isize,
+    c: Option<u16>,
+    start_of_line: bool,
+    skip_next_line_break: bool,
+    text_buffer: Vec<u16>,
+    curr: usize,
+    nodes: Vec<LNode>,
+    open_blocks: i64,
+    stack: Vec<Entry>
+}
+
+fn u(c: u8) -> u16 {
+    c as u16
+}
+
+impl Legacy {
+
+    fn new(text: &str) -> Self {
+        let mut result = Legacy {
+            code: text.encode_utf16().collect(),
+            i: 0,
+            c: None,
+            start_of_line: true,
+            skip_next_line_break: false,
+            text_buffer: Vec::new(),
+            curr: 0,
+            nodes: vec![LNode { kind: Kind::Root, args: Args::default(), content: Some(Vec::new()) }],
+            open_blocks: 0,
+            stack: vec![Entry { kind: Some(Kind::Root), node: Some(0), ..Default::default() }]
+        };
+        result.restore(0);
+        result
+    }
+
+    // -- state --
+
+    fn at(&self, index: isize) -> Option<u16> {
+        if index < 0 { None } else { self.code.get(index as usize).copied() }
+    }
+
+    fn substring(&self, start: isize, end: isize) -> Vec<u16> {
+        let clamp = |x: isize| x.clamp(0, self.code.len() as isize) as usize;
+        let (a, b) = (clamp(start), clamp(end));
+        let (a, b) = if a > b { (b, a) } else { (a, b) };
+        self.code[a..b].to_vec()
+    }
+
+    fn is(&self, chr: u8) -> bool {
+        self.c == Some(u(chr))
+    }
+
+    fn line_start(&mut self) {
+        self.start_of_line = true;
+    }
+
+    fn scan(&mut self) {
+        if self.c.is_none() || self.is(b'\n') {
+            self.line_start();
+        }
+        else if !self.is(b' ') {
+            self.start_of_line = false;
+        }
+        self.i += 1;
+        self.c = self.at(self.i);
+    }
+
+    // move to pos
+    fn restore(&mut self, pos: isize) {
+        self.i = pos - 1;
+        self.scan();
+    }
+
+    //try to read a char
+    fn eat(&mut self, chr: u8) -> bool {
+        if self.is(chr) {
+            self.scan();
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn stack_top(&self) -> &Entry {
+        self.stack.last().expect("Stack is never empty while parsing")
+    }
+
+    fn stack_contains(&self, kind: Option<Kind>) -> bool {
+        self.stack.iter().any(|x| x.kind == kind)
+    }
+
+    fn top_is(&self, kind: Kind) -> bool {
+        self.stack.last().map(|t| t.kind == Some(kind)).unwrap_or(false)
+    }
+
+    // -- outputting --
+
+    fn push_leaf(&mut self, leaf: LLeaf) {
+        let curr = self.curr;
+        self.nodes[curr].content.get_or_insert_with(Vec::new).push(leaf);
+    }
+
+    fn end_block(&mut self) {
+        self.flush_text();
+        let item = self.stack.pop().expect("Ended too many blocks");
+        if item.is_block {
+            self.skip_next_line_break = true;
+        }
+        // this skips {} fake nodes
+        if let Some(node) = self.stack.iter().rev().find_map(|e| e.node) {
+            self.curr = node;
+            self.open_blocks -= 1;
+        }
+    }
+
+    // output contents of text buffer
+    fn flush_text(&mut self) {
+        if !self.text_buffer.is_empty() {
+            let text = String::from_utf16_lossy(&self.text_buffer);
+            self.text_buffer.clear();
+            self.push_leaf(LLeaf::Text(text));
+        }
+    }
+
+    fn add_line_break(&mut self) {
+        if self.skip_next_line_break {
+            self.skip_next_line_break = false;
+        }
+        else {
+            self.add_text(&[u(b'\n')]);
+        }
+    }
+
+    // add text to output (buffered)
+    fn add_text(&mut self, text: &[u16]) {
+        if !text.is_empty() {
+            self.text_buffer.extend_from_slice(text);
+            self.skip_next_line_break = false;
+        }
+    }
+
+    fn add_str(&mut self, text: &str) {
+        let text: Vec<u16> = text.encode_utf16().collect();
+        self.add_text(&text);
+    }
+
+    fn add_char(&mut self) {
+        if let Some(c) = self.c {
+            self.add_text(&[c]);
+        }
+    }
+
+    fn trim_buffer_spaces(&mut self) {
+        while self.text_buffer.last() == Some(&u(b' ')) {
+            self.text_buffer.pop();
+        }
+    }
+
+    fn add_block(&mut self, kind: Kind, args: Args) {
+        self.flush_text();
+        self.nodes.push(LNode { kind, args, content: None });
+        let index = self.nodes.len() - 1;
+        self.push_leaf(LLeaf::Node(index));
+        self.skip_next_line_break = is_block(kind);
+    }
+
+    /// Start a block, returning where its entry is on the stack
+    fn start_block(&mut self, kind: Kind, args: Args, mut data: Entry) -> Result<usize, String> {
+        data.kind = Some(kind);
+        self.open_blocks += 1;
+        if self.open_blocks > MAXOPENBLOCKS {
+            return Err(String::from("too deep nestted blocks"));
+        }
+        self.nodes.push(LNode { kind, args, content: Some(Vec::new()) });
+        let index = self.nodes.len() - 1;
+        data.node = Some(index);
+        if is_block(kind) {
+            data.is_block = true;
+            self.skip_next_line_break = true;
+        }
+        self.flush_text();
+        self.push_leaf(LLeaf::Node(index));
+        self.curr = index;
+        self.stack.push(data);
+        Ok(self.stack.len() - 1)
+    }
+
+    // check for /\b(http://|https://|sbs:)/ basically
+    fn is_url_start(&self) -> bool {
+        if self.at(self.i - 1).and_then(|c| char::from_u32(c as u32)).map(js_word).unwrap_or(false) {
+            return false;
+        }
+        starts_with_url(&self.code[(self.i.max(0) as usize).min(self.code.len())..])
+    }
+
+    /// The FR regex: a run of plain text with nothing special in it, returning the end
+    fn plain_run(&self) -> Option<isize> {
+        let mut end = self.i;
+        while let Some(c) = self.at(end) {
+            if c < 128 && "\n\\{}*/_~>]|`![-".contains(c as u8 as char) {
+                break;
+            }
+            //No \b here (unlike is_url_start), any url start ends the run
+            if starts_with_url(&self.code[end as usize..]) {
+                break;
+            }
+            end += 1;
+        }
+        if end > self.i { Some(end) } else { None }
+    }
+
+    // read a url
+    // if `allow` is true, url is only ended by end of file or ]] or ][
+    fn read_url(&mut self, allow: bool) -> String {
+        let start = self.i;
+        let mut depth = 0;
+        if allow {
+            while self.c.is_some() {
+                if self.eat(b'[') {
+                    depth += 1;
+                }
+                else if self.is(b']') {
+                    depth -= 1;
+                    if depth < 0 {
+                        break;
+                    }
+                    self.scan();
+                }
+                else {
+                    self.scan();
+                }
+            }
+        }
+        else {
+            while let Some(c) = self.c {
+                if is_url_char(c) {
+                    self.scan();
+                }
+                else if self.eat(b'(') {
+                    depth += 1;
+                }
+                else if self.is(b')') {
+                    depth -= 1;
+                    if depth < 0 {
+                        break;
+                    }
+                    self.scan();
+                }
+                else {
+                    break;
+                }
+            }
+            if self.at(self.i - 1).map(|c| c < 128 && ",.?!:".contains(c as u8 as char)).unwrap_or(false) {
+                self.i -= 2;
+                self.scan();
+            }
+        }
+        String::from_utf16_lossy(&self.substring(start, self.i))
+    }
+
+    // -- the parser --
+
+    fn parse(&mut self) -> Result<(), String> {
+        while self.c.is_some() {
+            if let Some(end) = self.plain_run() {
+                let text = self.substring(self.i, end);
+                self.add_text(&text);
+                self.restore(end);
+            }
+            else if self.eat(b'\n') {
+                self.end_line()?;
+            }
+            // \ escape
+            else if self.eat(b'\\') {
+                self.add_char();
+                self.scan();
+            }
+            // { group start
+            else if self.is(b'{') {
+                self.read_env()?;
+            }
+            // } group end
+            else if self.eat(b'}') {
+                if self.stack_contains(None) {
+                    self.close_all();
+                }
+                else {
+                    self.add_str("}");
+                }
+            }
+            // * heading/bold
+            else if self.is(b'*') {
+                let next = self.at(self.i + 1);
+                if self.start_of_line && (next == Some(u(b'*')) || next == Some(u(b' '))) {
+                    let mut level = 0;
+                    while self.eat(b'*') {
+                        level += 1;
+                    }
+                    level = level.min(3);
+                    if self.eat(b' ') {
+                        self.start_block(Kind::Heading, Args { level: Some(level), ..Default::default() }, Entry::default())?;
+                    }
+                    else {
+                        self.add_str(&"*".repeat(level as usize));
+                    }
+                }
+                else {
+                    self.do_markup(Kind::Bold)?;
+                }
+            }
+            else if self.is(b'/') {
+                self.do_markup(Kind::Italic)?;
+            }
+            else if self.is(b'_') {
+                self.do_markup(Kind::Underline)?;
+            }
+            else if self.is(b'~') {
+                self.do_markup(Kind::Strikethrough)?;
+            }
+            // >... quote
+            else if self.start_of_line && self.eat(b'>') {
+                self.start_block(Kind::Quote, Args::default(), Entry::default())?;
+            }
+            // -... list/hr
+            else if self.start_of_line && self.eat(b'-') {
+                self.trim_buffer_spaces();
+                // --... hr
+                if self.eat(b'-') {
+                    let mut count = 2;
+                    while self.eat(b'-') {
+                        count += 1;
+                    }
+                    // ---<EOL> hr
+                    if self.c.is_none() || self.is(b'\n') {
+                        self.add_block(Kind::Divider, Args::default());
+                    }
+                    else {
+                        self.add_str(&"-".repeat(count));
+                    }
+                }
+                // - ... list
+                else if self.eat(b' ') {
+                    let mut spaces = 0;
+                    let mut x = self.i - 3;
+                    while self.at(x) == Some(u(b' ')) {
+                        spaces += 1;
+                        x -= 1;
+                    }
+                    self.start_block(Kind::List, Args::default(), Entry { level: spaces, ..Default::default() })?;
+                    self.start_block(Kind::ListItem, Args::default(), Entry { level: spaces, ..Default::default() })?;
+                }
+                else {
+                    self.add_str("-");
+                }
+            }
+            // ] end link if inside one
+            else if self.is(b']') && self.stack_top().in_brackets {
+                self.scan();
+                if self.stack_top().big {
+                    if self.eat(b']') {
+                        self.end_block();
+                    }
+                    else {
+                        self.add_str("]");
+                    }
+                }
+                else {
+                    self.end_block();
+                }
+            }
+            // |... table
+            else if self.is(b'|') {
+                self.read_table()?;
+            }
+            // `... code
+            else if self.eat(b'`') {
+                self.read_code()?;
+            }
+            else if self.read_link()? {
+            }
+            // normal char
+            else {
+                self.add_char();
+                self.scan();
+            }
+        }
+
+        // END
+        self.flush_text();
+        while !self.stack.is_empty() {
+            self.end_block();
+        }
+        Ok(())
+    }
+
+    fn read_table(&mut self) -> Result<(), String> {
+        let top = self.stack.len() - 1;
+        // continuation
+        if self.stack[top].kind == Some(Kind::TableCell) {
+            self.scan();
+            let mut row = self.stack[top].row;
+            let table = self.stack[row].table;
+            let eaten = self.eat(b'\n');
+            // | | next row
+            if eaten && self.eat(b'|') {
+                // number of cells in first row determines number of columns in table
+                if self.stack[table].columns.is_none() {
+                    self.stack[table].columns = Some(self.stack[row].cells);
+                }
+                self.end_block(); //cell
+                if self.top_is(Kind::TableRow) {
+                    self.end_block();
+                }
+                // calculate number of cells in row which will be already filled due to previous row-spanning cells
+                let mut cells = 0.0;
+                let rowspans = self.stack[table].rowspans.iter().map(|span| { cells += 1.0; span - 1.0 }).filter(|span| *span > 0.0).collect();
+                self.stack[table].rowspans = rowspans;
+                row = self.start_block(Kind::TableRow, Args::default(), Entry { table, cells, ..Default::default() })?;
+                self.stack[row].header = self.eat(b'*');
+                self.start_cell(row)?;
+            }
+            // | next cell or table end
+            else {
+                self.stack[row].cells += 1.0;
+                self.trim_buffer_spaces();
+                // table ends when number of cells in current row = number of cells in first row
+                let ended = self.stack[table].columns.map(|columns| self.stack[row].cells > columns).unwrap_or(false);
+                if ended {
+                    self.end_block(); //end cell
+                    if self.top_is(Kind::TableRow) {
+                        self.end_block();
+                    }
+                    if self.top_is(Kind::Table) {
+                        self.end_block();
+                    }
+                    if eaten {
+                        self.add_line_break();
+                    }
+                }
+                else {
+                    self.end_block(); //cell
+                    self.start_cell(row)?;
+                }
+            }
+        }
+        // start of new table (must be at beginning of line)
+        else if self.start_of_line {
+            self.scan();
+            let table = self.start_block(Kind::Table, Args::default(), Entry::default())?;
+            let row = self.start_block(Kind::TableRow, Args::default(), Entry { table, ..Default::default() })?;
+            self.stack[row].header = self.eat(b'*');
+            self.start_cell(row)?;
+        }
+        else {
+            self.scan();
+            self.add_str("|");
+        }
+        Ok(())
+    }
+
+    fn read_code(&mut self) -> Result<(), String> {
+        // ``...
+        if self.eat(b'`') {
+            // ``` code block
+            if self.eat(b'`') {
+                // read lang name
+                let mut start = self.i;
+                while self.c.is_some() && !self.is(b'\n') && !self.is(b'`') {
+                    self.scan();
+                }
+                //treat first line as language name, if it matches the pattern. otherwise it's code
+                let mut language = String::from_utf16_lossy(&self.substring(start, self.i));
+                let mut eaten = false;
+                let trimmed = language.trim_matches(js_space);
+                if trimmed.chars().all(js_word) {
+                    language = trimmed.to_ascii_lowercase();
+                    eaten = self.eat(b'\n');
+                    start = self.i;
+                }
+                let fence: Vec<u16> = "```".encode_utf16().collect();
+                let from = (self.i.max(0) as usize).min(self.code.len());
+                let found = self.code[from..].windows(3).position(|w| w == fence.as_slice()).map(|p| (p + from) as isize);
+                let text = String::from_utf16_lossy(&self.substring(start, found.unwrap_or(self.code.len() as isize)));
+                let lang = if language.is_empty() { String::from("sb") } else { language };
+                self.add_block(Kind::Code, Args { lang: Some(lang), text: Some(text), ..Default::default() });
+                self.skip_next_line_break = eaten;
+                self.restore(found.map(|f| f + 3).unwrap_or(self.code.len() as isize));
+            }
+            // `` invalid
+            else {
+                self.add_str("``");
+            }
+        }
+        // ` inline code
+        else {
+            let start = self.i;
+            let mut code_text: Vec<u16> = Vec::new();
+            while self.c.is_some() {
+                if self.is(b'`') {
+                    if self.at(self.i + 1) != Some(u(b'`')) {
+                        break;
+                    }
+                    if self.i == start + 1 && code_text.first() == Some(&u(b' ')) {
+                        code_text.remove(0);
+                    }
+                    self.scan();
+                }
+                if let Some(c) = self.c {
+                    code_text.push(c);
+                }
+                self.scan();
+            }
+            self.add_block(Kind::ICode, Args { text: Some(String::from_utf16_lossy(&code_text)), ..Default::default() });
+            self.scan();
+        }
+        Ok(())
+    }
+
+    fn read_bracketed_link(&mut self, embed: bool) -> Result<bool, String> {
+        if self.eat(b'[') {
+            if self.eat(b'[') {
+                // read url:
+                let mut after = false;
+                let url = self.read_url(true);
+                if self.eat(b']') && !self.eat(b']') && self.eat(b'[') {
+                    after = true;
+                }
+                if embed {
+                    let (kind, mut args) = url_type(&url);
+                    if after {
+                        let mut alt_text = Vec::new();
+                        while self.c.is_some() {
+                            if self.is(b']') && self.at(self.i + 1) == Some(u(b']')) {
+                                self.scan();
+                                self.scan();
+                                break;
+                            }
+                            self.eat(b'\\');
+                            if let Some(c) = self.c {
+                                alt_text.push(c);
+                            }
+                            self.scan();
+                        }
+                        args.alt = Some(String::from_utf16_lossy(&alt_text));
+                    }
+                    self.add_block(kind, args);
+                }
+                else if after {
+                    self.start_block(Kind::Link, Args { url: Some(url), ..Default::default() }, Entry { big: true, in_brackets: true, ..Default::default() })?;
+                }
+                else {
+                    self.add_block(Kind::SimpleLink, Args { url: Some(url), ..Default::default() });
+                }
+                return Ok(true);
+            }
+            else {
+                self.add_str("[");
+            }
+        }
+        Ok(false)
+    }
+
+    fn read_env(&mut self) -> Result<bool, String> {
+        if !self.eat(b'{') {
+            return Ok(false);
+        }
+        self.stack.push(Entry::default());
+        self.line_start();
+
+        let start = self.i;
+        if self.eat(b'#') {
+            let name = self.read_tag_name();
+            let props = self.read_props();
+            let arg = props.get("");
+            match name.as_deref() {
+                Some("spoiler") if !self.stack_contains(Some(Kind::Spoiler)) => {
+                    //arg==true in the js, which is also true for anything that's the number 1
+                    let label = match arg {
+                        Some(None) => Some(String::from("spoiler")),
+                        Some(Some(a)) if js_number(a) == Some(1.0) => Some(String::from("spoiler")),
+                        Some(Some(a)) => Some(a.clone()),
+                        None => None
+                    };
+                    self.start_block(Kind::Spoiler, Args { label, ..Default::default() }, Entry::default())?;
+                },
+                Some("ruby") => {
+                    self.start_block(Kind::Ruby, Args { text: Some(prop_string(arg)), ..Default::default() }, Entry::default())?;
+                },
+                Some("align") => {
+                    let align = match arg {
+                        Some(Some(a)) if a == "center" || a == "right" || a == "left" => Some(a.clone()),
+                        _ => None
+                    };
+                    self.start_block(Kind::Align, Args { align, ..Default::default() }, Entry::default())?;
+                },
+                Some("anchor") => {
+                    self.start_block(Kind::Anchor, Args { name: Some(prop_string(arg)), ..Default::default() }, Entry::default())?;
+                },
+                Some("bg") => {
+                    self.start_block(Kind::BackgroundColor, Args { color: Some(prop_string(arg)), ..Default::default() }, Entry::default())?;
+                },
+                Some("sub") => {
+                    self.start_block(Kind::Subscript, Args::default(), Entry::default())?;
+                },
+                Some("sup") => {
+                    self.start_block(Kind::Superscript, Args::default(), Entry::default())?;
+                },
+                _ => {
+                    let text = String::from_utf16_lossy(&self.substring(start, self.i));
+                    self.add_block(Kind::Invalid, Args { text: Some(text), reason: Some(String::from("invalid tag")), ..Default::default() });
+                }
+            }
+        }
+        self.line_start();
+        Ok(true)
+    }
+
+    // read table cell properties and start cell block, and eat whitespace
+    // assumed to be called when pointing to char after |
+    fn start_cell(&mut self, row: usize) -> Result<(), String> {
+        let props = if self.eat(b'#') { self.read_props() } else { HashMap::new() };
+        let table = self.stack[row].table;
+
+        if prop_truthy(props.get("rs")) {
+            let rs = prop_number(props.get("rs")).unwrap_or(f64::NAN);
+            self.stack[table].rowspans.push(rs - 1.0);
+        }
+        if prop_truthy(props.get("cs")) {
+            let cs = prop_number(props.get("cs")).unwrap_or(f64::NAN);
+            self.stack[row].cells += cs - 1.0;
+        }
+
+        let args = convert_cell_args(&props, self.stack[row].header);
+        self.start_block(Kind::TableCell, args, Entry { row, ..Default::default() })?;
+        while self.eat(b' ') {}
+        Ok(())
+    }
+
+    fn read_tag_name(&mut self) -> Option<String> {
+        let start = self.i;
+        while self.c.map(|c| (u(b'a')..=u(b'z')).contains(&c)).unwrap_or(false) {
+            self.scan();
+        }
+        if self.i > start { Some(String::from_utf16_lossy(&self.substring(start, self.i))) } else { None }
+    }
+
+    // read properties key=value,key=value... ended by a space or \n or } or {
+    // =value is optional and defaults to `true`
+    fn read_props(&mut self) -> HashMap<String, Prop> {
+        let start = self.i;
+        let from = (start.max(0) as usize).min(self.code.len());
+        let end = self.code[from..].iter().position(|c| *c < 128 && " \n}{".contains(*c as u8 as char))
+            .map(|p| (p + from) as isize)
+            .unwrap_or(self.code.len() as isize);
+
+        self.restore(end);
+        self.eat(b' ');
+
+        let propst = String::from_utf16_lossy(&self.substring(start, end));
+        let mut props = HashMap::new();
+        for x in propst.split(',') {
+            match x.split_once('=') {
+                Some((key, value)) => props.insert(key.to_string(), Some(value.to_string())),
+                None => props.insert(x.to_string(), None)
+            };
+        }
+        props
+    }
+
+    fn read_link(&mut self) -> Result<bool, String> {
+        let embed = self.eat(b'!');
+        if self.read_bracketed_link(embed)? || self.read_plain_link(embed)? {
+            return Ok(true);
+        }
+        if embed {
+            self.add_str("!");
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn read_plain_link(&mut self, embed: bool) -> Result<bool, String> {
+        if !self.is_url_start() {
+            return Ok(false);
+        }
+
+        let url = self.read_url(false);
+        let after = self.eat(b'[');
+
+        if embed {
+            let (kind, mut args) = url_type(&url);
+            if after {
+                let mut alt_text = Vec::new();
+                while self.c.is_some() && !self.is(b']') && !self.is(b'\n') {
+                    self.eat(b'\\');
+                    if let Some(c) = self.c {
+                        alt_text.push(c);
+                    }
+                    self.scan();
+                }
+                self.scan();
+                args.alt = Some(String::from_utf16_lossy(&alt_text));
+            }
+            self.add_block(kind, args);
+        }
+        else if after {
+            self.start_block(Kind::Link, Args { url: Some(url), ..Default::default() }, Entry { in_brackets: true, ..Default::default() })?;
+        }
+        else {
+            self.add_block(Kind::SimpleLink, Args { url: Some(url), ..Default::default() });
+        }
+        Ok(true)
+    }
+
+    // called at end of {} block
+    fn close_all(&mut self) {
+        while let Some(top) = self.stack.last() {
+            if top.kind == Some(Kind::Root) {
+                break;
+            }
+            let env = top.kind.is_none();
+            self.end_block();
+            if env {
+                break;
+            }
+        }
+    }
+
+    // called at the end of a line (unescaped newline)
+    fn end_line(&mut self) -> Result<(), String> {
+        loop {
+            let top_kind = self.stack_top().kind;
+            if top_kind == Some(Kind::Heading) || top_kind == Some(Kind::Quote) {
+                self.end_block();
+            }
+            else if top_kind == Some(Kind::ListItem) {
+                let level = self.stack_top().level;
+                self.end_block();
+                let mut indent = 0;
+                while self.eat(b' ') {
+                    indent += 1;
+                }
+                // OPTION 1: no next item; end list
+                if !self.is(b'-') {
+                    while self.top_is(Kind::List) {
+                        self.end_block();
+                    }
+                    self.add_str(&" ".repeat(indent as usize));
+                }
+                else {
+                    self.scan();
+                    while self.eat(b' ') {}
+                    // OPTION 2: next item has same indent level; add item to list
+                    if indent == level {
+                        self.start_block(Kind::ListItem, Args::default(), Entry { level: indent, ..Default::default() })?;
+                    }
+                    // OPTION 3: next item has larger indent; start nested list
+                    else if indent > level {
+                        self.start_block(Kind::List, Args::default(), Entry { level: indent, ..Default::default() })?;
+                        self.start_block(Kind::ListItem, Args::default(), Entry { level: indent, ..Default::default() })?;
+                    }
+                    // OPTION 4: next item has less indent; try to exit 1 or more layers of nested lists
+                    else {
+                        loop {
+                            let top = self.stack_top();
+                            if top.kind == Some(Kind::List) {
+                                if top.level <= indent {
+                                    break;
+                                }
+                                self.end_block();
+                            }
+                            else {
+                                // no suitable list was found, so just create a new one
+                                self.start_block(Kind::List, Args::default(), Entry { level: indent, ..Default::default() })?;
+                                break;
+                            }
+                        }
+                        self.start_block(Kind::ListItem, Args::default(), Entry { level: indent, ..Default::default() })?;
+                    }
+                    break;
+                }
+            }
+            else {
+                self.add_line_break();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // common code for all text styling tags (bold etc.)
+    fn do_markup(&mut self, kind: Kind) -> Result<(), String> {
+        let symbol = self.c;
+        self.scan();
+        if self.can_start_markup(kind) {
+            self.start_block(kind, Args::default(), Entry::default())?;
+        }
+        else if self.can_end_markup(kind) {
+            self.end_block();
+        }
+        else if let Some(symbol) = symbol {
+            self.add_text(&[symbol]);
+        }
+        Ok(())
+    }
+
+    /// The js uses "".includes(char), which is true for the empty string (past either end)
+    fn includes(set: &str, c: Option<u16>) -> bool {
+        c.map(|c| c < 128 && set.contains(c as u8 as char)).unwrap_or(true)
+    }
+
+    fn can_start_markup(&self, kind: Kind) -> bool {
+        Self::includes(" \t\n({'\"", self.at(self.i - 2)) &&
+        !Self::includes(" \t\n,'\"", self.c) &&
+        !self.stack_contains(Some(kind))
+    }
+
+    fn can_end_markup(&self, kind: Kind) -> bool {
+        self.top_is(kind) &&
+        !Self::includes(" \t\n,'\"", self.at(self.i - 2)) &&
+        Self::includes(" \t\n-.,:!?')}\"", self.c)
+    }
+
+    fn build(&mut self, index: usize) -> Node {
+        let node = &mut self.nodes[index];
+        let kind = node.kind;
+        let args = std::mem::take(&mut node.args);
+        let content = node.content.take().map(|leaves| {
+            leaves.into_iter().map(|leaf| match leaf {
+                LLeaf::Text(text) => Leaf::Text(text),
+                LLeaf::Node(child) => Leaf::Node(self.build(child))
+            }).collect()
+        });
+        Node::new(kind, args, content)
+    }
+}
+
+/// Parse legacy 12y markup into a tree. Fails only when blocks are nested too deep, like the js
+pub fn parse_12y(text: &str) -> Result<Node, String> {
+    if text.is_empty() {
+        return Ok(Node::new(Kind::Root, Args::default(), Some(Vec::new())));
+    }
+    let mut parser = Legacy::new(text);
+    parser.parse()?;
+    Ok(parser.build(0))
+}
+
+/// Plain text, with links turned into links and nothing else
+pub fn parse_plaintext(text: &str) -> Node {
+    let mut content = Vec::new();
+    let mut last = 0;
+    let mut p = 0;
+    // \b(?:https?://|sbs:)[-\w$.+!*'(),;/?:@=&#%]*
+    while p < text.len() {
+        let rest = &text[p..];
+        let boundary = !text[..p].chars().next_back().map(js_word).unwrap_or(false);
+        let scheme = ["http://", "https://", "sbs:"].iter().find(|s| rest.starts_with(*s));
+        if let (true, Some(scheme)) = (boundary, scheme) {
+            let after = &rest[scheme.len()..];
+            let len = scheme.len() + after.find(|c: char| !(js_word(c) || "-$.+!*'(),;/?:@=&#%".contains(c))).unwrap_or(after.len());
+            if p > last {
+                content.push(Leaf::Text(text[last..p].to_string()));
+            }
+            content.push(Leaf::Node(Node::new(Kind::SimpleLink, Args { url: Some(rest[..len].to_string()), ..Default::default() }, None)));
+            p += len;
+            last = p;
+        }
+        else {
+            p += rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+    }
+    if last < text.len() {
+        content.push(Leaf::Text(text[last..].to_string()));
+    }
+    Node::new(Kind::Root, Args::default(), Some(content))
+}
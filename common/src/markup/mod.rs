@@ -0,0 +1,175 @@
+//! Server side versions of the 12y and 12y2 markup parsers from static/markup. These are direct ports:
+//! the js is the reference, and these should produce the same trees (and so the same html) for the same
+//! text, quirks and all. If you change one, change the other!
+
+pub mod parse;
+pub mod legacy;
+pub mod render;
+
+#[cfg(test)]
+mod tests;
+
+pub const MARKUP12Y: &str = "12y";
+pub const MARKUP12Y2: &str = "12y2";
+pub const MARKUPPLAINTEXT: &str = "plaintext";
+
+/// Every kind of node the parsers can produce. The last few only exist while parsing 12y2 and never
+/// make it into a finished tree
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Root,
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Subscript,
+    Superscript,
+    Heading,
+    Quote,
+    Divider,
+    Code,
+    ICode,
+    SimpleLink,
+    Link,
+    Image,
+    Audio,
+    Video,
+    Youtube,
+    List,
+    ListItem,
+    Table,
+    TableRow,
+    TableCell,
+    Align,
+    Ruby,
+    Spoiler,
+    BackgroundColor,
+    Anchor,
+    Invalid,
+    Key,
+    //Parse only
+    Style,
+    NullEnv,
+    TableDivider
+}
+
+impl Kind {
+    /// The node type name as the js parsers call it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Root => "ROOT",
+            Kind::Bold => "bold",
+            Kind::Italic => "italic",
+            Kind::Underline => "underline",
+            Kind::Strikethrough => "strikethrough",
+            Kind::Subscript => "subscript",
+            Kind::Superscript => "superscript",
+            Kind::Heading => "heading",
+            Kind::Quote => "quote",
+            Kind::Divider => "divider",
+            Kind::Code => "code",
+            Kind::ICode => "icode",
+            Kind::SimpleLink => "simple_link",
+            Kind::Link => "link",
+            Kind::Image => "image",
+            Kind::Audio => "audio",
+            Kind::Video => "video",
+            Kind::Youtube => "youtube",
+            Kind::List => "list",
+            Kind::ListItem => "list_item",
+            Kind::Table => "table",
+            Kind::TableRow => "table_row",
+            Kind::TableCell => "table_cell",
+            Kind::Align => "align",
+            Kind::Ruby => "ruby",
+            Kind::Spoiler => "spoiler",
+            Kind::BackgroundColor => "background_color",
+            Kind::Anchor => "anchor",
+            Kind::Invalid => "invalid",
+            Kind::Key => "key",
+            Kind::Style => "style",
+            Kind::NullEnv => "null_env",
+            Kind::TableDivider => "table_divider"
+        }
+    }
+}
+
+/// All the arguments any node might have. The js uses a different object per node type; most of
+/// these will be empty for any given node
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+    pub url: Option<String>,
+    pub text: Option<String>,
+    pub alt: Option<String>,
+    pub lang: Option<String>,
+    pub cite: Option<String>,
+    pub label: Option<String>,
+    pub align: Option<String>,
+    pub color: Option<String>,
+    pub truecolor: Option<String>,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub reason: Option<String>,
+    pub style: Option<String>,
+    pub level: Option<i64>,
+    pub indent: Option<i64>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub colspan: Option<i64>,
+    pub rowspan: Option<i64>,
+    pub header: bool,
+    pub div: bool,
+    pub divider: bool
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: Kind,
+    pub args: Box<Args>,
+    pub content: Option<Vec<Leaf>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Leaf {
+    Text(String),
+    Node(Node)
+}
+
+impl Node {
+    pub fn new(kind: Kind, args: Args, content: Option<Vec<Leaf>>) -> Self {
+        Node { kind, args: Box::new(args), content }
+    }
+}
+
+/// Parse the text with the given markup. Like the js, anything we don't know is plaintext. Fails where
+/// the js would throw (nesting too deep in 12y), and on 12y2 nested deeper than we can safely render
+pub fn parse_lang(text: &str, lang: &str) -> Result<Node, String> {
+    match lang {
+        MARKUP12Y2 => parse::parse(text),
+        MARKUP12Y => legacy::parse_12y(text),
+        _ => Ok(legacy::parse_plaintext(text))
+    }
+}
+
+/// The markup equivalent of the js Markup.convert_lang: parse the text and render it straight to html.
+/// `http_root` is for the site-local sbs: links
+pub fn convert_lang(text: &str, lang: &str, http_root: &str) -> Result<String, String> {
+    parse_lang(text, lang).map(|tree| render::render(&tree, http_root))
+}
+
+/// JS "truthy" number conversion for the handful of places the parsers lean on it; not a full ToNumber
+pub(crate) fn js_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0.0);
+    }
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok().map(|n| n as f64);
+    }
+    if text.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-' || c == '+' || c == 'e' || c == 'E') {
+        return text.parse::<f64>().ok();
+    }
+    if text == "Infinity" || text == "+Infinity" { return Some(f64::INFINITY); }
+    if text == "-Infinity" { return Some(f64::NEG_INFINITY); }
+    None
+}
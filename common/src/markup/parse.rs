@@ -0,0 +1,1047 @@
+//! The 12y2 parser, ported from static/markup/parse.js. The js drives everything off one big regex with
+//! lookaheads; here the tokens are matched by hand, in the same order, at the same positions. Like the js,
+//! "start of line" tokens only match at the start of the remaining text, which gets cut down every time
+//! a token starts a new line or body.
+
+use std::collections::HashMap;
+
+use super::*;
+
+/// How deep blocks can nest before the text is given up on. The js doesn't care, but everything that walks
+/// the finished tree (rendering, dropping it) recurses, and a few kilobytes of \quote{ would run it out of stack
+const MAXDEPTH: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Prev {
+    AllNewline,
+    Newline,
+    Text,
+    Block
+}
+
+/// Arguments as written in [...]; a list of plain values plus any name=value pairs
+#[derive(Clone, Debug, Default)]
+struct RawArgs {
+    list: Vec<String>,
+    named: HashMap<String, String>
+}
+
+impl RawArgs {
+    fn first(&self) -> Option<&str> {
+        self.list.first().map(|s| s.as_str())
+    }
+}
+
+/// What's stored in a node while it's still open. Most are finished args, but styles keep the token that
+/// opened them, rows keep their raw text (in case they're cancelled) and cells keep the raw args
+enum FrameArgs {
+    Args(Box<Args>),
+    Token(String),
+    Raw(Option<RawArgs>)
+}
+
+impl FrameArgs {
+    fn boxed(args: Args) -> Self {
+        FrameArgs::Args(Box::new(args))
+    }
+}
+
+struct Frame {
+    kind: Kind,
+    args: FrameArgs,
+    content: Vec<Leaf>,
+    body: bool,
+    prev: Prev
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Token {
+    BlockEnd,
+    Newline,
+    Heading,
+    Quote,
+    Divider,
+    Style,
+    Tag,
+    NullEnv,
+    Escaped,
+    CodeBlock,
+    InlineCode,
+    Embed,
+    Link,
+    TableDivider,
+    TableStart,
+    TableCell,
+    ListItem
+}
+
+struct Match {
+    index: usize,
+    end: usize,
+    token: Token
+}
+
+const COLORS: &[&str] = &["red", "orange", "yellow", "green", "blue", "purple", "gray"];
+
+fn is_block(kind: Kind) -> bool {
+    matches!(kind, Kind::Code | Kind::Divider | Kind::Root | Kind::Heading | Kind::Quote | Kind::Table |
+        Kind::TableCell | Kind::Image | Kind::Video | Kind::Audio | Kind::Spoiler | Kind::Align | Kind::List |
+        Kind::ListItem | Kind::Youtube | Kind::Anchor | Kind::TableDivider)
+}
+
+fn is_color(arg: Option<&str>) -> bool {
+    arg.map(|a| COLORS.contains(&a)).unwrap_or(false)
+}
+
+/// Javascript's \s, which is a little different from rust's idea of whitespace
+pub(crate) fn js_space(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\u{b}' | '\u{c}' | '\r' | ' ' | '\u{a0}' | '\u{1680}' | '\u{2000}'..='\u{200a}' |
+        '\u{2028}' | '\u{2029}' | '\u{202f}' | '\u{205f}' | '\u{3000}' | '\u{feff}')
+}
+
+/// Javascript's \w (ascii only)
+pub(crate) fn js_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Javascript's "." doesn't match any of these
+fn js_line_end(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+fn url_char(c: char) -> bool {
+    js_word(c) || "-/%&=#+~@$*'!?,.;:".contains(c)
+}
+
+fn url_final(c: char) -> bool {
+    js_word(c) || "-/%&=#+~@$*'".contains(c)
+}
+
+fn parse_args(arglist: &str) -> RawArgs {
+    let mut result = RawArgs::default();
+    for arg in arglist.split(';') {
+        //value OR =value (this is to allow values to contain =. ex: [=1=2] is "1=2")
+        match arg.split_once('=') {
+            Some((name, value)) if !name.is_empty() => { result.named.insert(name.to_string(), value.to_string()); },
+            Some((_, value)) => result.list.push(value.to_string()),
+            None => result.list.push(arg.to_string())
+        }
+    }
+    result
+}
+
+/// Does the text (lowercased) have .ext for any of these extensions, with a word boundary after?
+fn has_extension(url: &str, extensions: &[&str]) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.match_indices('.').any(|(i, _)| {
+        let rest = &lower[i + 1..];
+        extensions.iter().any(|ext| rest.starts_with(ext) && !rest[ext.len()..].chars().next().map(js_word).unwrap_or(false))
+    })
+}
+
+/// ^https?://(?:www[.])?(?:youtube.com/watch[?]v=|youtu[.]be/|youtube.com/shorts/)[\w-]{11} (the unescaped dots
+/// in the js match anything, so they do here too)
+pub(crate) fn is_youtube(url: &str) -> bool {
+    let rest = match url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) {
+        Some(rest) => rest,
+        None => return false
+    };
+    let id_ok = |s: &str| s.chars().take(11).filter(|c| js_word(*c) || *c == '-').count() == 11;
+    let pattern_match = |s: &str, pattern: &str| -> Option<usize> {
+        let mut chars = s.char_indices();
+        for p in pattern.chars() {
+            let (_, c) = chars.next()?;
+            if p != '.' && p != c { return None; }
+        }
+        Some(chars.next().map(|(i, _)| i).unwrap_or(s.len()))
+    };
+    let check = |s: &str| {
+        ["youtube.com/watch?v=", "youtu.be/", "youtube.com/shorts/"].iter().any(|pattern| {
+            //the ? in watch?v= is escaped in the js, so it really is a question mark
+            pattern_match(s, pattern).map(|end| id_ok(&s[end..])).unwrap_or(false)
+        })
+    };
+    check(rest) || rest.strip_prefix("www.").map(check).unwrap_or(false)
+}
+
+/// process an embed url: !https://example.com/image.png[alt=balls]
+fn process_embed(url: &str, rargs: Option<&RawArgs>) -> (Kind, Args) {
+    let mut kind = None;
+    let mut args = Args { url: Some(url.to_string()), ..Default::default() };
+    if let Some(rargs) = rargs {
+        for arg in &rargs.list {
+            if arg == "video" || arg == "audio" || arg == "image" {
+                kind = Some(match arg.as_str() { "video" => Kind::Video, "audio" => Kind::Audio, _ => Kind::Image });
+            }
+            else if let Some((w, h)) = parse_size(arg, false) {
+                args.width = Some(w);
+                args.height = Some(h);
+            }
+            else {
+                args.alt = Some(match args.alt {
+                    None => arg.clone(),
+                    Some(alt) => format!("{};{}", alt, arg)
+                });
+            }
+        }
+        if let Some(alt) = rargs.named.get("alt") {
+            args.alt = Some(alt.clone());
+        }
+    }
+    let kind = kind.unwrap_or_else(|| {
+        if has_extension(url, &["mp3", "ogg", "wav", "m4a", "flac", "aac", "oga", "opus", "wma"]) {
+            Kind::Audio
+        }
+        else if has_extension(url, &["mp4", "mkv", "mov", "webm", "avi", "flv", "m4v", "mpeg", "mpg", "ogv", "ogm", "ogx", "wmv", "xvid"]) {
+            Kind::Video
+        }
+        else if is_youtube(url) {
+            Kind::Youtube
+        }
+        else {
+            Kind::Image
+        }
+    });
+    (kind, args)
+}
+
+/// WxH, where the numbers may be empty only if allow_empty (so (\d+)x(\d+) or (\d*)x(\d*))
+fn parse_size(arg: &str, allow_empty: bool) -> Option<(i64, i64)> {
+    let (w, h) = arg.split_once('x')?;
+    let valid = |s: &str| s.chars().all(|c| c.is_ascii_digit()) && (allow_empty || !s.is_empty());
+    if !valid(w) || !valid(h) {
+        return None;
+    }
+    let num = |s: &str| s.parse::<f64>().unwrap_or(0.0) as i64;
+    Some((num(w), num(h)))
+}
+
+fn process_cell_args(rargs: Option<&RawArgs>) -> Args {
+    let mut args = Args::default();
+    for arg in rargs.map(|r| r.list.as_slice()).unwrap_or(&[]) {
+        if arg == "*" || arg == "#" {
+            args.header = true;
+        }
+        else if arg == "-div" {
+            args.div = true;
+        }
+        else if is_color(Some(arg)) {
+            args.color = Some(arg.clone());
+        }
+        else if let Some((w, h)) = parse_size(arg, true) {
+            if w > 1 { args.colspan = Some(w); }
+            if h > 1 { args.rowspan = Some(h); }
+        }
+    }
+    args
+}
+
+fn process_row_args(rargs: Option<&RawArgs>) -> Args {
+    Args {
+        header: rargs.map(|r| r.list.iter().any(|a| a == "*" || a == "#")).unwrap_or(false),
+        ..Default::default()
+    }
+}
+
+fn frame_args(args: FrameArgs) -> Args {
+    match args {
+        FrameArgs::Args(args) => *args,
+        _ => Args::default()
+    }
+}
+
+/// \W+ replaced with -, for anchor names
+fn anchor_id(id: Option<&str>) -> Option<String> {
+    let id = id.filter(|i| !i.is_empty())?;
+    let mut result = String::new();
+    let mut in_run = false;
+    for c in id.chars() {
+        if js_word(c) {
+            result.push(c);
+            in_run = false;
+        }
+        else if !in_run {
+            result.push('-');
+            in_run = true;
+        }
+    }
+    Some(result)
+}
+
+/// Put a finished list item where it belongs: in the last list at the same indent, or a new (nested) list.
+/// Returns how deep the item ended up, counting from `depth` for `dest`
+fn insert_list_item(dest: &mut Vec<Leaf>, indent: i64, style: &Option<String>, item: Leaf, depth: usize) -> usize {
+    if let Some(Leaf::Node(last)) = dest.last_mut() {
+        if last.kind == Kind::List && last.args.indent.unwrap_or(0) <= indent && depth < MAXDEPTH {
+            let content = last.content.get_or_insert_with(Vec::new);
+            if last.args.indent.unwrap_or(0) == indent {
+                content.push(item);
+                return depth + 1;
+            }
+            else {
+                return insert_list_item(content, indent, style, item, depth + 1);
+            }
+        }
+    }
+    let list_args = Args { indent: Some(indent), style: style.clone(), ..Default::default() };
+    dest.push(Leaf::Node(Node::new(Kind::List, list_args, Some(vec![item]))));
+    depth + 1
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    base: usize,    //Where the "text" currently starts (start of line tokens only match here)
+    pos: usize,     //REGEX.lastIndex
+    last: usize,    //End of the last accepted token
+    stack: Vec<Frame>,
+    brackets: i32,
+    body: Option<bool>,
+    rargs: Option<RawArgs>,
+    too_deep: bool
+}
+
+impl<'a> Parser<'a> {
+
+    fn char_at(&self, index: usize) -> Option<char> {
+        self.src.get(index..).and_then(|s| s.chars().next())
+    }
+
+    fn char_before(&self, index: usize) -> Option<char> {
+        self.src.get(self.base..index).and_then(|s| s.chars().next_back())
+    }
+
+    fn rest(&self, index: usize) -> &'a str {
+        self.src.get(index..).unwrap_or("")
+    }
+
+    fn current(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("Always at least a root!")
+    }
+
+    // -- tree operations --
+
+    fn pop(&mut self) -> Frame {
+        let frame = self.stack.pop().expect("Popped past the root!");
+        if frame.body {
+            self.brackets -= 1;
+        }
+        frame
+    }
+
+    fn push(&mut self, kind: Kind, args: Args, content: Vec<Leaf>) {
+        self.current().content.push(Leaf::Node(Node::new(kind, args, Some(content))));
+    }
+
+    // push text
+    fn text(&mut self, text: &str) {
+        let current = self.current();
+        let text = if current.prev == Prev::Block { text.trim_start_matches(' ') } else { text };
+        if !text.is_empty() {
+            current.content.push(Leaf::Text(text.to_string()));
+            current.prev = Prev::Text;
+        }
+    }
+
+    fn close(&mut self, cancel: bool) {
+        let o = self.pop();
+        let kind = o.kind;
+
+        match kind {
+            Kind::Style => {
+                let token = match o.args { FrameArgs::Token(t) => t, _ => String::new() };
+                if cancel {
+                    self.text(&token);
+                    self.current().content.extend(o.content);
+                }
+                else {
+                    let style = match token.as_str() {
+                        "**" => Kind::Bold,
+                        "__" => Kind::Underline,
+                        "~~" => Kind::Strikethrough,
+                        _ => Kind::Italic
+                    };
+                    self.push(style, Args::default(), o.content);
+                }
+            },
+            Kind::NullEnv => {
+                self.current().content.extend(o.content);
+            },
+            Kind::TableDivider => {
+                if let Some(Leaf::Node(above)) = self.current().content.last_mut() {
+                    if above.kind == Kind::Table {
+                        *above.args = Args { divider: true, ..Default::default() };
+                    }
+                }
+            },
+            Kind::TableCell => {
+                let raw = match o.args { FrameArgs::Raw(r) => r, _ => None };
+                let empty = o.content.is_empty();
+                // push cell if not empty
+                if !cancel || !empty {
+                    self.push(Kind::TableCell, process_cell_args(raw.as_ref()), o.content);
+                    self.current().prev = Prev::Block;
+                }
+                // cancelled = next row
+                if cancel {
+                    // empty cell -> parse arguments as row arguments
+                    if empty {
+                        // exception: empty row -> cancel table
+                        if self.current().content.is_empty() {
+                            let row = self.pop();
+                            if let FrameArgs::Token(token) = row.args {
+                                self.text(&token);
+                            }
+                            return;
+                        }
+                        self.current().args = FrameArgs::boxed(process_row_args(raw.as_ref()));
+                    }
+                    else {
+                        self.current().args = FrameArgs::boxed(Args::default());
+                    }
+                    self.close(true);
+                    return;
+                }
+            },
+            Kind::ListItem => {
+                let args = frame_args(o.args);
+                let item = Leaf::Node(Node::new(Kind::ListItem, Args::default(), Some(o.content)));
+                let depth = self.stack.len();
+                if insert_list_item(&mut self.current().content, args.indent.unwrap_or(0), &args.style, item, depth) >= MAXDEPTH {
+                    self.too_deep = true;
+                }
+            },
+            Kind::TableRow => {
+                let mut args = frame_args(o.args);
+                let current = self.current();
+                let has_table = matches!(current.content.last(), Some(Leaf::Node(n)) if n.kind == Kind::Table);
+                if !has_table {
+                    current.content.push(Leaf::Node(Node::new(Kind::Table, Args::default(), Some(Vec::new()))));
+                }
+                if let Some(Leaf::Node(table)) = current.content.last_mut() {
+                    if table.args.divider {
+                        table.args.divider = false;
+                        args.divider = true;
+                    }
+                    table.content.get_or_insert_with(Vec::new).push(Leaf::Node(Node::new(Kind::TableRow, args, Some(o.content))));
+                }
+            },
+            _ => {
+                self.push(kind, frame_args(o.args), o.content);
+            }
+        }
+
+        self.current().prev = if is_block(kind) { Prev::Block } else { o.prev };
+    }
+
+    // push empty tag
+    fn block(&mut self, kind: Kind, args: Args) {
+        let current = self.current();
+        current.content.push(Leaf::Node(Node::new(kind, args, None)));
+        current.prev = if is_block(kind) { Prev::Block } else { Prev::Text };
+    }
+
+    fn newline(&mut self, real: bool) {
+        if real {
+            while !self.current().body && self.current().kind != Kind::Root {
+                self.close(true);
+            }
+        }
+        let current = self.current();
+        if current.prev != Prev::Block {
+            current.content.push(Leaf::Text(String::from("\n")));
+        }
+        if current.prev != Prev::AllNewline {
+            current.prev = Prev::Newline;
+        }
+    }
+
+    // start a new block
+    fn open(&mut self, kind: Kind, args: FrameArgs) {
+        let body = self.body == Some(true);
+        self.stack.push(Frame { kind, args, content: Vec::new(), body, prev: Prev::AllNewline });
+        if body {
+            self.brackets += 1;
+        }
+        if self.stack.len() >= MAXDEPTH {
+            self.too_deep = true;
+        }
+    }
+
+    // -- styles --
+
+    fn find_style(&self, token: &str) -> Option<usize> {
+        for (i, frame) in self.stack.iter().enumerate().rev() {
+            if frame.kind != Kind::Style {
+                break;
+            }
+            if matches!(&frame.args, FrameArgs::Token(t) if t == token) {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// None means this isn't a style at all, Some(None) opens a new one, Some(Some(i)) closes the one at i
+    fn check_style(&self, token: &str, before: char, after: char) -> Option<Option<usize>> {
+        let ital = token == "/";
+        let style_end = |b: char, a: char| {
+            !js_space(b) && b != ',' && (!ital || b != '/' && b != '>') &&
+            (js_space(a) || "-.,:;!?'\"}{)<\\|".contains(a))
+        };
+        let style_start = |b: char, a: char| {
+            let after_ok = |extra: &str| !js_space(a) && !extra.contains(a);
+            (js_space(b) && after_ok(if ital { ",/" } else { "," })) ||
+            ("'\"}{(>|".contains(b) && after_ok(if ital { ",'\"/<" } else { ",'\"" }))
+        };
+        if let Some(c) = self.find_style(token) {
+            if style_end(before, after) {
+                return Some(Some(c));
+            }
+        }
+        if style_start(before, after) {
+            return Some(None);
+        }
+        None
+    }
+
+    // -- reading --
+
+    fn skip_spaces(&mut self) {
+        while self.char_at(self.pos) == Some(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn read_code(&mut self) -> (Option<String>, String) {
+        let start = self.pos;
+        let rest = self.rest(start);
+        // (?: *([-\w.+#$ ]+?) *(?![^\n]))?
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+        let line = &rest[..line_end];
+        let mut lang = None;
+        let mut code_start = start;
+        if !line.is_empty() && line.chars().all(|c| js_word(c) || "-.+#$ ".contains(c)) {
+            let trimmed = line.trim_matches(' ');
+            lang = Some(if trimmed.is_empty() { String::from(" ") } else { trimmed.to_string() });
+            code_start = start + line_end;
+        }
+        // \n?
+        if self.char_at(code_start) == Some('\n') {
+            code_start += 1;
+        }
+        // ([^]*?)(?:\n?```|$)
+        let body = self.rest(code_start);
+        let (code_end, end) = match body.find("```") {
+            Some(i) if i > 0 && body[..i].ends_with('\n') => (code_start + i - 1, code_start + i + 3),
+            Some(i) => (code_start + i, code_start + i + 3),
+            None => (self.src.len(), self.src.len())
+        };
+        self.pos = end;
+        (lang, self.src[code_start..code_end].to_string())
+    }
+
+    fn read_args(&mut self) {
+        self.rargs = None;
+        if self.char_at(self.pos) != Some('[') {
+            return;
+        }
+        let rest = self.rest(self.pos + 1);
+        for (i, c) in rest.char_indices() {
+            if c == ']' {
+                self.rargs = Some(parse_args(&rest[..i]));
+                self.pos = self.pos + 1 + i + 1;
+                return;
+            }
+            if js_line_end(c) {
+                return;
+            }
+        }
+    }
+
+    fn read_body(&mut self, space: bool) {
+        let next = self.char_at(self.pos);
+        if next == Some('{') {
+            if self.char_at(self.pos + 1) == Some('\n') {
+                self.pos += 1;
+            }
+            self.pos += 1;
+            self.body = Some(true);
+            return;
+        }
+        if space {
+            if next == Some(' ') {
+                self.pos += 1;
+            }
+            else {
+                self.body = Some(false);
+                return;
+            }
+        }
+        self.body = None;
+    }
+
+    fn read_word(&mut self) -> String {
+        let rest = self.rest(self.pos);
+        let len = rest.find(|c: char| js_space(c) || "`^()+=[]{}\\|\"';:,.<>/?!*".contains(c)).unwrap_or(rest.len());
+        let word = rest[..len].to_string();
+        self.pos += len;
+        self.last = self.pos;
+        word
+    }
+
+    fn word_maybe(&mut self) {
+        if self.body != Some(true) {
+            let word = self.read_word();
+            self.text(&word);
+            self.close(false);
+        }
+    }
+
+    fn accept(&mut self, m: &Match) {
+        let text = &self.src[self.last..m.index];
+        self.text(text);
+        self.last = self.pos;
+    }
+
+    // -- tokens --
+
+    /// The url part of a link (after the optional !), returning the end
+    fn match_url(&self, start: usize) -> Option<usize> {
+        let rest = self.rest(start);
+        let scheme = ["https://", "http://", "sbs:"].iter().find(|s| rest.starts_with(*s))?;
+        //{URL_CHARS}{URL_FINAL}: the longest run of url chars, backed up to the last final char
+        let final_run = |from: usize| -> (usize, usize) {
+            let run = self.rest(from);
+            let run_len = run.find(|c: char| !url_char(c)).unwrap_or(run.len());
+            let final_len = run[..run_len].rfind(url_final).map(|i| i + 1).unwrap_or(0);
+            (run_len, final_len)
+        };
+        let url_start = start + scheme.len();
+        let (run_len, final_len) = final_run(url_start);
+        if final_len == 0 {
+            return None;
+        }
+        let mut end = url_start + final_len;
+        //([(]{URL_CHARS}[)]({URL_CHARS}{URL_FINAL})?)?
+        if final_len == run_len && self.char_at(end) == Some('(') {
+            let inner = self.rest(end + 1);
+            let inner_len = inner.find(|c: char| !url_char(c)).unwrap_or(inner.len());
+            if inner[inner_len..].starts_with(')') {
+                end = end + 1 + inner_len + 1;
+                let (_, after_final) = final_run(end);
+                end += after_final;
+            }
+        }
+        Some(end)
+    }
+
+    fn match_at(&self, p: usize) -> Option<(usize, Token)> {
+        let rest = self.rest(p);
+        let bol = p == self.base;
+        let c = rest.chars().next()?;
+        let next = |n: usize| rest.as_bytes().get(n).map(|b| *b as char);
+
+        //BLOCK_END
+        if c == '}' { return Some((p + 1, Token::BlockEnd)); }
+        if c == '\n' && next(1) == Some('}') { return Some((p + 2, Token::BlockEnd)); }
+        //NEWLINE
+        if c == '\n' { return Some((p + 1, Token::Newline)); }
+        if bol {
+            //HEADING
+            let hashes = rest.len() - rest.trim_start_matches('#').len();
+            if (1..=4).contains(&hashes) && matches!(next(hashes), Some('[' | '{' | ' ')) {
+                return Some((p + hashes, Token::Heading));
+            }
+            //QUOTE
+            if c == '>' && matches!(next(1), Some('[' | '{' | ' ')) {
+                return Some((p + 1, Token::Quote));
+            }
+            //DIVIDER
+            let dashes = rest.len() - rest.trim_start_matches('-').len();
+            if dashes >= 3 && matches!(next(dashes), None | Some('\n')) {
+                return Some((p + dashes, Token::Divider));
+            }
+        }
+        //STYLE
+        if rest.starts_with("**") || rest.starts_with("__") || rest.starts_with("~~") { return Some((p + 2, Token::Style)); }
+        if c == '/' { return Some((p + 1, Token::Style)); }
+        if c == '\\' {
+            //TAG (and the escaped url schemes inside it)
+            let name = &rest[1..];
+            let name_len = name.len() - name.trim_start_matches(|c: char| c.is_ascii_lowercase()).len();
+            if name_len > 0 && !name[name_len..].chars().next().map(|c| c.is_ascii_alphanumeric()).unwrap_or(false) {
+                let token = if matches!(&name[..name_len], "http" | "https" | "sbs") { Token::Escaped } else { Token::Tag };
+                return Some((p + 1 + name_len, token));
+            }
+            //NULL_ENV
+            if next(1) == Some('{') {
+                return Some((p + if next(2) == Some('\n') { 3 } else { 2 }, Token::NullEnv));
+            }
+            //ESCAPED
+            if let Some(escaped) = rest[1..].chars().next() {
+                return Some((p + 1 + escaped.len_utf8(), Token::Escaped));
+            }
+        }
+        //CODE_BLOCK
+        if bol && rest.starts_with("```") && !rest[3..].chars().take_while(|c| !js_line_end(*c)).any(|c| c == '`') {
+            return Some((p + 3, Token::CodeBlock));
+        }
+        //INLINE_CODE
+        if c == '`' {
+            let run = |from: usize| from + self.rest(from).find(['`', '\n']).unwrap_or(self.rest(from).len());
+            let mut end = run(p + 1);
+            while self.rest(end).starts_with("``") {
+                end = run(end + 2);
+            }
+            if self.char_at(end) == Some('`') {
+                end += 1;
+            }
+            return Some((end, Token::InlineCode));
+        }
+        //LINK (and EMBED)
+        if c == '!' {
+            if let Some(end) = self.match_url(p + 1) {
+                return Some((end, Token::Embed));
+            }
+        }
+        if !self.char_before(p).map(js_word).unwrap_or(false) {
+            if let Some(end) = self.match_url(p) {
+                return Some((end, Token::Link));
+            }
+        }
+        if bol {
+            //TABLE_DIVIDER
+            if c == '|' {
+                let inner = &rest[1..];
+                let inner_len = inner.len() - inner.trim_start_matches(['-', '+']).len();
+                let inner_run = &inner[..inner_len];
+                if inner_len >= 2 && inner_run.starts_with('-') && inner_run.ends_with('-') && next(1 + inner_len) == Some('|') &&
+                    matches!(next(2 + inner_len), None | Some('\n'))
+                {
+                    return Some((p + 2 + inner_len, Token::TableDivider));
+                }
+            }
+        }
+        let spaces = rest.len() - rest.trim_start_matches(' ').len();
+        //TABLE_START
+        if bol && next(spaces) == Some('|') {
+            return Some((p + spaces + 1, Token::TableStart));
+        }
+        //TABLE_CELL
+        if next(spaces) == Some('|') {
+            return Some((p + spaces + if next(spaces + 1) == Some('|') { 2 } else { 1 }, Token::TableCell));
+        }
+        //LIST_ITEM
+        if bol && next(spaces) == Some('-') {
+            return Some((p + spaces + 1, Token::ListItem));
+        }
+        None
+    }
+
+    fn next_match(&self) -> Option<Match> {
+        let mut p = self.pos;
+        while p < self.src.len() {
+            if let Some((end, token)) = self.match_at(p) {
+                return Some(Match { index: p, end, token });
+            }
+            p += self.char_at(p).map(|c| c.len_utf8()).unwrap_or(1);
+        }
+        None
+    }
+
+    fn parse(mut self) -> Result<Node, String> {
+        'main: while let Some(m) = self.next_match() {
+            if self.too_deep {
+                return Err(String::from("too deeply nested blocks"));
+            }
+            self.pos = m.end;
+            let token = self.src[m.index..m.end].to_string();
+            self.body = None;
+            self.rargs = None;
+
+            match m.token {
+                Token::Tag => {
+                    self.read_args();
+                    if token == "\\link" {
+                        self.read_body(false);
+                    }
+                    else {
+                        self.read_body(true);
+                        if self.rargs.is_none() && self.body == Some(false) {
+                            self.pos = m.index + 1;
+                            continue 'main;
+                        }
+                    }
+                    self.accept(&m);
+                    let first = self.rargs.as_ref().and_then(|r| r.first()).map(String::from);
+                    let simple = |kind: Kind| (kind, FrameArgs::boxed(Args::default()));
+                    let (kind, args) = match token.as_str() {
+                        "\\sub" => simple(Kind::Subscript),
+                        "\\sup" => simple(Kind::Superscript),
+                        "\\b" => simple(Kind::Bold),
+                        "\\i" => simple(Kind::Italic),
+                        "\\u" => simple(Kind::Underline),
+                        "\\s" => simple(Kind::Strikethrough),
+                        "\\key" => simple(Kind::Key),
+                        "\\quote" => (Kind::Quote, FrameArgs::boxed(Args { cite: first, ..Default::default() })),
+                        "\\align" => {
+                            let align = first.filter(|a| matches!(a.as_str(), "left" | "right" | "center")).unwrap_or_else(|| String::from("center"));
+                            (Kind::Align, FrameArgs::boxed(Args { align: Some(align), ..Default::default() }))
+                        },
+                        "\\spoiler" | "\\h" => (Kind::Spoiler, FrameArgs::boxed(Args { label: Some(first.unwrap_or_else(|| String::from("spoiler"))), ..Default::default() })),
+                        "\\ruby" => (Kind::Ruby, FrameArgs::boxed(Args { text: Some(first.unwrap_or_else(|| String::from("true"))), ..Default::default() })),
+                        "\\a" => (Kind::Anchor, FrameArgs::boxed(Args { id: anchor_id(first.as_deref()), ..Default::default() })),
+                        "\\link" => (Kind::Link, FrameArgs::boxed(Args { url: first, ..Default::default() })),
+                        "\\bg" => (Kind::BackgroundColor, FrameArgs::boxed(Args { color: first.filter(|c| is_color(Some(c))), ..Default::default() })),
+                        _ => {
+                            let args = Args { text: Some(self.src[m.index..self.last].to_string()), reason: Some(String::from("invalid tag")), ..Default::default() };
+                            (Kind::Invalid, FrameArgs::boxed(args))
+                        }
+                    };
+                    if kind == Kind::Invalid && self.body != Some(true) {
+                        self.block(kind, frame_args(args));
+                    }
+                    else if kind == Kind::Link && self.body != Some(true) {
+                        self.block(Kind::SimpleLink, frame_args(args));
+                    }
+                    else {
+                        self.open(kind, args);
+                        if matches!(kind, Kind::Subscript | Kind::Superscript | Kind::Bold | Kind::Italic | Kind::Underline |
+                            Kind::Strikethrough | Kind::Ruby | Kind::Key)
+                        {
+                            self.word_maybe();
+                        }
+                        if kind == Kind::Anchor {
+                            self.body = Some(true); // ghhhh?
+                        }
+                    }
+                },
+                Token::Style => {
+                    let before = self.char_before(m.index).unwrap_or('\n');
+                    let after = self.char_at(self.pos).unwrap_or('\n');
+                    let style = match self.check_style(&token, before, after) {
+                        Some(style) => style,
+                        None => {
+                            self.pos = m.index + 1;
+                            continue 'main;
+                        }
+                    };
+                    self.accept(&m);
+                    match style {
+                        None => self.open(Kind::Style, FrameArgs::Token(token)),
+                        Some(c) => {
+                            while self.stack.len() - 1 != c {
+                                self.close(true);
+                            }
+                            self.close(false);
+                        }
+                    }
+                },
+                Token::TableCell => {
+                    let mut found = None;
+                    for (i, frame) in self.stack.iter().enumerate().rev() {
+                        if frame.kind == Kind::TableCell {
+                            found = Some(i);
+                            break;
+                        }
+                        if frame.kind != Kind::Style {
+                            break;
+                        }
+                    }
+                    let cell = match found {
+                        Some(cell) => cell,
+                        None => {
+                            //Table cell tokens can be a lot of spaces, skip the whole thing so we don't try them all again
+                            self.pos = m.end;
+                            continue 'main;
+                        }
+                    };
+                    self.read_args();
+                    self.skip_spaces();
+                    self.accept(&m);
+                    while self.stack.len() - 1 != cell {
+                        self.close(true);
+                    }
+                    self.close(false);
+                    if token.trim_start_matches(' ').starts_with("||") {
+                        if let Some(Leaf::Node(last)) = self.current().content.last_mut() {
+                            last.args.div = true;
+                        }
+                    }
+                    let rargs = self.rargs.take();
+                    self.open(Kind::TableCell, FrameArgs::Raw(rargs));
+                },
+                Token::TableDivider => {
+                    let has_table = matches!(self.current().content.last(), Some(Leaf::Node(n)) if n.kind == Kind::Table);
+                    if !has_table {
+                        self.pos = m.index + 1;
+                        continue 'main;
+                    }
+                    self.accept(&m);
+                    self.open(Kind::TableDivider, FrameArgs::boxed(Args::default()));
+                },
+                Token::TableStart => {
+                    self.read_args();
+                    self.skip_spaces();
+                    self.accept(&m);
+                    let args_token = self.src[m.index..self.last].to_string();
+                    self.open(Kind::TableRow, FrameArgs::Token(args_token));
+                    let rargs = self.rargs.take();
+                    self.open(Kind::TableCell, FrameArgs::Raw(rargs));
+                },
+                Token::Newline => {
+                    self.accept(&m);
+                    self.newline(true);
+                    self.body = Some(true); // to trigger start_line
+                },
+                Token::Heading => {
+                    self.read_args();
+                    self.read_body(true);
+                    if self.rargs.is_none() && self.body == Some(false) {
+                        self.pos = m.index + 1;
+                        continue 'main;
+                    }
+                    self.accept(&m);
+                    let id = anchor_id(self.rargs.as_ref().and_then(|r| r.first()));
+                    self.open(Kind::Heading, FrameArgs::boxed(Args { level: Some(token.len() as i64), id, ..Default::default() }));
+                },
+                Token::Divider => {
+                    self.accept(&m);
+                    self.block(Kind::Divider, Args::default());
+                },
+                Token::BlockEnd => {
+                    self.accept(&m);
+                    if self.brackets > 0 {
+                        while !self.current().body {
+                            self.close(true);
+                        }
+                        if self.current().kind == Kind::Invalid {
+                            if token == "\n}" {
+                                self.newline(false); // false since we already closed everything
+                            }
+                            self.text("}");
+                        }
+                        self.close(false);
+                    }
+                    else {
+                        if token == "\n}" {
+                            self.newline(true);
+                        }
+                        self.text("}");
+                    }
+                },
+                Token::NullEnv => {
+                    self.body = Some(true);
+                    self.accept(&m);
+                    self.open(Kind::NullEnv, FrameArgs::boxed(Args::default()));
+                    let parent_prev = self.stack[self.stack.len() - 2].prev;
+                    self.current().prev = parent_prev;
+                },
+                Token::Escaped => {
+                    self.accept(&m);
+                    if token == "\\\n" {
+                        self.newline(false);
+                    }
+                    else if token == "\\." {
+                        // \. is a no-op
+                    }
+                    else {
+                        let current = self.current();
+                        current.content.push(Leaf::Text(token[1..].to_string()));
+                        current.prev = Prev::Text;
+                    }
+                },
+                Token::Quote => {
+                    self.read_args();
+                    self.read_body(true);
+                    if self.rargs.is_none() && self.body == Some(false) {
+                        self.pos = m.index + 1;
+                        continue 'main;
+                    }
+                    self.accept(&m);
+                    let cite = self.rargs.as_ref().and_then(|r| r.first()).map(String::from);
+                    self.open(Kind::Quote, FrameArgs::boxed(Args { cite, ..Default::default() }));
+                },
+                Token::CodeBlock => {
+                    let (lang, code) = self.read_code();
+                    self.accept(&m);
+                    self.block(Kind::Code, Args { text: Some(code), lang, ..Default::default() });
+                },
+                Token::InlineCode => {
+                    self.accept(&m);
+                    let inner = token.strip_prefix('`').unwrap_or(&token);
+                    let inner = inner.strip_suffix('`').unwrap_or(inner);
+                    self.block(Kind::ICode, Args { text: Some(inner.replace("``", "`")), ..Default::default() });
+                },
+                Token::Embed => {
+                    self.read_args();
+                    self.accept(&m);
+                    let (kind, args) = process_embed(&token[1..], self.rargs.as_ref());
+                    self.block(kind, args);
+                },
+                Token::Link => {
+                    self.read_args();
+                    self.read_body(false);
+                    self.accept(&m);
+                    if self.body == Some(true) {
+                        self.open(Kind::Link, FrameArgs::boxed(Args { url: Some(token), ..Default::default() }));
+                    }
+                    else {
+                        let text = self.rargs.as_ref().and_then(|r| r.first()).map(String::from);
+                        self.block(Kind::SimpleLink, Args { url: Some(token), text, ..Default::default() });
+                    }
+                },
+                Token::ListItem => {
+                    self.read_args();
+                    self.read_body(true);
+                    if self.rargs.is_none() && self.body == Some(false) {
+                        self.pos = m.index + 1;
+                        continue 'main;
+                    }
+                    self.accept(&m);
+                    let indent = token.find('-').unwrap_or(0) as i64;
+                    let kind = if self.rargs.as_ref().and_then(|r| r.first()) == Some("1") { Some(String::from("1")) } else { None };
+                    self.open(Kind::ListItem, FrameArgs::boxed(Args { indent: Some(indent), style: kind, ..Default::default() }));
+                }
+            }
+
+            if self.body == Some(true) {
+                self.base = self.last;
+                self.pos = self.last;
+            }
+        }
+
+        let rest = &self.src[self.last..];
+        self.text(rest); // text after last token
+
+        while self.current().kind != Kind::Root {
+            self.close(true);
+        }
+        let mut root = self.stack.pop().expect("Always a root!");
+        if root.prev == Prev::Newline {
+            root.content.push(Leaf::Text(String::from("\n")));
+        }
+
+        if self.too_deep {
+            return Err(String::from("too deeply nested blocks"));
+        }
+
+        Ok(Node::new(Kind::Root, Args::default(), Some(root.content)))
+    }
+}
+
+/// Parse 12y2 markup into a tree. Fails only when blocks nest deeper than MAXDEPTH
+pub fn parse(text: &str) -> Result<Node, String> {
+    let parser = Parser {
+        src: text,
+        base: 0,
+        pos: 0,
+        last: 0,
+        stack: vec![Frame { kind: Kind::Root, args: FrameArgs::boxed(Args::default()), content: Vec::new(), body: false, prev: Prev::AllNewline }],
+        brackets: 0,
+        body: None,
+        rargs: None,
+        too_deep: false
+    };
+    parser.parse()
+}
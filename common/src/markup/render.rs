@@ -0,0 +1,380 @@
+//! Turn a markup tree into html, the same html static/markup/render.js builds with the DOM. Anything the js
+//! only does with event handlers (media controls, click-to-load audio) is left out; the plain elements
+//! underneath still work without it.
+
+use super::*;
+
+/// Escape for both text and (double quoted) attributes
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(c)
+        }
+    }
+    result
+}
+
+/// Everything style.textAlign will actually take
+fn valid_align(align: &str) -> bool {
+    matches!(align, "left" | "right" | "center" | "justify" | "start" | "end" | "match-parent")
+}
+
+/// Hex colors are the only ones the parsers let through as "true" colors
+fn valid_truecolor(color: &str) -> bool {
+    color.strip_prefix('#')
+        .map(|hex| matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false)
+}
+
+/// The sbs: scheme is for links within this site; these are the same rules as layout.js
+fn sbs_link(path: &str, http_root: &str) -> String {
+    let (front, back) = match path.find('/') {
+        Some(index) => (&path[..index], &path[index + 1..]),
+        //slice(0,-1) in the js
+        None => (&path[..path.char_indices().last().map(|(i, _)| i).unwrap_or(0)], path)
+    };
+    let link = match front {
+        "page" => format!("/forum/thread/{}", back),
+        "docs" => format!("/forum/thread/docs-{}", back),
+        _ => format!("/{}", path)
+    };
+    format!("{}{}", http_root, link)
+}
+
+/// Only let through schemes that can't do anything nasty, and make scheme-less urls https
+pub fn filter_url(url: &str, http_root: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(parsed) => match parsed.scheme() {
+            "http" | "https" | "data" => parsed.to_string(),
+            "sbs" => sbs_link(parsed.path(), http_root),
+            _ => format!("about:blank#{}", parsed)
+        },
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let relative = url::Url::parse("no-scheme:/").and_then(|base| base.join(url));
+            match relative {
+                Ok(_) => {
+                    let stripped = url.strip_prefix('/').unwrap_or(url);
+                    format!("https://{}", stripped.strip_prefix('/').unwrap_or(stripped))
+                },
+                Err(_) => format!("about:blank#{}", url)
+            }
+        },
+        Err(_) => format!("about:blank#{}", url)
+    }
+}
+
+struct Renderer<'a> {
+    http_root: &'a str,
+    output: String
+}
+
+impl<'a> Renderer<'a> {
+
+    fn push(&mut self, html: &str) {
+        self.output.push_str(html);
+    }
+
+    fn text(&mut self, text: &str) {
+        self.output.push_str(&escape(text));
+    }
+
+    fn attr(&mut self, name: &str, value: &str) {
+        self.output.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+    }
+
+    fn content(&mut self, node: &Node) {
+        if let Some(ref content) = node.content {
+            self.fill(content);
+        }
+    }
+
+    /// Links that start with # stay on the page; everything else gets filtered and opens elsewhere
+    fn link_target(&mut self, url: &str) {
+        if url.starts_with('#') {
+            self.attr("href", url);
+            self.attr("target", "_self");
+        }
+        else {
+            self.attr("href", &filter_url(url, self.http_root));
+            self.attr("target", "_blank");
+        }
+    }
+
+    fn cell(&mut self, cell: &Node, row: &Args) {
+        let args = &cell.args;
+        let tag = if args.header || row.header { "th" } else { "td" };
+        self.push("<");
+        self.push(tag);
+        if let Some(ref color) = args.color {
+            self.attr("data-bgcolor", color);
+        }
+        let mut style = String::new();
+        if let Some(ref truecolor) = args.truecolor {
+            if valid_truecolor(truecolor) {
+                style.push_str(&format!("background-color:{};", truecolor));
+            }
+        }
+        if let Some(ref align) = args.align {
+            if valid_align(align) {
+                style.push_str(&format!("text-align:{};", align));
+            }
+        }
+        if let Some(colspan) = args.colspan {
+            self.attr("colspan", &colspan.to_string());
+        }
+        if let Some(rowspan) = args.rowspan {
+            self.attr("rowspan", &rowspan.to_string());
+        }
+        if !style.is_empty() {
+            self.attr("style", &style);
+        }
+        let mut classes = Vec::new();
+        if args.div { classes.push("M-wall-right"); }
+        if row.divider { classes.push("M-wall-top"); }
+        if !classes.is_empty() {
+            self.attr("class", &classes.join(" "));
+        }
+        self.push(">");
+        self.content(cell);
+        self.push(&format!("</{}>", tag));
+    }
+
+    /// A simple element wrapping the node's content
+    fn wrap(&mut self, tag: &str, node: &Node) {
+        self.push(&format!("<{}>", tag));
+        self.content(node);
+        self.push(&format!("</{}>", tag));
+    }
+
+    fn node(&mut self, node: &Node) {
+        let args = &node.args;
+        match node.kind {
+            Kind::Divider => self.push("<hr>"),
+            Kind::Code => {
+                self.push("<pre");
+                let lang = args.lang.as_deref().map(|l| l.trim()).filter(|l| !l.is_empty());
+                if let Some(lang) = lang {
+                    self.attr("data-code", lang);
+                }
+                self.push(" data-highlighted>");
+                let text = args.text.as_deref().unwrap_or("");
+                //The html parser eats a newline right after <pre>, so it has to be doubled
+                if text.starts_with('\n') {
+                    self.push("\n");
+                }
+                //The js drops the language and the page highlights every block as SmileBASIC, so one in a language
+                //we don't know still is, just without picking a dialect
+                let dialect = lang.filter(|l| crate::highlight::smilebasic_dialect(Some(l)).is_some());
+                self.push(&crate::highlight::highlight_code(text, dialect));
+                self.push("</pre>");
+            },
+            Kind::ICode => {
+                self.push("<code>");
+                self.text(&args.text.as_deref().unwrap_or("").replace(' ', "\u{a0}"));
+                self.push("</code>");
+            },
+            Kind::SimpleLink => {
+                let url = args.url.as_deref().unwrap_or("");
+                self.push("<a");
+                self.attr("class", if args.text.is_some() { "M-link M-link-custom" } else { "M-link" });
+                self.link_target(url);
+                self.push(">");
+                self.text(args.text.as_deref().unwrap_or(url));
+                self.push("</a>");
+            },
+            Kind::Link => {
+                self.push("<a");
+                self.attr("class", "M-link M-link-custom");
+                self.link_target(args.url.as_deref().unwrap_or(""));
+                self.push(">");
+                self.content(node);
+                self.push("</a>");
+            },
+            Kind::Image => {
+                self.push("<img");
+                self.attr("class", "M-image");
+                self.attr("data-shrink", "");
+                if let Some(ref alt) = args.alt {
+                    self.attr("alt", alt);
+                    self.attr("title", alt);
+                }
+                self.attr("tabindex", "0");
+                match (args.width, args.height) {
+                    (width, Some(height)) if height != 0 => {
+                        let width = width.unwrap_or(0);
+                        self.attr("width", &width.to_string());
+                        self.attr("height", &height.to_string());
+                        self.attr("style", &format!("--width:{};--height:{};", width, height));
+                        self.attr("data-state", "size");
+                    },
+                    //The js sets this once the image has loaded; the browser will figure out the size on its own
+                    _ => self.attr("data-state", "loaded")
+                }
+                self.attr("src", &filter_url(args.url.as_deref().unwrap_or(""), self.http_root));
+                self.push(">");
+            },
+            Kind::Audio => {
+                let url = filter_url(args.url.as_deref().unwrap_or(""), self.http_root);
+                let name = match url.rfind('/') {
+                    Some(index) => format!("…/{}", &url[index + 1..]),
+                    None => url.clone()
+                };
+                self.push("<y12-audio");
+                self.attr("data-src", &url);
+                self.push("><a");
+                self.attr("href", &url);
+                self.attr("title", &url);
+                self.push(">🎵️<span>");
+                self.text(&name);
+                self.push("</span></a></y12-audio>");
+            },
+            Kind::Video => {
+                self.push("<y12-video><figure class=\"M-image-wrapper\"><video");
+                self.attr("tabindex", "0");
+                self.attr("preload", "none");
+                self.attr("data-shrink", "video");
+                self.attr("src", &filter_url(args.url.as_deref().unwrap_or(""), self.http_root));
+                //No custom controls without javascript, so use the browser's
+                self.push(" controls></video></figure></y12-video>");
+            },
+            Kind::Youtube => {
+                let url = args.url.as_deref().unwrap_or("");
+                self.push("<youtube-embed");
+                self.attr("data-href", url);
+                self.push("><a target=\"_blank\"");
+                self.attr("href", url);
+                self.push(">");
+                self.text(url);
+                self.push("</a></youtube-embed>");
+            },
+            Kind::Heading => {
+                let tag = format!("h{}", args.level.unwrap_or(1) + 1);
+                let id = args.id.as_deref().filter(|id| !id.is_empty());
+                if let Some(id) = id {
+                    self.push("<a");
+                    self.attr("name", id);
+                    self.attr("class", "M-anchor");
+                    self.push(">");
+                }
+                self.wrap(&tag, node);
+                if id.is_some() {
+                    self.push("</a>");
+                }
+            },
+            Kind::Anchor => {
+                self.push("<a");
+                self.attr("name", args.id.as_deref().unwrap_or(""));
+                self.attr("class", "M-anchor");
+                self.push(">");
+                self.content(node);
+                self.push("</a>");
+            },
+            Kind::Quote => {
+                self.push("<blockquote class=\"M-quote\">");
+                match args.cite {
+                    Some(ref cite) => {
+                        self.push("<cite class=\"M-quote-label\">");
+                        self.text(cite);
+                        self.push("</cite>:<div class=\"M-quote-inner\">");
+                        self.content(node);
+                        self.push("</div>");
+                    },
+                    None => self.content(node)
+                }
+                self.push("</blockquote>");
+            },
+            Kind::Table => {
+                self.push("<div class=\"M-table-outer\"><table><tbody>");
+                self.content(node);
+                self.push("</tbody></table></div>");
+            },
+            Kind::TableRow => {
+                //Rows only ever hold cells, and cells need to know about their row
+                self.push("<tr>");
+                for leaf in node.content.iter().flatten() {
+                    if let Leaf::Node(cell) = leaf {
+                        if cell.kind == Kind::TableCell {
+                            self.cell(cell, args);
+                        }
+                    }
+                }
+                self.push("</tr>");
+            },
+            Kind::TableCell => self.cell(node, &Args::default()),
+            Kind::List => self.wrap(if args.style.is_some() { "ol" } else { "ul" }, node),
+            Kind::ListItem => self.wrap("li", node),
+            Kind::Align => {
+                self.push("<div");
+                if let Some(align) = args.align.as_deref().filter(|a| valid_align(a)) {
+                    self.attr("style", &format!("text-align:{};", align));
+                }
+                self.push(">");
+                self.content(node);
+                self.push("</div>");
+            },
+            Kind::Subscript => self.wrap("sub", node),
+            Kind::Superscript => self.wrap("sup", node),
+            Kind::Ruby => {
+                self.push("<ruby><span>");
+                self.content(node);
+                self.push("</span><rt>");
+                self.text(args.text.as_deref().unwrap_or(""));
+                self.push("</rt></ruby>");
+            },
+            Kind::Spoiler => {
+                self.push("<details class=\"M-spoiler\"><summary class=\"M-spoiler-label\">");
+                self.text(args.label.as_deref().unwrap_or(""));
+                self.push("</summary><div class=\"M-spoiler-inner\">");
+                self.content(node);
+                self.push("</div></details>");
+            },
+            Kind::BackgroundColor => {
+                self.push("<span class=\"M-background\"");
+                if let Some(color) = args.color.as_deref().filter(|c| !c.is_empty()) {
+                    self.attr("data-bgcolor", color);
+                }
+                self.push(">");
+                self.content(node);
+                self.push("</span>");
+            },
+            Kind::Invalid => {
+                self.push("<span class=\"M-invalid\"");
+                if let Some(ref reason) = args.reason {
+                    self.attr("title", reason);
+                }
+                self.push(">");
+                self.text(args.text.as_deref().unwrap_or(""));
+                self.content(node);
+                self.push("</span>");
+            },
+            Kind::Italic => self.wrap("i", node),
+            Kind::Bold => self.wrap("b", node),
+            Kind::Strikethrough => self.wrap("s", node),
+            Kind::Underline => self.wrap("u", node),
+            Kind::Key => self.wrap("kbd", node),
+            //Never in a finished tree, but don't lose the text if it somehow is
+            Kind::Root | Kind::Style | Kind::NullEnv | Kind::TableDivider => self.content(node)
+        }
+    }
+
+    fn fill(&mut self, leaves: &[Leaf]) {
+        for leaf in leaves {
+            match leaf {
+                Leaf::Text(text) => self.text(text),
+                Leaf::Node(node) => self.node(node)
+            }
+        }
+    }
+}
+
+/// Render the tree's contents to html. The caller provides the wrapper (it should have the "Markup" class)
+pub fn render(tree: &Node, http_root: &str) -> String {
+    let mut renderer = Renderer { http_root, output: String::new() };
+    renderer.content(tree);
+    renderer.output
+}
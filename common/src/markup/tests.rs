@@ -0,0 +1,217 @@
+//! Golden tests against the js: every <name>.<markup> in fixtures/ is rendered here and compared with the
+//! <name>.<markup>.html beside it, which is what static/markup made of the same text (fixtures/generate.js
+//! makes those; rerun it whenever the js changes). The two aren't compared byte for byte, only as the
+//! elements, attributes and text a browser would end up with, minus what the server does on purpose
+//! because it has no javascript (see render.rs).
+
+use std::path::Path;
+
+use html_escape::decode_html_entities;
+
+use super::*;
+
+const VOID: [&str; 6] = ["br", "hr", "img", "input", "source", "wbr"];
+/// Attributes only one side ever has: the server marks code it already highlighted and images it can't
+/// wait on, and uses the browser's video controls instead of the js ones
+const IGNORED_ATTRIBUTES: [&str; 4] = ["data-highlighted", "data-code", "data-state", "controls"];
+const IGNORED_CLASS: &str = "M-media-controls";
+
+/// Read one tag from the start of `html` (just past the <), returning the name, attributes and what's left
+fn read_tag(html: &str) -> (String, Vec<(String, String)>, &str) {
+    let end = html.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/').unwrap_or(html.len());
+    let name = html[..end].to_ascii_lowercase();
+    let mut rest = &html[end..];
+    let mut attributes = Vec::new();
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if rest.is_empty() || rest.starts_with('>') {
+            return (name, attributes, rest.get(1..).unwrap_or(""));
+        }
+        let end = rest.find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '>').unwrap_or(rest.len());
+        let attribute = rest[..end].to_ascii_lowercase();
+        rest = rest[end..].trim_start();
+        let mut value = "";
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let close = after[1..].find(quote).map(|i| i + 1).unwrap_or(after.len());
+                    (&after[1..close], after.get(close + 1..).unwrap_or(""))
+                },
+                _ => {
+                    let close = after.find(|c: char| c.is_ascii_whitespace() || c == '>').unwrap_or(after.len());
+                    (&after[..close], &after[close..])
+                }
+            };
+            value = raw;
+            rest = remaining;
+        }
+        attributes.push((attribute, decode_html_entities(value).into_owned()));
+    }
+}
+
+/// One line per element and run of text, with attributes in a fixed order and class/style lists tidied,
+/// so html that builds the same page comes out the same. Code blocks are only their text: the server
+/// highlights for the block's language, while the js drops it and the page highlights everything as SmileBASIC
+fn normalize(html: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut skipping = 0;
+    let mut in_code = false;
+    let mut rest = html;
+
+    let flush = |text: &mut String, lines: &mut Vec<String>| {
+        if !text.is_empty() {
+            lines.push(format!("{:?}", text));
+            text.clear();
+        }
+    };
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            if skipping == 0 { text.push_str(&decode_html_entities(rest)); }
+            break;
+        };
+        if skipping == 0 { text.push_str(&decode_html_entities(&rest[..start])); }
+        rest = &rest[start + 1..];
+
+        if let Some(closing) = rest.strip_prefix('/') {
+            let (name, _, remaining) = read_tag(closing);
+            rest = remaining;
+            if skipping > 0 {
+                skipping -= 1;
+                continue;
+            }
+            if in_code && name != "pre" {
+                continue;
+            }
+            in_code = false;
+            flush(&mut text, &mut lines);
+            lines.push(format!("</{}>", name));
+            continue;
+        }
+
+        let (name, mut attributes, remaining) = read_tag(rest);
+        rest = remaining;
+        let void = VOID.contains(&name.as_str());
+
+        if in_code {
+            continue;
+        }
+        if skipping > 0 || attributes.iter().any(|(a, v)| a == "class" && v.split_whitespace().any(|c| c == IGNORED_CLASS)) {
+            if !void { skipping += 1; }
+            continue;
+        }
+
+        attributes.retain(|(a, _)| !IGNORED_ATTRIBUTES.contains(&a.as_str()));
+        for (attribute, value) in attributes.iter_mut() {
+            if attribute == "class" {
+                let mut classes: Vec<&str> = value.split_whitespace().collect();
+                classes.sort();
+                *value = classes.join(" ");
+            }
+            else if attribute == "style" {
+                *value = value.split(';')
+                    .filter(|s| !s.trim().is_empty())
+                    .map(|s| s.split(':').map(|p| p.trim()).collect::<Vec<_>>().join(":"))
+                    .collect::<Vec<_>>().join(";");
+            }
+        }
+        attributes.sort();
+
+        flush(&mut text, &mut lines);
+        let attributes: String = attributes.iter().map(|(a, v)| format!(" {}={:?}", a, v)).collect();
+        lines.push(format!("<{}{}>", name, attributes));
+
+        //The html parser drops a newline right after <pre>
+        if name == "pre" {
+            rest = rest.strip_prefix('\n').unwrap_or(rest);
+            in_code = true;
+        }
+    }
+
+    flush(&mut text, &mut lines);
+    lines.join("\n")
+}
+
+#[test]
+fn matches_js_renderer() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/markup/fixtures");
+    let mut checked = 0;
+    let mut failed = Vec::new();
+
+    let mut files: Vec<_> = std::fs::read_dir(&fixtures).unwrap().map(|f| f.unwrap().path()).collect();
+    files.sort();
+
+    for path in files {
+        let lang = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if ![MARKUP12Y, MARKUP12Y2, MARKUPPLAINTEXT].contains(&lang) {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let expected = std::fs::read_to_string(fixtures.join(format!("{}.html", name)))
+            .unwrap_or_else(|_| panic!("{} has no .html, run fixtures/generate.js", name));
+
+        let rendered = convert_lang(&text, lang, "").unwrap_or_else(|e| panic!("{} failed to parse: {}", name, e));
+        let (expected, rendered) = (normalize(&expected), normalize(&rendered));
+        if expected != rendered {
+            let line = expected.lines().zip(rendered.lines()).position(|(e, r)| e != r)
+                .unwrap_or_else(|| expected.lines().count().min(rendered.lines().count()));
+            failed.push(format!("{}: first difference at item {}\n  js:   {}\n  rust: {}", name, line,
+                expected.lines().nth(line).unwrap_or("(end)"), rendered.lines().nth(line).unwrap_or("(end)")));
+        }
+        checked += 1;
+    }
+
+    assert!(checked > 0, "no fixtures found in {}", fixtures.display());
+    assert!(failed.is_empty(), "{} of {} fixtures don't match the js:\n{}", failed.len(), checked, failed.join("\n"));
+}
+
+/// Run it with the stack a tokio worker gets, since that's where pages are rendered
+fn on_worker_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new().stack_size(2 * 1024 * 1024).spawn(f).unwrap().join().unwrap()
+}
+
+#[test]
+fn deep_nesting_is_refused() {
+    let quotes = on_worker_stack(|| convert_lang(&"\\quote{".repeat(4000), MARKUP12Y2, "").is_err());
+    assert!(quotes, "4000 nested quotes should be refused");
+
+    let mixed = on_worker_stack(|| convert_lang(&"**>{\\spoiler{|[*] ".repeat(1000), MARKUP12Y2, "").is_err());
+    assert!(mixed, "deeply nested mixed blocks should be refused");
+
+    let lists = on_worker_stack(|| {
+        let text: String = (0..300).map(|i| format!("{}- item\n", " ".repeat(i))).collect();
+        convert_lang(&text, MARKUP12Y2, "").is_err()
+    });
+    assert!(lists, "300 levels of nested lists should be refused");
+
+    let legacy = on_worker_stack(|| convert_lang(&"{#sup ".repeat(4000), MARKUP12Y, "").is_err());
+    assert!(legacy, "12y has always refused deep nesting");
+}
+
+#[test]
+fn reasonable_nesting_still_renders() {
+    let rendered = on_worker_stack(|| convert_lang(&format!("{}text{}", "\\quote{".repeat(50), "}".repeat(50)), MARKUP12Y2, ""));
+    let rendered = rendered.expect("50 nested quotes are fine");
+    assert_eq!(rendered.matches("<blockquote").count(), 50);
+
+    let lists = on_worker_stack(|| {
+        let text: String = (0..20).map(|i| format!("{}- item\n", " ".repeat(i))).collect();
+        convert_lang(&text, MARKUP12Y2, "")
+    });
+    assert_eq!(lists.expect("20 levels of lists are fine").matches("<ul>").count(), 20);
+}
+
+#[test]
+fn code_in_other_languages_is_still_smilebasic() {
+    //Like the page did for the js renderer, which never kept the language
+    let rendered = convert_lang("```js\nPRINT 1\n```", MARKUP12Y2, "").unwrap();
+    assert!(rendered.contains(r#"data-code="js""#), "{}", rendered);
+    assert!(rendered.contains(r#"<span class="keyword">PRINT</span>"#), "{}", rendered);
+
+    let rendered = convert_lang("```sb3\nSTOP\n```", MARKUP12Y2, "").unwrap();
+    assert!(rendered.contains(r#"<span class="keyword">STOP</span>"#), "{}", rendered);
+}
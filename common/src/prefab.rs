@@ -1,6 +1,13 @@
 
 use crate::response::*;
 use crate::constants::*;
+use crate::search::SavedSearch;
+use crate::collections::Collection;
+use crate::view::*;
+use crate::LinkConfig;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
 use contentapi::*;
 use contentapi::endpoints::*;
 use serde_json::Value;
@@ -39,6 +46,78 @@ pub async fn get_all_categories(context: &mut ApiContext, limit: Option<Vec<i64>
     conversion::cast_result_required::<Content>(&result, &RequestType::content.to_string()).map_err(|e| e.into())
 }
 
+/// How many pages are tagged with each of the given categories (by id)
+pub async fn get_category_counts(context: &mut ApiContext, categories: &[Category]) -> Result<HashMap<i64, i64>, Error>
+{
+    let mut result = HashMap::new();
+    if categories.is_empty() {
+        return Ok(result);
+    }
+
+    let mut request = FullRequest::new();
+    for category in categories {
+        let key = format!("category{}", category.id);
+        request.values.insert(key.clone(), vec![format!("{}{}", CATEGORYPREFIX, category.id)].into());
+        let mut count_request = build_request!(
+            RequestType::content,
+            String::from("specialCount,id,values"),
+            format!("!notdeleted() and !valuekeyin(@{})", key)
+        );
+        count_request.name = Some(key);
+        request.requests.push(count_request);
+    }
+
+    let response = context.post_request_profiled_opt(&request, "category_counts").await?;
+    for category in categories {
+        if let Some(count) = cast_result_safe::<SpecialCount>(&response, &format!("category{}", category.id))?.pop() {
+            result.insert(category.id, count.specialCount as i64);
+        }
+    }
+    Ok(result)
+}
+
+/// Move every page tagged with the "from" category over to the "to" category (or just untag them if "to" is 0).
+/// Returns how many pages were changed
+pub async fn retag_pages(context: &mut ApiContext, from: i64, to: i64) -> Result<usize, Error>
+{
+    const RETAGBATCH: i32 = 100;
+    let from_key = format!("{}{}", CATEGORYPREFIX, from);
+    let mut changed = 0;
+
+    let mut after = 0;
+
+    loop {
+        let mut request = FullRequest::new();
+        add_value!(request, "from", vec![from_key.clone()]);
+        add_value!(request, "after", after);
+        request.requests.push(build_request!(
+            RequestType::content,
+            String::from("*"),
+            String::from("!notdeleted() and !valuekeyin(@from) and id > @after"),
+            String::from("id"),
+            RETAGBATCH
+        ));
+
+        let result = context.post_request_profiled_opt(&request, "retag").await?;
+        let pages = cast_result_required::<Content>(&result, &RequestType::content.to_string())?;
+
+        if pages.is_empty() {
+            return Ok(changed);
+        }
+
+        for mut page in pages {
+            after = page.id.unwrap_or(after);
+            let values = page.values.get_or_insert_with(HashMap::new);
+            values.remove(&from_key);
+            if to != 0 {
+                values.insert(format!("{}{}", CATEGORYPREFIX, to), true.into());
+            }
+            context.post_content(&page, Some(format!("Retag category {} to {}", from, to))).await?;
+            changed += 1;
+        }
+    }
+}
+
 pub async fn get_content_vote(context: &ApiContext, content_id: i64) -> Result<Option<ContentEngagement>, ApiError>
 {
     let mut request = FullRequest::new();
@@ -60,6 +139,384 @@ pub async fn get_content_vote(context: &ApiContext, content_id: i64) -> Result<O
 }
 
 
+/// Find all pages which use the given (normalized) download key, including those where the key has since
+/// been marked as removed (check the values yourself). Older keys weren't always stored normalized, so the
+/// search is loose (like ignores case, and anything can sit between the characters) and then each stored
+/// key is normalized and compared here
+pub async fn get_pages_with_key(context: &mut ApiContext, key: &str) -> Result<Vec<Content>, ApiError>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "type", ContentType::PAGE);
+    add_value!(request, "dlkeykey", SBSValue::DOWNLOADKEY);
+    add_value!(request, "dlkey", key_search_pattern(key));
+
+    request.requests.push(build_request!(
+        RequestType::content,
+        String::from("id,hash,name,literalType,contentType,values"),
+        String::from("contentType = @type and !notdeleted() and !valuelike(@dlkeykey, @dlkey)")
+    ));
+
+    let result = context.post_request_profiled_opt(&request, "pages_with_key").await?;
+    let mut pages = conversion::cast_result_required::<Content>(&result, &RequestType::content.to_string())?;
+    pages.retain(|p| p.get_value_string(SBSValue::DOWNLOADKEY).map(|k| normalize_key(&k)).as_deref() == Some(key));
+    Ok(pages)
+}
+
+
+pub const RELATEDFIELDS: &str = "id,hash,parentId,contentType,literalType,values,keywords,name,description,createUserId,createDate,lastRevisionId,popScore1";
+pub const RELATEDCANDIDATES: i32 = 100;
+pub const RELATEDCOUNT: usize = 6;
+
+/// Related pages per (page id, revision), so editing a page's tags refreshes it. Everyone gets the same
+/// entry, so it must be filled with an anonymous api context
+pub type RelatedCache = crate::cache::TimedCache<(i64, i64), RelatedPages>;
+
+/// Pages related to some other page, along with the users needed to render them as cards
+#[derive(Default, Debug, Clone)]
+pub struct RelatedPages {
+    pub pages: Vec<Content>,
+    pub users: HashMap<i64, User>
+}
+
+/// Find up to 'count' pages related to the given one (must have keywords and values), best first. Only pages
+/// sharing a keyword, category, or author are even considered; the rest of the ranking is done here
+pub async fn get_related_pages(context: &mut ApiContext, page: &Content, count: usize) -> Result<RelatedPages, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "type", ContentType::PAGE);
+    add_value!(request, "relatedtypes", vec![SBSPageType::PROGRAM, SBSPageType::RESOURCE]);
+    add_value!(request, "uid", page.createUserId.unwrap_or(0));
+
+    let mut related_query = vec![String::from("createUserId = @uid")];
+
+    if let Some(keywords) = page.keywords.as_ref().filter(|k| !k.is_empty()) {
+        add_value!(request, "keywords", keywords.clone());
+        related_query.push(String::from("!keywordin(@keywords)"));
+    }
+
+    let categories = get_tagged_categories(page);
+    if !categories.is_empty() {
+        add_value!(request, "categories", categories.iter().map(|c| format!("{}{}", CATEGORYPREFIX, c)).collect::<Vec<String>>());
+        related_query.push(String::from("!valuekeyin(@categories)"));
+    }
+
+    //Popular pages first, so ties in the ranking go to them (sorting below is stable)
+    let mut candidate_request = build_request!(
+        RequestType::content,
+        String::from(RELATEDFIELDS),
+        format!("contentType = @type and !notdeleted() and literalType in @relatedtypes and ({})", related_query.join(" or ")),
+        String::from(POPSCORE1SORT),
+        RELATEDCANDIDATES
+    );
+    candidate_request.name = Some(String::from("related"));
+    request.requests.push(candidate_request);
+
+    request.requests.push(build_request!(
+        RequestType::user,
+        String::from("*"),
+        String::from("id in @related.createUserId")
+    ));
+
+    let result = context.post_request_profiled_opt(&request, "related_pages").await?;
+    let candidates = cast_result_required::<Content>(&result, "related")?;
+    let users = cast_result_required::<User>(&result, &RequestType::user.to_string())?;
+
+    let mut scored = candidates.into_iter()
+        .filter(|c| c.id != page.id)
+        .map(|c| (get_related_score(page, &c), c))
+        .filter(|(score, _)| *score > 0)
+        .collect::<Vec<(i32, Content)>>();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.truncate(count);
+
+    Ok(RelatedPages {
+        pages: scored.into_iter().map(|(_, c)| c).collect(),
+        users: map_users(users)
+    })
+}
+
+
+/// All of the given user's saved searches, oldest first
+pub async fn get_saved_searches(context: &mut ApiContext, user_id: i64) -> Result<Vec<SavedSearch>, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "uid", user_id);
+    add_value!(request, "savedsearch", format!("{}%", SAVEDSEARCHPREFIX));
+
+    request.requests.push(build_request!(
+        RequestType::uservariable,
+        String::from("id,userId,key,value,createDate,editDate"),
+        String::from("userId = @uid and key like @savedsearch"),
+        String::from("id")
+    ));
+
+    let result = context.post_request_profiled_opt(&request, "saved_searches").await?;
+    let variables = cast_result_required::<UserVariable>(&result, &RequestType::uservariable.to_string())?;
+    variables.into_iter().map(SavedSearch::from_variable).collect()
+}
+
+/// Write the saved search to the user variable for its key (overwriting whatever was there)
+pub async fn post_saved_search(context: &ApiContext, saved: &SavedSearch) -> Result<(), Error>
+{
+    context.post_uservariable(&saved.key, &serde_json::to_string(saved)?).await?;
+    Ok(())
+}
+
+
+// ----------------------
+//    USERPAGES
+// ----------------------
+
+/// Get the current userpage for the given user (which has all fields), if they have one
+pub async fn get_userpage(context: &mut ApiContext, user_id: i64) -> Result<Option<Content>, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "uid", user_id);
+    let mut user_request = build_request!(
+        RequestType::content, 
+        String::from("*"),
+        String::from("!userpage(@uid)")
+    ); 
+    user_request.name = Some(String::from("userpage"));
+    request.requests.push(user_request);
+
+    let result = context.post_request_profiled_opt(&request, "userpage").await?;
+    Ok(cast_result_safe::<Content>(&result, "userpage")?.pop())
+}
+
+/// The page all userpages go under (public collections go there too)
+async fn get_userpages_parent(context: &ApiContext) -> Result<Content, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "type", "userpages"); //Need the parent
+
+    let mut parent_request = build_request!(
+        RequestType::content, 
+        String::from("id,parentId,literalType"), 
+        String::from("literalType = @type")
+    ); 
+    parent_request.name = Some(String::from("parent"));
+    request.requests.push(parent_request);
+
+    let result = context.post_request(&request).await?;
+    cast_result_required::<Content>(&result, "parent")?.pop()
+        .ok_or_else(|| Error::Other(String::from("Couldn't find the userpage parent! This is a programming error!")))
+}
+
+/// Build a brand new userpage for the given user which hasn't been written yet; fill in the text yourself
+pub async fn new_userpage(context: &ApiContext, user: &User) -> Result<Content, Error>
+{
+    let parent = get_userpages_parent(context).await?;
+
+    //note: the hash it autogenerated from the name (hopefully)
+    Ok(Content {
+        id: Some(0),
+        text: Some(String::new()),
+        parentId: parent.id,
+        contentType: Some(ContentType::USERPAGE),
+        name: Some(format!("{}'s userpage", user.username)),
+        permissions: Some(make_permissions! {
+            "0": "CR" //Create so people can post on your "wall" (idk if that'll ever happen)
+        }),
+        values: Some(make_values! {
+            "markup": "bbcode"
+        }),
+        ..Default::default()
+    })
+}
+
+
+// ----------------------
+//     COLLECTIONS
+// ----------------------
+
+/// All of the given user's collections (private and public), oldest first
+pub async fn get_collections(context: &mut ApiContext, user_id: i64) -> Result<Vec<Collection>, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "uid", user_id);
+    add_value!(request, "collection", format!("{}%", COLLECTIONPREFIX));
+
+    request.requests.push(build_request!(
+        RequestType::uservariable,
+        String::from("id,userId,key,value,createDate,editDate"),
+        String::from("userId = @uid and key like @collection"),
+        String::from("id")
+    ));
+
+    let result = context.post_request_profiled_opt(&request, "collections").await?;
+    let variables = cast_result_required::<UserVariable>(&result, &RequestType::uservariable.to_string())?;
+    variables.into_iter().map(Collection::from_variable).collect()
+}
+
+/// Write the collection to the user variable for its key (overwriting whatever was there)
+pub async fn post_collection(context: &ApiContext, collection: &Collection) -> Result<(), Error>
+{
+    context.post_uservariable(&collection.key, &serde_json::to_string(collection)?).await?;
+    Ok(())
+}
+
+/// The request for the pages the given user's public collections are published on. These are readable by
+/// anyone, unlike the user variables
+pub fn published_collections_request(user_id: i64) -> FullRequest
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "uid", user_id);
+    add_value!(request, "type", ContentType::PAGE);
+    add_value!(request, "littype", SBSPageType::COLLECTION);
+
+    request.requests.push(build_request!(
+        RequestType::content,
+        String::from("*"),
+        String::from("contentType = @type and literalType = @littype and createUserId = @uid and !notdeleted()"),
+        String::from("id")
+    ));
+    request
+}
+
+async fn get_published_collections(context: &mut ApiContext, user_id: i64) -> Result<Vec<Content>, Error>
+{
+    let request = published_collections_request(user_id);
+    let result = context.post_request_profiled_opt(&request, "published_collections").await?;
+    Ok(cast_result_required::<Content>(&result, &RequestType::content.to_string())?)
+}
+
+/// Only the given user's public collections, sorted by name; what anyone else gets to see
+pub async fn get_public_collections(context: &mut ApiContext, user_id: i64) -> Result<Vec<Collection>, Error>
+{
+    let mut collections: Vec<Collection> = get_published_collections(context, user_id).await?.iter()
+        .filter_map(Collection::from_published).collect();
+    collections.sort_by_key(|c| c.name.to_ascii_lowercase());
+    Ok(collections)
+}
+
+/// Make the published copy of the collection match it: written if it's public (and only if something changed),
+/// deleted if it isn't. Call this after any change to a public collection, or one that just stopped being public
+pub async fn publish_collection(context: &mut ApiContext, user_id: i64, collection: &Collection) -> Result<(), Error>
+{
+    let mut published = get_published_collections(context, user_id).await?.into_iter()
+        .filter(|c| Collection::from_published(c).is_some_and(|p| p.key == collection.key));
+
+    if !collection.public {
+        for page in published {
+            if let Some(id) = page.id {
+                context.post_delete_content(id).await?;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut page = match published.next() {
+        Some(page) => page,
+        None => Content { parentId: get_userpages_parent(context).await?.id, ..Default::default() }
+    };
+    if collection.publish_into(&mut page)? {
+        context.post_content(&page, None).await?;
+    }
+    Ok(())
+}
+
+/// The "favorited" engagement on a page means the user has it in at least one collection, so
+/// set or clear it to match the given collections
+pub async fn sync_favorite(context: &ApiContext, content_id: i64, collections: &[Collection]) -> Result<(), Error>
+{
+    let favorited = collections.iter().any(|c| c.pages.contains(&content_id));
+    context.post_set_content_engagement(content_id, FAVORITETYPE, if favorited { FAVORITED } else { "" }).await?;
+    Ok(())
+}
+
+
+// ----------------------
+//       SITEMAP
+// ----------------------
+
+pub const SITEMAPTYPES: &[&str] = &[
+    SBSPageType::PROGRAM,
+    SBSPageType::RESOURCE,
+    SBSPageType::DOCUMENTATION,
+    SBSPageType::FORUMTHREAD
+];
+const SITEMAPBATCH: i32 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct SitemapUrl {
+    pub loc: String,    //Absolute
+    pub lastmod: Option<DateTime<Utc>>
+}
+
+/// The last good list of sitemap urls. Building it means going through everything on the site, so it's
+/// rebuilt in the background (see refresh_sitemap) and requests only ever read whatever's there
+#[derive(Default)]
+pub struct SitemapCache(RwLock<Option<Arc<Vec<SitemapUrl>>>>);
+
+impl SitemapCache {
+    /// Nothing until the first build is done
+    pub fn get(&self) -> Option<Arc<Vec<SitemapUrl>>> {
+        //Nothing can be left half written in here, so a panic elsewhere doesn't matter
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Rebuild the sitemap into the cache. If it fails, the old copy stays. Use an anonymous context!
+pub async fn refresh_sitemap(context: &mut ApiContext, links: &LinkConfig, cache: &SitemapCache) -> Result<usize, Error>
+{
+    let urls = get_sitemap_urls(context, links).await?;
+    let count = urls.len();
+    *cache.0.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(urls));
+    Ok(count)
+}
+
+/// Every url that should be in the sitemap. Use an anonymous context, so only public things are found!
+pub async fn get_sitemap_urls(context: &mut ApiContext, links: &LinkConfig) -> Result<Vec<SitemapUrl>, Error>
+{
+    let mut result = Vec::new();
+
+    //Go through everything in batches by id, there's no way we get it all in one request
+    let mut after = 0;
+    loop {
+        let mut request = FullRequest::new();
+        add_value!(request, "types", SITEMAPTYPES);
+        add_value!(request, "after", after);
+        request.requests.push(build_request!(
+            RequestType::content,
+            String::from("id,hash,literalType,createDate,lastActionDate"),
+            String::from("!notdeleted() and literalType in @types and id > @after"),
+            String::from("id"),
+            SITEMAPBATCH
+        ));
+        let response = context.post_request_profiled_opt(&request, "sitemap_content").await?;
+        let content = cast_result_required::<Content>(&response, &RequestType::content.to_string())?;
+        if content.is_empty() { break; }
+        after = content.iter().filter_map(|c| c.id).max().unwrap_or(after);
+        result.extend(content.iter().map(|c| SitemapUrl {
+            loc: links.absolute(&links.forum_thread(c)),
+            lastmod: c.lastActionDate.or(c.createDate)
+        }));
+    }
+
+    let mut after = 0;
+    loop {
+        let mut request = FullRequest::new();
+        add_value!(request, "usertype", UserType::USER);
+        add_value!(request, "after", after);
+        request.requests.push(build_request!(
+            RequestType::user,
+            String::from("*"),
+            String::from("type = @usertype and id > @after"),
+            String::from("id"),
+            SITEMAPBATCH
+        ));
+        let response = context.post_request_profiled_opt(&request, "sitemap_users").await?;
+        let users = cast_result_required::<User>(&response, &RequestType::user.to_string())?;
+        if users.is_empty() { break; }
+        after = users.iter().map(|u| u.id).max().unwrap_or(after);
+        result.extend(users.iter().map(|u| SitemapUrl { loc: links.absolute(&links.user(u)), lastmod: None }));
+    }
+
+    Ok(result)
+}
+
+
 // ---------------------------
 //   SPECIAL SYSTEM CONTENT
 // ---------------------------
@@ -150,6 +607,54 @@ pub async fn get_fullpage_by_id(context: &mut ApiContext, id: i64) -> Result<Ful
 }
 
 
+// --------------------
+//       CARDS
+// --------------------
+
+/// The kinds of pages that can be shown as a standalone card (embeds, oembed)
+pub const CARDTYPES: &[&str] = &[
+    SBSPageType::PROGRAM,
+    SBSPageType::RESOURCE,
+    SBSPageType::FORUMTHREAD
+];
+
+pub struct CardPage {
+    pub page: Content,
+    pub users: HashMap<i64, User>
+}
+
+/// Get everything needed to show a page card for the given hash, which is just the page and its author
+pub async fn get_card_page(context: &mut ApiContext, hash: &str) -> Result<CardPage, Error>
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "hash", hash);
+    add_value!(request, "types", CARDTYPES);
+
+    let mut page_request = build_request!(
+        RequestType::content,
+        String::from("*"),
+        String::from("!notdeleted() and hash = @hash and literalType in @types")
+    );
+    page_request.limit = 1;
+    page_request.name = Some(String::from("card"));
+    request.requests.push(page_request);
+
+    let user_request = build_request!(
+        RequestType::user,
+        String::from("*"),
+        String::from("id in @card.createUserId")
+    );
+    request.requests.push(user_request);
+
+    let result = context.post_request_profiled_opt(&request, "card").await?;
+    let page = cast_result_required::<Content>(&result, "card")?.pop()
+        .ok_or_else(|| Error::NotFound(format!("Could not find page {}", hash)))?;
+    let users = map_users(cast_result_required::<User>(&result, &RequestType::user.to_string())?);
+
+    Ok(CardPage { page, users })
+}
+
+
 // --------------------
 //    DOCUMENTATION
 // --------------------
@@ -0,0 +1,320 @@
+//! Token bucket rate limiting for the things that are expensive or annoying when hammered: logging in,
+//! registering, sending emails, posting and voting. Every client gets a bucket per group, holding up to
+//! `burst` requests and refilling at `per_minute`. A client is whatever key the caller gives (an ip, a
+//! user id); the frontend checks both so neither switching accounts nor switching networks gets around it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+
+/// How many requests a client can make in one group, as read from settings
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RateLimit {
+    pub burst: f64,
+    pub per_minute: f64
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Requests turned away since it started getting throttled this time around
+    rejected: u64,
+    last_rejected: Option<Instant>
+}
+
+/// A client that was turned away recently, for the admin page
+#[derive(Debug, Clone)]
+pub struct ThrottledClient {
+    pub group: String,
+    pub client: String,
+    pub rejected: u64,
+    pub last_rejected: Duration,
+    /// How long until they can make a request again (zero if they already can)
+    pub retry_after: Duration
+}
+
+/// How long someone who was turned away still shows up as throttled, even once they can make requests again
+const THROTTLE_MEMORY: Duration = Duration::from_secs(600);
+/// Full buckets are just the default, so they're thrown out once there's this many...
+const PRUNE_SIZE: usize = 10000;
+/// ...but only this often, since it means going through all of them
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+/// No matter what, there's never more buckets than this; past it, the ones untouched the longest go first
+const MAX_BUCKETS: usize = 100000;
+
+struct Buckets {
+    buckets: HashMap<(String, String), Bucket>,
+    last_prune: Instant
+}
+
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<Buckets>,
+    max_buckets: usize
+}
+
+impl RateLimiter {
+    /// Groups with no burst or no refill aren't limited at all
+    pub fn new(limits: &HashMap<String, RateLimit>) -> Self {
+        let limits = limits.iter()
+            .filter(|(_, limit)| limit.burst >= 1.0 && limit.per_minute > 0.0)
+            .map(|(group, limit)| (group.clone(), limit.clone()))
+            .collect();
+        let buckets = Buckets { buckets: HashMap::new(), last_prune: Instant::now() };
+        Self { limits, buckets: Mutex::new(buckets), max_buckets: MAX_BUCKETS }
+    }
+
+    fn refill(bucket: &mut Bucket, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_minute / 60.0).min(limit.burst);
+        bucket.updated = now;
+    }
+
+    fn wait_for_token(bucket: &Bucket, limit: &RateLimit) -> Duration {
+        Duration::from_secs_f64(((1.0 - bucket.tokens).max(0.0) * 60.0 / limit.per_minute).ceil())
+    }
+
+    /// Throw out the buckets which are back to full and haven't turned anyone away in a while
+    fn prune(&self, buckets: &mut HashMap<(String, String), Bucket>, now: Instant) {
+        buckets.retain(|(group, _), bucket| {
+            match self.limits.get(group) {
+                Some(limit) => {
+                    Self::refill(bucket, limit, now);
+                    bucket.tokens < limit.burst || bucket.last_rejected.map(|l| now - l < THROTTLE_MEMORY).unwrap_or(false)
+                },
+                None => false
+            }
+        });
+    }
+
+    /// Make room for `needed` new buckets under the cap by dropping the ones untouched the longest. A tenth of
+    /// the cap goes at once, so a flood of new clients doesn't mean going through everything for each one
+    fn evict_oldest(buckets: &mut HashMap<(String, String), Bucket>, max: usize, needed: usize) {
+        if buckets.len() + needed <= max {
+            return;
+        }
+        let count = (buckets.len() + needed - max).max(max / 10).min(buckets.len());
+        if count == 0 {
+            return;
+        }
+        let mut ages: Vec<Instant> = buckets.values().map(|b| b.updated).collect();
+        let (_, cutoff, _) = ages.select_nth_unstable(count - 1);
+        let cutoff = *cutoff;
+        let mut removed = 0;
+        buckets.retain(|_, bucket| {
+            if removed < count && bucket.updated <= cutoff {
+                removed += 1;
+                false
+            }
+            else {
+                true
+            }
+        });
+    }
+
+    /// Take one request out of each client's bucket for the group. If any of them is empty, nothing is taken
+    /// and you get back how long to wait. Groups without a limit always pass
+    pub fn check(&self, group: &str, clients: &[String]) -> Result<(), Duration> {
+        self.check_at(group, clients, Instant::now())
+    }
+
+    fn check_at(&self, group: &str, clients: &[String], now: Instant) -> Result<(), Duration> {
+        let limit = match self.limits.get(group) {
+            Some(limit) => limit,
+            None => return Ok(())
+        };
+
+        let mut state = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *state;
+
+        if state.buckets.len() > PRUNE_SIZE && now.saturating_duration_since(state.last_prune) >= PRUNE_INTERVAL {
+            self.prune(&mut state.buckets, now);
+            state.last_prune = now;
+        }
+
+        let buckets = &mut state.buckets;
+        let new_clients = clients.iter().filter(|c| !buckets.contains_key(&(group.to_string(), (*c).clone()))).count();
+        if new_clients > 0 {
+            Self::evict_oldest(buckets, self.max_buckets, new_clients);
+        }
+
+        let mut wait = Duration::ZERO;
+        for client in clients {
+            let bucket = buckets.entry((group.to_string(), client.clone()))
+                .or_insert_with(|| Bucket { tokens: limit.burst, updated: now, rejected: 0, last_rejected: None });
+            Self::refill(bucket, limit, now);
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Self::wait_for_token(bucket, limit));
+            }
+        }
+
+        for client in clients {
+            if let Some(bucket) = buckets.get_mut(&(group.to_string(), client.clone())) {
+                if wait.is_zero() {
+                    bucket.tokens -= 1.0;
+                }
+                //Only the clients that actually ran out count as throttled
+                else if bucket.tokens < 1.0 {
+                    if !bucket.last_rejected.map(|l| now - l < THROTTLE_MEMORY).unwrap_or(false) {
+                        bucket.rejected = 0;
+                    }
+                    bucket.rejected += 1;
+                    bucket.last_rejected = Some(now);
+                }
+            }
+        }
+
+        if wait.is_zero() { Ok(()) } else { Err(wait) }
+    }
+
+    /// Everyone who was turned away in the last little while, most recent first
+    pub fn throttled(&self) -> Vec<ThrottledClient> {
+        let now = Instant::now();
+        let mut state = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let mut result = Vec::new();
+
+        for ((group, client), bucket) in state.buckets.iter_mut() {
+            let limit = match self.limits.get(group) {
+                Some(limit) => limit,
+                None => continue
+            };
+            if let Some(last_rejected) = bucket.last_rejected {
+                if now - last_rejected < THROTTLE_MEMORY {
+                    Self::refill(bucket, limit, now);
+                    result.push(ThrottledClient {
+                        group: group.clone(),
+                        client: client.clone(),
+                        rejected: bucket.rejected,
+                        last_rejected: now - last_rejected,
+                        retry_after: Self::wait_for_token(bucket, limit)
+                    });
+                }
+            }
+        }
+
+        result.sort_by_key(|t| t.last_rejected);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: f64, per_minute: f64) -> RateLimiter {
+        let mut limits = HashMap::new();
+        limits.insert(String::from("post"), RateLimit { burst, per_minute });
+        RateLimiter::new(&limits)
+    }
+
+    fn clients(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn burst_then_throttled() {
+        let limiter = limiter(3.0, 6.0);
+        let now = Instant::now();
+        let ip = clients(&["1.2.3.4"]);
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at("post", &ip, now), Ok(()));
+        }
+        //6 a minute is one every 10 seconds
+        assert_eq!(limiter.check_at("post", &ip, now), Err(Duration::from_secs(10)));
+        assert_eq!(limiter.check_at("post", &ip, now + Duration::from_secs(4)), Err(Duration::from_secs(6)));
+        //Someone else isn't affected
+        assert_eq!(limiter.check_at("post", &clients(&["5.6.7.8"]), now), Ok(()));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = limiter(2.0, 60.0);
+        let now = Instant::now();
+        let ip = clients(&["ip"]);
+        assert!(limiter.check_at("post", &ip, now).is_ok());
+        assert!(limiter.check_at("post", &ip, now).is_ok());
+        assert!(limiter.check_at("post", &ip, now).is_err());
+        assert!(limiter.check_at("post", &ip, now + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check_at("post", &ip, now + Duration::from_secs(1)).is_err());
+        //Never refills past the burst
+        let later = now + Duration::from_secs(3600);
+        assert!(limiter.check_at("post", &ip, later).is_ok());
+        assert!(limiter.check_at("post", &ip, later).is_ok());
+        assert!(limiter.check_at("post", &ip, later).is_err());
+    }
+
+    #[test]
+    fn every_client_has_to_pass() {
+        let limiter = limiter(1.0, 1.0);
+        let now = Instant::now();
+        assert!(limiter.check_at("post", &clients(&["ip", "user1"]), now).is_ok());
+        //New account, same ip
+        assert!(limiter.check_at("post", &clients(&["ip", "user2"]), now).is_err());
+        //Nothing was taken from user2 when they were turned away
+        assert!(limiter.check_at("post", &clients(&["otherip", "user2"]), now).is_ok());
+    }
+
+    #[test]
+    fn unlimited_groups_always_pass() {
+        let limiter = limiter(0.0, 10.0);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.check_at("post", &clients(&["ip"]), now).is_ok());
+            assert!(limiter.check_at("login", &clients(&["ip"]), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn throttled_clients_are_listed() {
+        let limiter = limiter(1.0, 60.0);
+        let now = Instant::now();
+        let ip = clients(&["ip"]);
+        assert!(limiter.check_at("post", &ip, now).is_ok());
+        assert!(limiter.throttled().is_empty());
+        assert!(limiter.check_at("post", &ip, now).is_err());
+        assert!(limiter.check_at("post", &ip, now).is_err());
+
+        let throttled = limiter.throttled();
+        assert_eq!(throttled.len(), 1);
+        assert_eq!(throttled[0].group, "post");
+        assert_eq!(throttled[0].client, "ip");
+        assert_eq!(throttled[0].rejected, 2);
+    }
+
+    #[test]
+    fn never_more_buckets_than_the_cap() {
+        let mut limiter = limiter(5.0, 1.0);
+        limiter.max_buckets = 100;
+        let now = Instant::now();
+        for i in 0..1000 {
+            assert!(limiter.check_at("post", &clients(&[&i.to_string()]), now + Duration::from_millis(i)).is_ok());
+            assert!(limiter.buckets.lock().unwrap().buckets.len() <= 100);
+        }
+        //The oldest went first, the newest are all still there
+        let state = limiter.buckets.lock().unwrap();
+        assert!(!state.buckets.contains_key(&(String::from("post"), String::from("0"))));
+        assert!(state.buckets.contains_key(&(String::from("post"), String::from("999"))));
+    }
+
+    #[test]
+    fn pruning_waits_for_the_interval() {
+        let limiter = limiter(1.0, 60.0);
+        let start = limiter.buckets.lock().unwrap().last_prune;
+        for i in 0..=PRUNE_SIZE {
+            assert!(limiter.check_at("post", &clients(&[&i.to_string()]), start).is_ok());
+        }
+        //Every bucket is back to full a second later, but it's too soon to go through them all
+        let soon = start + Duration::from_secs(1);
+        assert!(limiter.check_at("post", &clients(&["new"]), soon).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), PRUNE_SIZE + 2);
+
+        assert!(limiter.check_at("post", &clients(&["newer"]), start + PRUNE_INTERVAL).is_ok());
+        let state = limiter.buckets.lock().unwrap();
+        assert_eq!(state.last_prune, start + PRUNE_INTERVAL);
+        //Only the ones that weren't full yet survive
+        assert_eq!(state.buckets.len(), 1);
+        assert!(state.buckets.contains_key(&(String::from("post"), String::from("newer"))));
+    }
+}
@@ -13,6 +13,7 @@ use crate::forms::*;
 use crate::render::*;
 use crate::constants::*;
 use crate::forum::*;
+use crate::cache::{RenderCache, RenderKey};
 use crate::pagination::*;
 
 
@@ -94,6 +95,7 @@ pub struct PostsConfig {
     pub start_num: Option<i32>,
     pub selected_post_id: Option<i64>,
     pub docs_content: Option<Vec<Content>>, //DocTreeNode<'a>>,
+    pub related_pages: Option<crate::prefab::RelatedPages>,
 
     pub render_header: bool,
     pub render_page: bool,
@@ -120,7 +122,8 @@ impl PostsConfig {
             render_reply_chain: false,
             render_reply_link: true,
             render_controls: true,
-            docs_content: None
+            docs_content: None,
+            related_pages: None
         }
     }
     pub fn reply_mode(thread: ForumThread, related: HashMap<i64,Message>, users: HashMap<i64,User>, selected_post_id: Option<i64>) -> Self {
@@ -137,25 +140,26 @@ impl PostsConfig {
             render_reply_chain: true,
             render_reply_link: false,
             render_controls: false,
-            docs_content: None
+            docs_content: None,
+            related_pages: None
         }
     }
 }
 
-fn walk_post_tree(layout_data: &MainLayoutData, bbcode: &mut BBCode, config: &PostsConfig, tree: &ReplyTree, 
+fn walk_post_tree(layout_data: &MainLayoutData, bbcode: &mut BBCode, cache: &RenderCache, config: &PostsConfig, tree: &ReplyTree, 
     sequence: Option<i32>, posts_left: &mut i32) -> Markup
 {
     *posts_left -= 1;
     html! {
         //@let (sequence = config.start_num.and_then(|s| Some(s + index as i32));
-        (post_item(layout_data, bbcode, config, tree.post, sequence)) 
+        (post_item(layout_data, bbcode, cache, config, tree.post, sequence)) 
         @if *posts_left > 0 { hr."smaller"; }
         @if tree.children.len() > 0 {
             div."replychain" {
                 @for child in &tree.children {
                     //Note: only the very top level should get sequence numbers, so all inner recursive calls get None sequence
                     //@let (markup, posts_left) = (walk_post_tree(layout_data, &mut bbcode, config, child, None, posts_left - 1))
-                    (walk_post_tree(layout_data, bbcode, config, child, None, posts_left))
+                    (walk_post_tree(layout_data, bbcode, cache, config, child, None, posts_left))
                 }
             }
         }
@@ -187,6 +191,7 @@ pub fn render_posts(context: &mut PageContext, config: PostsConfig) -> Markup
 
     let data = &context.layout_data;
     let bbcode = &mut context.bbcode;
+    let cache = &context.render_cache;
     let mut post_count = config.thread.posts.len() as i32;
 
     let reply_tree: Vec<ReplyTree> = if config.render_reply_chain 
@@ -236,10 +241,23 @@ pub fn render_posts(context: &mut PageContext, config: PostsConfig) -> Markup
                     }
                     iframe."votes" src={(data.links.votewidget(&thread.thread))}{}
                 }
+                (keyword_chips(&data.links, &thread.thread))
             }
         }
         @if config.render_page && is_pagetype {
-            (render_page(&data, bbcode, &thread, &config.docs_content))
+            (render_page(&data, bbcode, cache, &thread, &config.docs_content))
+            @if let Some(ref related) = config.related_pages {
+                @if !related.pages.is_empty() {
+                    section #"related-pages" {
+                        h3 { "Related" }
+                        div."cardslist" {
+                            @for page in &related.pages {
+                                (crate::render::submissions::page_card(&data.links, page, &related.users))
+                            }
+                        }
+                    }
+                }
+            }
         }
         //it says "thread-top" because it is: it's the beginning of the section that displays posts. After the 
         //for loop, it then displays pages, which is on the bottom of the thread, so it might seem confusing.
@@ -254,7 +272,7 @@ pub fn render_posts(context: &mut PageContext, config: PostsConfig) -> Markup
             @if reply_tree.len() > 0 {
                 @for (index,tree) in reply_tree.iter().enumerate() {
                     @let sequence = config.start_num.and_then(|s| Some(s + index as i32));
-                    (walk_post_tree(&context.layout_data, &mut context.bbcode, &config, tree, sequence, &mut post_count))
+                    (walk_post_tree(&context.layout_data, &mut context.bbcode, &context.render_cache, &config, tree, sequence, &mut post_count))
                 }
             }
             @else {
@@ -283,6 +301,7 @@ pub fn render_posts(context: &mut PageContext, config: PostsConfig) -> Markup
                             }
                             @if can_delete_thread(user, &thread.thread) {
                                 form."nospacing" #"deletethread" method="POST" action=(data.links.forum_thread_delete(&thread.thread)) {
+                                    (csrf_input(data))
                                     input."coolbutton notheme" data-confirmdelete=(format!("thread '{}'", opt_s!(&thread.thread.name))) type="submit" value="Delete thread";
                                 }
                             }
@@ -356,7 +375,7 @@ pub fn display_doctree(layout_data: &MainLayoutData, documentation: &Vec<Content
 
 /// Render the page data, such as text and infoboxes, on standard pages. True forum threads don't have main
 /// content like that, so this is only called on programs, resources, etc
-pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThread, _docs_content: &Option<Vec<Content>>) -> Markup 
+pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, cache: &RenderCache, thread: &ForumThread, _docs_content: &Option<Vec<Content>>) -> Markup 
 {
     let values = match &thread.thread.values { Some(values) => values.clone(), None => HashMap::new() };
 
@@ -392,6 +411,9 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
                             span."smallseparate" {
                                 b { "Download:" }
                                 span."key" { (key) }
+                                @if let Some(removed) = get_key_removed(&thread.thread) {
+                                    span."error" { "(removed " (removed) ")" }
+                                }
                                 (threadicon(&data.links, &thread))
                             }
                         }
@@ -401,6 +423,10 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
                                 a."key" href=(data.links.qr_generator(&thread.thread)) { "QR Codes" }
                                 (threadicon(&data.links, &thread))
                             }
+                            span."smallseparate" {
+                                b { "Files:" }
+                                a."key" href=(data.links.ptc_download_all(&thread.thread)) { "Download all (.zip)" }
+                            }
                         }
                         @if let Some(version) = values.get(SBSValue::VERSION).and_then(|k| k.as_str()) {
                             span."smallseparate" {
@@ -415,6 +441,7 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
                             }
                         }
                     }
+                    iframe."collections" src=(data.links.collections_widget(&thread.thread)) {}
                 }
             }
             //Snail says he doesn't want the doctree on pages
@@ -431,7 +458,7 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
             //        }
             //    }
             //}
-            (render_content(&thread.thread, bbcode))
+            (render_content(&thread.thread, bbcode, &data.links, Some(cache)))
             @if can_edit || can_delete {
                 div."pagelist smallseparate" {
                     @if can_edit {
@@ -439,6 +466,7 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
                     }
                     @if can_delete {
                         form."nospacing" #"deletepage" method="POST" action=(data.links.page_delete(&thread.thread)) {
+                            (csrf_input(data))
                             input."coolbutton notheme" data-confirmdelete=(format!("page '{}'", opt_s!(&thread.thread.name))) type="submit" value="Delete page";
                         }
                     }
@@ -459,8 +487,9 @@ pub fn render_page(data: &MainLayoutData, bbcode: &mut BBCode, thread: &ForumThr
     }
 }
 
-//Now that we support multiple markups, rendering content can get a little complex
-pub fn render_content(content: &Content, bbcode: &mut BBCode) -> Markup {
+//Now that we support multiple markups, rendering content can get a little complex. Content with an id and
+//revision is only rendered once per revision if a cache is given
+pub fn render_content(content: &Content, bbcode: &mut BBCode, links: &LinkConfig, cache: Option<&RenderCache>) -> Markup {
     if let Some(text) = &content.text {
         let mut markup : &str = MARKUPBBCODE;
         if let Some(ref values) = content.values {
@@ -470,24 +499,45 @@ pub fn render_content(content: &Content, bbcode: &mut BBCode) -> Markup {
                 }
             }
         }
+        match (cache, content.id, content.lastRevisionId) {
+            (Some(cache), Some(id), Some(revision)) => {
+                let key = RenderKey { kind: "content", id, revision: revision.to_string(), markup: markup.to_string() };
+                PreEscaped(cache.get_or_render(key, || render_content_markup(text, markup, content.id, bbcode, links).into_string()).to_string())
+            },
+            _ => render_content_markup(text, markup, content.id, bbcode, links)
+        }
+    }
+    else {
+        html!(div."error" { "No content found? That's not supposed to happen!" })
+    }
+}
+
+fn render_content_markup(text: &str, markup: &str, id: Option<i64>, bbcode: &mut BBCode, links: &LinkConfig) -> Markup {
+    if markup == MARKUPBBCODE {
         html!(
-            div."content" data-markup=(markup) data-prerendered[markup == MARKUPBBCODE] {
-                @if markup == MARKUPBBCODE {
-                    (PreEscaped(&bbcode.parse_profiled_opt(text, format!("program-{}", i(&content.id)))))
-                }
-                @else {
-                    (text)
-                }
+            div."content" data-markup=(markup) data-prerendered {
+                (PreEscaped(&bbcode.parse_profiled_opt(text, format!("program-{}", i(&id)))))
             }
         )
     }
     else {
-        html!(div."error" { "No content found? That's not supposed to happen!" })
+        //Everything else goes through the ports of the js markup parsers. If those fail, the js gets the raw
+        //text like before, which will at least show the same error everyone's used to
+        match markup::convert_lang(text, markup, &links.http_root) {
+            Ok(rendered) => html!(
+                div."content Markup" data-markup=(markup) data-prerendered {
+                    (PreEscaped(rendered))
+                }
+            ),
+            Err(_) => html!(
+                div."content" data-markup=(markup) { (text) }
+            )
+        }
     }
 }
 
 /// Render content WITHOUT a full content. This is more expensive than just rendering with content (sorry?)
-pub fn render_content_nocontent(text: String, markup: Option<String>, bbcode: &mut BBCode) -> Markup {
+pub fn render_content_nocontent(text: String, markup: Option<String>, bbcode: &mut BBCode, links: &LinkConfig) -> Markup {
     let mut content = Content::default();
     content.text = Some(text);
     if let Some(markup) = markup {
@@ -495,11 +545,22 @@ pub fn render_content_nocontent(text: String, markup: Option<String>, bbcode: &m
         values.insert(SBSValue::MARKUP.to_string(), markup.into());
         content.values = Some(values); 
     }
-    render_content(&content, bbcode)
+    render_content(&content, bbcode, links, None)
+}
+
+/// Messages are always bbcode and only change when they're edited, so they can be cached by edit date. 
+fn render_message(bbcode: &mut BBCode, cache: &RenderCache, post: &Message, text: &str, profile_name: String) -> String {
+    match (post.id, post.editDate.or(post.createDate)) {
+        (Some(id), Some(revision)) => {
+            let key = RenderKey { kind: "message", id, revision: revision.to_rfc3339(), markup: MARKUPBBCODE.to_string() };
+            cache.get_or_render(key, || bbcode.parse_profiled_opt(text, profile_name)).to_string()
+        },
+        _ => bbcode.parse_profiled_opt(text, profile_name)
+    }
 }
 
 //WAS consuming bbcode, now i'm not sure. leaving for now
-pub fn post_item(layout_data: &MainLayoutData, bbcode: &mut BBCode, config: &PostsConfig, post: &Message, 
+pub fn post_item(layout_data: &MainLayoutData, bbcode: &mut BBCode, cache: &RenderCache, config: &PostsConfig, post: &Message, 
     sequence: Option<i32>) -> Markup
 {
     let users = &config.users;
@@ -550,6 +611,7 @@ pub fn post_item(layout_data: &MainLayoutData, bbcode: &mut BBCode, config: &Pos
                                 }
                                 @if can_user_delete_message(&current_user, post) {
                                     form."postdelete nospacing" method="POST" action=(layout_data.links.forum_post_delete(post)) {
+                                        (csrf_input(layout_data))
                                         input."flatlink notheme" title="Delete" data-confirmdelete=(format!("post '{}'", opt_s!(&post.text))) type="submit" value="✖";
                                     }
                                     //a."postreply flatlink" title="Delete" href=(layout_data.links.forum_post_delete(post)) { "✖" }
@@ -563,10 +625,10 @@ pub fn post_item(layout_data: &MainLayoutData, bbcode: &mut BBCode, config: &Pos
                 }
                 @if let Some(reply_post) = reply_post {
                     //TODO: can't decide between consuming or not. spoilers are the important bit
-                    (post_reply(layout_data, bbcode, reply_post, &config.thread.thread, &config.users))
+                    (post_reply(layout_data, bbcode, cache, reply_post, &config.thread.thread, &config.users))
                 }
                 @if let Some(text) = &post.text {
-                    div."content bbcode" data-postid=(i(&post.id)) { (PreEscaped(bbcode.parse_profiled_opt(text, format!("post-{}",i(&post.id))))) }
+                    div."content bbcode" data-postid=(i(&post.id)) { (PreEscaped(render_message(bbcode, cache, post, text, format!("post-{}",i(&post.id))))) }
                 }
                 div."postfooter mediumseparate" {
                     @if let Some(reply_link) = reply_chain_link {
@@ -592,7 +654,7 @@ pub fn post_item(layout_data: &MainLayoutData, bbcode: &mut BBCode, config: &Pos
     }
 }
 
-pub fn post_reply(layout_data: &MainLayoutData, bbcode: &mut BBCode, post: &Message, thread: &Content, users: &HashMap<i64, User>) -> Markup
+pub fn post_reply(layout_data: &MainLayoutData, bbcode: &mut BBCode, cache: &RenderCache, post: &Message, thread: &Content, users: &HashMap<i64, User>) -> Markup
 {
     let user = user_or_default(users.get(&post.createUserId.unwrap_or(0)));
     html! {
@@ -604,7 +666,7 @@ pub fn post_reply(layout_data: &MainLayoutData, bbcode: &mut BBCode, post: &Mess
                 //Ignoring graphemes for now, sorry. In NEARLY all cases, 200 bytes should be enough to fill 
                 //a line, unless you're being ridiculous
                 //@let text = if text.len() > 200 { &text[0..200] } else { &text };
-                div."content bbcode postpreview" { (PreEscaped(bbcode.parse_profiled_opt(text, format!("reply-{}",i(&post.id))))) }
+                div."content bbcode postpreview" { (PreEscaped(render_message(bbcode, cache, post, text, format!("reply-{}",i(&post.id))))) }
             }
         }
     }
@@ -58,7 +58,7 @@ pub fn header(data: &MainLayoutData) -> Markup {
         }
         @if let Some(alert) = &data.raw_alert {
             @if alert.len() > 0 {
-                div."alert" { (PreEscaped(alert)) }
+                div."alert" { (PreEscaped(crate::sanitize::clean_html(alert))) }
             }
         }
     }
@@ -98,7 +98,7 @@ pub fn basic_skeleton(data: &MainLayoutData, head_inner: Markup, body_inner: Mar
                 (data.links.style("/base.css"))
                 (data.links.style("/themes.css"))
                 (data.links.script("/base.js"))
-                script {
+                script nonce=(data.csp_nonce) {
                     (PreEscaped("var SBSBASEURL = \"")) (data.links.http_root) (PreEscaped("\";"))
                 }
                 (head_inner)
@@ -110,7 +110,7 @@ pub fn basic_skeleton(data: &MainLayoutData, head_inner: Markup, body_inner: Mar
                 (body_inner) 
                 //Gotta do it HERE so everything has already run!
                 @if let Some(profile_data) = profile_data {
-                    script {
+                    script nonce=(data.csp_nonce) {
                         "var profiler_data = "(PreEscaped(serde_json::to_string(&profile_data).unwrap_or(String::from("{} /* COULD NOT SERIALIZE */"))))";"
                     }
                 }
@@ -123,30 +123,60 @@ pub struct LayoutMeta {
     pub title : String,
     pub description : String,
     pub image : Option<String>,
-    pub canonical: Option<String>
+    pub canonical: Option<String>,
+    pub feeds: Vec<(String, String)>, //Title and link for each atom feed about this page
+    pub og_type: String,
+    pub oembed: Option<String>, //Discovery link for embedding this page elsewhere
+    pub structured_data: Option<serde_json::Value> //Schema.org JSON-LD for this page, see crate::structured
+}
+
+impl Default for LayoutMeta {
+    fn default() -> Self {
+        LayoutMeta {
+            title: "SmileBASIC Source".to_string(),
+            description: "A community for sharing programs and getting advice on SmileBASIC applications on the Nintendo DSi, 3DS, and Switch".to_string(),
+            image: None,
+            canonical: None,
+            feeds: Vec::new(),
+            og_type: String::from("website"),
+            oembed: None,
+            structured_data: None
+        }
+    }
 }
 
 pub fn layout(main_data: &MainLayoutData, page: Markup) -> Markup {
-    layout_with_meta(main_data, LayoutMeta {
-        title: "SmileBASIC Source".to_string(),
-        description: "A community for sharing programs and getting advice on SmileBASIC applications on the Nintendo DSi, 3DS, and Switch".to_string(),
-        image: None,
-        canonical: None
-    }, page)
+    layout_with_meta(main_data, LayoutMeta::default(), page)
 }
 
 pub fn layout_with_meta(main_data: &MainLayoutData, meta: LayoutMeta, page: Markup) -> Markup {
     basic_skeleton(main_data, html!{
         title { (meta.title) }
         meta name="description" content=(meta.description);
-        @if let Some(meta_image) = meta.image {
-            meta property="og:title" content=(meta.title);
-            meta property="og:description" content=(meta.description);
-            meta property="og:image" content=(meta_image);
+        //Link previews want the full url, and every page has one even if it isn't canonical
+        meta property="og:type" content=(meta.og_type);
+        meta property="og:url" content=(main_data.links.absolute(meta.canonical.as_deref().unwrap_or(&main_data.current())));
+        meta property="og:title" content=(meta.title);
+        meta property="og:description" content=(meta.description);
+        meta name="twitter:card" content="summary";
+        meta name="twitter:title" content=(meta.title);
+        meta name="twitter:description" content=(meta.description);
+        @if let Some(ref meta_image) = meta.image {
+            meta property="og:image" content=(main_data.links.absolute(meta_image));
+            meta name="twitter:image" content=(main_data.links.absolute(meta_image));
+        }
+        @if let Some(ref oembed) = meta.oembed {
+            link rel="alternate" type="application/json+oembed" title=(meta.title) href=(main_data.links.absolute(oembed));
+        }
+        @if let Some(ref structured_data) = meta.structured_data {
+            script type="application/ld+json" { (PreEscaped(crate::structured::render_json_ld(structured_data))) }
         }
         @if let Some(ref canonical) = meta.canonical {
             link rel="canonical" href=(canonical);
         }
+        @for (title, href) in &meta.feeds {
+            link rel="alternate" type="application/atom+xml" title=(title) href=(href);
+        }
         //This is a terrible hit to take on all pages but... caching??
         (main_data.links.script("/markup/langs.js"))
         (main_data.links.script("/markup/legacy.js"))
@@ -159,7 +189,7 @@ pub fn layout_with_meta(main_data: &MainLayoutData, meta: LayoutMeta, page: Mark
         (main_data.links.script("/sb-highlight.js"))
         //MUST come after, it uses sb-highlight!
         (main_data.links.script("/layout.js"))
-        style { (PreEscaped(r#"
+        style nonce=(main_data.csp_nonce) { (PreEscaped(r#"
             body {
                 background-repeat: repeat;
                 background-image: url(""#))(main_data.links.resource_root)(PreEscaped(r#"/sb-tile.png")
@@ -148,6 +148,23 @@ pub fn errorlist(errors: Option<Vec<String>>) -> Markup {
 }
 
 
+/// Clickable chips for each keyword on the content, which go to the tag page for that keyword.
+/// Renders nothing if there are no keywords (or they weren't requested)
+pub fn keyword_chips(links: &LinkConfig, content: &Content) -> Markup {
+    html! {
+        @if let Some(ref keywords) = content.keywords {
+            @if !keywords.is_empty() {
+                div."keywordchips" {
+                    @for keyword in keywords {
+                        a."keywordchip flatlink" href=(links.tag(keyword)) { "#" (keyword) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+
 #[derive(Default)]
 pub struct PostTextboxConfig {
     pub textbox_id: Option<String>,
@@ -189,6 +206,17 @@ impl PostTextboxConfig {
     }
 }
 
+/// The hidden csrf token field that has to go in every form that POSTs back to us. Logged out visitors have no 
+/// session to protect (and their pages get cached and shared), so for them this is empty
+pub fn csrf_input(data: &MainLayoutData) -> Markup
+{
+    html! {
+        @if let Some(ref token) = data.csrf_token {
+            input type="hidden" name=(crate::constants::CSRFFIELD) value=(token);
+        }
+    }
+}
+
 //Eventually may expand this
 pub fn post_textbox(config: PostTextboxConfig) -> Markup //id: Option<&str>, name: Option<&str>, value: Option<&str>) -> Markup
 {
@@ -61,6 +61,7 @@ pub fn page_card(links: &LinkConfig, page: &Content, users: &HashMap<i64, User>)
                 div."cardtext" {
                     a."flatlink" href=(link) { h3 { (opt_s!(page.name)) } }
                     div."description" { (opt_s!(page.description)) }
+                    (keyword_chips(links, page))
                 }
                 //Conditionally render the "cardimage" container
                 @if let Some(images) = values.get(SBSValue::IMAGES).and_then(|k| k.as_array()) {
@@ -78,7 +79,12 @@ pub fn page_card(links: &LinkConfig, page: &Content, users: &HashMap<i64, User>)
                 time."aside" datetime=(d(&page.createDate)) { (timeago_o(&page.createDate)) } 
                 //All this junk needs "key" so it can display properly... probably should change this?
                 @if let Some(key) = values.get(SBSValue::DOWNLOADKEY).and_then(|k| k.as_str()) {
-                    span."key" { (key) }
+                    @if let Some(removed) = get_key_removed(page) {
+                        span."key error" title={"Key " (key) " removed " (removed)} { "REMOVED" }
+                    }
+                    @else {
+                        span."key" { (key) }
+                    }
                 }
                 @else if systems.iter().any(|s| s == &PTCSYSTEM) {
                     a."key" href=(links.qr_generator(page)) { "QR Codes" }
@@ -1,4 +1,7 @@
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
 use contentapi::endpoints;
 
 // -------------------------------------
@@ -10,7 +13,55 @@ pub enum Response {
     Render(String), //string is the markup
     RenderWithStatus(String, u16),  //string is the markup, status is the status code returned
     MessageWithStatus(String, u16), //Not an html page, just a message
-    Redirect(String)
+    Redirect(String),
+    Document(String, String),       //Not an html page, some other text document (like a feed) with the given content type
+    File(Vec<u8>, String, String),  //Raw bytes to download, the content type, and the filename to save as
+    Tagged(Box<Response>, ETag),    //Any other response, sent with an ETag so browsers can revalidate instead of refetching
+    Unavailable(String, u16)        //The backend is down or too slow. The message is for the logs; the frontend shows its own page
+}
+
+impl Response {
+    /// Attach an ETag to this response. If the browser already has a copy with the same tag, the
+    /// conditional GET layer answers with 304 instead of sending the page again
+    pub fn with_etag(self, etag: ETag) -> Self {
+        Response::Tagged(Box::new(self), etag)
+    }
+}
+
+/// Put on responses for a backend that couldn't be reached (see Response::Unavailable), so something with
+/// enough context can replace the bare message with a proper page
+#[derive(Debug, Clone)]
+pub struct BackendUnavailable;
+
+/// A weak entity tag. It's built from the data a page is rendered FROM (ids, revisions, dates, the user and 
+/// their settings) rather than from the rendered bytes, so it's only "semantically" equivalent: hence weak
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(u64);
+
+impl ETag {
+    pub fn builder() -> ETagBuilder {
+        ETagBuilder(DefaultHasher::new())
+    }
+
+    /// The value as it goes in the ETag header, quotes and all
+    pub fn to_header(&self) -> String {
+        format!("W/\"{:016x}\"", self.0)
+    }
+}
+
+/// Hash together everything a page's output depends on. Anything left out means a stale page 
+/// gets a 304, so when in doubt, add it
+pub struct ETagBuilder(DefaultHasher);
+
+impl ETagBuilder {
+    pub fn with<T: Hash + ?Sized>(mut self, value: &T) -> Self {
+        value.hash(&mut self.0);
+        self
+    }
+
+    pub fn finish(self) -> ETag {
+        ETag(self.0.finish())
+    }
 }
 
 #[derive(Debug)]
@@ -64,6 +115,7 @@ pub fn flatten(result: Result<Response, Error>) -> Response
         Err(error) => {
             match error
             {
+                Error::Api(apierr) if apierr.is_unavailable() => Response::Unavailable(apierr.to_verbose_string(), apierr.to_status()),
                 Error::Api(apierr) => Response::MessageWithStatus(apierr.to_verbose_string(), apierr.to_status()),
                 Error::Other(otherr) => Response::MessageWithStatus(otherr.clone(), 500),
                 Error::NotFound(otherr) => Response::MessageWithStatus(otherr.clone(), 404),
@@ -93,7 +145,38 @@ impl axum::response::IntoResponse for Response {
                     axum::http::StatusCode::from_u16(status).unwrap(),
                     msg,
                 ).into_response(),
-            Response::Redirect(uri) => axum::response::Redirect::to(&uri).into_response()
+            Response::Redirect(uri) => axum::response::Redirect::to(&uri).into_response(),
+            Response::Document(text, content_type) =>
+                (
+                    [(axum::http::header::CONTENT_TYPE, content_type)],
+                    text,
+                ).into_response(),
+            Response::File(bytes, content_type, filename) =>
+                (
+                    [
+                        (axum::http::header::CONTENT_TYPE, content_type),
+                        (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename))
+                    ],
+                    bytes,
+                ).into_response(),
+            Response::Tagged(response, etag) => {
+                let mut result = response.into_response();
+                //Errors and the like shouldn't be revalidated against a tag meant for the real page
+                if result.status().is_success() {
+                    let headers = result.headers_mut();
+                    if let Ok(value) = axum::http::HeaderValue::from_str(&etag.to_header()) {
+                        headers.insert(axum::http::header::ETAG, value);
+                    }
+                    //Always ask us first; the whole point is that the check is cheap
+                    headers.insert(axum::http::header::CACHE_CONTROL, axum::http::HeaderValue::from_static("no-cache"));
+                }
+                result
+            },
+            Response::Unavailable(msg, status) => {
+                let mut result = (axum::http::StatusCode::from_u16(status).unwrap(), msg).into_response();
+                result.extensions_mut().insert(BackendUnavailable);
+                result
+            }
         }
     }
 }
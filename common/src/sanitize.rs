@@ -0,0 +1,364 @@
+//! Allowlist html sanitizer for the html admins write by hand: the front page, the alert banner and the
+//! documentation preamble. Those go out on every page as-is, so a paste mistake or a stolen admin account
+//! would otherwise be script on the whole site. This isn't a full html parser, it only has to understand
+//! enough to never let through anything it didn't recognize: tags and attributes not on the lists are
+//! dropped (the text inside unknown tags is kept), and the contents of things like script are dropped too.
+//! It runs when the html is saved AND when it's rendered, so whatever is already stored gets cleaned.
+
+use html_escape::{decode_html_entities, encode_double_quoted_attribute};
+
+/// The cleaned html plus a description of what was taken out of it and how many times, in the order
+/// it was first found
+#[derive(Debug, Clone, Default)]
+pub struct Sanitized {
+    pub html: String,
+    pub removed: Vec<(String, usize)>
+}
+
+impl Sanitized {
+    fn note(&mut self, what: String) {
+        match self.removed.iter_mut().find(|(w,_)| *w == what) {
+            Some((_, count)) => *count += 1,
+            None => self.removed.push((what, 1))
+        }
+    }
+}
+
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "article", "aside", "b", "blockquote", "br", "caption", "cite", "code", "col", "colgroup",
+    "dd", "del", "details", "div", "dl", "dt", "em", "figcaption", "figure", "footer", "h1", "h2", "h3",
+    "h4", "h5", "h6", "header", "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s",
+    "samp", "section", "small", "span", "strong", "sub", "summary", "sup", "table", "tbody", "td", "tfoot",
+    "th", "thead", "time", "tr", "u", "ul", "var", "wbr"
+];
+
+const VOID_TAGS: &[&str] = &["br", "col", "hr", "img", "wbr"];
+
+/// Removed along with everything inside them; none of it is text anyone meant to show
+const DROP_CONTENT_TAGS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet", "template", "noscript",
+    "noembed", "noframes", "textarea", "title", "xmp", "svg", "math", "select"
+];
+
+const GLOBAL_ATTRIBUTES: &[&str] = &["class", "id", "title", "lang", "dir", "style"];
+
+const TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "target", "rel"]),
+    ("img", &["src", "alt", "width", "height", "loading"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan", "scope"]),
+    ("col", &["span"]),
+    ("colgroup", &["span"]),
+    ("ol", &["start", "reversed", "type"]),
+    ("li", &["value"]),
+    ("time", &["datetime"]),
+    ("details", &["open"]),
+    ("blockquote", &["cite"]),
+    ("q", &["cite"]),
+];
+
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite"];
+const ALLOWED_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Css that can pull in other resources or run things (the last two are old browser features, but still)
+const STYLE_BLOCKLIST: &[&str] = &["url(", "@import", "expression(", "javascript:", "behavior:", "-moz-binding"];
+
+fn attribute_allowed(tag: &str, name: &str) -> bool {
+    GLOBAL_ATTRIBUTES.contains(&name) ||
+    (name.starts_with("data-") && name.len() > 5 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')) ||
+    TAG_ATTRIBUTES.iter().any(|(t, attrs)| *t == tag && attrs.contains(&name))
+}
+
+/// The scheme a url would be followed with, if it has one. Browsers ignore whitespace and control characters
+/// in the scheme ("java\tscript:"), so this does too
+fn url_scheme(url: &str) -> Option<String> {
+    let cleaned: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).collect();
+    let colon = cleaned.find(':')?;
+    let scheme = &cleaned[..colon];
+    //A colon after any of these is part of the path or query of a relative url
+    if scheme.contains(['/', '?', '#']) {
+        None
+    }
+    else {
+        Some(scheme.to_ascii_lowercase())
+    }
+}
+
+struct Parser<'a> {
+    html: &'a str,
+    pos: usize,
+    result: Sanitized,
+    open: Vec<String>
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.html[self.pos..]
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.html.as_bytes().get(self.pos + offset).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(0), Some(c) if c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Move past the next occurrence of the given string, or to the end if there isn't one
+    fn skip_past(&mut self, pattern: &str) {
+        match self.rest().find(pattern) {
+            Some(index) => self.pos += index + pattern.len(),
+            None => self.pos = self.html.len()
+        }
+    }
+
+    fn read_while<F: Fn(u8) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while matches!(self.peek(0), Some(c) if f(c)) {
+            self.pos += 1;
+        }
+        &self.html[start..self.pos]
+    }
+
+    fn read_tag_name(&mut self) -> String {
+        self.read_while(|c| !c.is_ascii_whitespace() && c != b'>' && c != b'/').to_ascii_lowercase()
+    }
+
+    /// Read the attributes up to the end of the tag. Returns None if the html ended before the tag did
+    fn read_attributes(&mut self) -> Option<Vec<(String, Option<String>)>> {
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek(0) {
+                None => return None,
+                Some(b'>') => { self.pos += 1; return Some(attributes); },
+                Some(b'/') => { self.pos += 1; continue; },
+                _ => {}
+            }
+            let name = self.read_while(|c| !c.is_ascii_whitespace() && c != b'=' && c != b'>' && c != b'/').to_ascii_lowercase();
+            self.skip_whitespace();
+            if self.peek(0) != Some(b'=') {
+                attributes.push((name, None));
+                continue;
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let value = match self.peek(0) {
+                Some(quote @ (b'"' | b'\'')) => {
+                    self.pos += 1;
+                    let value = self.read_while(|c| c != quote);
+                    self.peek(0)?;
+                    self.pos += 1;
+                    value
+                },
+                _ => self.read_while(|c| !c.is_ascii_whitespace() && c != b'>')
+            };
+            attributes.push((name, Some(decode_html_entities(value).into_owned())));
+        }
+    }
+
+    fn start_tag(&mut self, name: String, attributes: Vec<(String, Option<String>)>) {
+        if DROP_CONTENT_TAGS.contains(&name.as_str()) {
+            self.result.note(format!("<{}> and everything in it", name));
+            //Case doesn't matter for the closing tag, but searching the lowercase copy keeps the positions the same
+            let closing = format!("</{}", name);
+            match self.rest().to_ascii_lowercase().find(&closing) {
+                Some(index) => { self.pos += index; self.skip_past(">"); },
+                None => self.pos = self.html.len()
+            }
+            return;
+        }
+
+        if !ALLOWED_TAGS.contains(&name.as_str()) {
+            self.result.note(format!("<{}> tag", name));
+            return;
+        }
+
+        let mut tag = format!("<{}", name);
+        for (attribute, value) in attributes {
+            if attribute.starts_with("on") {
+                self.result.note(format!("{} attribute", attribute));
+                continue;
+            }
+            if !attribute_allowed(&name, &attribute) {
+                self.result.note(format!("{} attribute on <{}>", attribute, name));
+                continue;
+            }
+            if let Some(ref value) = value {
+                if URL_ATTRIBUTES.contains(&attribute.as_str()) {
+                    if let Some(scheme) = url_scheme(value) {
+                        if !ALLOWED_SCHEMES.contains(&scheme.as_str()) {
+                            self.result.note(format!("{}: link", scheme));
+                            continue;
+                        }
+                    }
+                }
+                if attribute == "style" {
+                    let lower = value.to_ascii_lowercase();
+                    if let Some(blocked) = STYLE_BLOCKLIST.iter().find(|b| lower.contains(*b)) {
+                        self.result.note(format!("style using {}", blocked));
+                        continue;
+                    }
+                }
+            }
+            match value {
+                Some(value) => tag.push_str(&format!(" {}=\"{}\"", attribute, encode_double_quoted_attribute(&value))),
+                None => tag.push_str(&format!(" {}", attribute))
+            }
+        }
+        tag.push('>');
+        self.result.html.push_str(&tag);
+
+        if !VOID_TAGS.contains(&name.as_str()) {
+            self.open.push(name);
+        }
+    }
+
+    fn end_tag(&mut self, name: String) {
+        //Closing something that isn't open (or that we dropped) just goes away; closing something further
+        //down closes everything inside it too, like a browser would
+        if let Some(index) = self.open.iter().rposition(|t| *t == name) {
+            for tag in self.open.drain(index..).rev() {
+                self.result.html.push_str(&format!("</{}>", tag));
+            }
+        }
+    }
+
+    fn parse(mut self) -> Sanitized {
+        while self.pos < self.html.len() {
+            let next = self.rest().find('<').unwrap_or(self.rest().len());
+            self.result.html.push_str(&self.html[self.pos..self.pos + next]);
+            self.pos += next;
+
+            if self.pos >= self.html.len() {
+                break;
+            }
+
+            if self.rest().starts_with("<!--") {
+                self.pos += 4;
+                self.skip_past("-->");
+            }
+            else if matches!(self.peek(1), Some(b'!' | b'?')) {
+                //Doctypes, cdata, processing instructions; nothing that belongs in a fragment
+                self.skip_past(">");
+            }
+            else if self.peek(1) == Some(b'/') && matches!(self.peek(2), Some(c) if c.is_ascii_alphabetic()) {
+                self.pos += 2;
+                let name = self.read_tag_name();
+                self.skip_past(">");
+                self.end_tag(name);
+            }
+            else if matches!(self.peek(1), Some(c) if c.is_ascii_alphabetic()) {
+                self.pos += 1;
+                let name = self.read_tag_name();
+                match self.read_attributes() {
+                    Some(attributes) => self.start_tag(name, attributes),
+                    None => {
+                        self.result.note(String::from("unfinished tag"));
+                        self.pos = self.html.len();
+                    }
+                }
+            }
+            else {
+                //Just a less than sign in the text
+                self.result.html.push_str("&lt;");
+                self.pos += 1;
+            }
+        }
+
+        for tag in self.open.drain(..).rev() {
+            self.result.html.push_str(&format!("</{}>", tag));
+        }
+
+        self.result
+    }
+}
+
+/// Clean the given html, also reporting what was removed
+pub fn sanitize_html(html: &str) -> Sanitized {
+    Parser { html, pos: 0, result: Sanitized::default(), open: Vec::new() }.parse()
+}
+
+/// Clean the given html, for when you only want the html
+pub fn clean_html(html: &str) -> String {
+    sanitize_html(html).html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_ordinary_html() {
+        let html = r#"<h2 class="big">Hi</h2><p>Some <a href="https://example.com/a?b=c" target="_blank">link</a> &amp; <img src="/x.png" alt="x"><br></p>"#;
+        assert_eq!(clean_html(html), html);
+        assert!(sanitize_html(html).removed.is_empty());
+    }
+
+    #[test]
+    fn strips_event_handlers() {
+        let result = sanitize_html(r#"<p onclick="steal()" ONMOUSEOVER='x()' class="a">text</p>"#);
+        assert_eq!(result.html, r#"<p class="a">text</p>"#);
+        assert_eq!(result.removed, vec![(String::from("onclick attribute"), 1), (String::from("onmouseover attribute"), 1)]);
+    }
+
+    #[test]
+    fn strips_script_urls() {
+        for url in ["javascript:alert(1)", " JavaScript:alert(1)", "java&#x09;script:alert(1)", "javascript&colon;alert(1)",
+            "vbscript:x", "data:text/html,<script>x()</script>"]
+        {
+            let html = clean_html(&format!(r#"<a href="{}">link</a>"#, url));
+            assert_eq!(html, "<a>link</a>", "{} got through", url);
+        }
+        //Colons further in are just part of a relative url
+        assert_eq!(clean_html(r#"<a href="/page?time=12:00">x</a>"#), r#"<a href="/page?time=12:00">x</a>"#);
+        assert_eq!(clean_html(r#"<img src="javascript:x()">"#), "<img>");
+    }
+
+    #[test]
+    fn drops_script_style_and_svg_content() {
+        assert_eq!(clean_html("a<script>alert('</p>')</script>b"), "ab");
+        assert_eq!(clean_html("a<STYLE>body { display: none }</sTyLe>b"), "ab");
+        assert_eq!(clean_html(r#"a<svg><script>x()</script><circle onload="x()"/></svg>b"#), "ab");
+        assert_eq!(clean_html("a<script>never closed"), "a");
+    }
+
+    #[test]
+    fn unterminated_attribute_ends_everything() {
+        let result = sanitize_html(r#"<p>safe</p><img src="x onerror=alert(1)>more"#);
+        assert_eq!(result.html, "<p>safe</p>");
+        assert_eq!(result.removed, vec![(String::from("unfinished tag"), 1)]);
+        assert_eq!(clean_html("<p>ok</p><div class='open"), "<p>ok</p>");
+    }
+
+    #[test]
+    fn blocks_style_urls() {
+        assert_eq!(clean_html(r#"<div style="background: URL(https://evil.example/track.png)">x</div>"#), "<div>x</div>");
+        assert_eq!(clean_html(r#"<div style="color:red;background:u&#x72;l(x)">x</div>"#), "<div>x</div>");
+        assert_eq!(clean_html(r#"<div style="color: red">x</div>"#), r#"<div style="color: red">x</div>"#);
+    }
+
+    #[test]
+    fn stray_closing_tags_go_away() {
+        assert_eq!(clean_html("</x>a</p>b</div>"), "ab");
+        assert_eq!(clean_html("<b>a</i>b</b>"), "<b>ab</b>");
+        //Closing an outer tag closes what's inside it
+        assert_eq!(clean_html("<div><b><i>a</div>b"), "<div><b><i>a</i></b></div>b");
+    }
+
+    #[test]
+    fn open_tags_get_closed() {
+        assert_eq!(clean_html("<div><p>text"), "<div><p>text</p></div>");
+        assert_eq!(clean_html("<ul><li>one<li>two"), "<ul><li>one<li>two</li></li></ul>");
+        assert_eq!(clean_html("<br><hr><p>x"), "<br><hr><p>x</p>");
+    }
+
+    #[test]
+    fn unknown_tags_keep_their_text() {
+        let result = sanitize_html("<marquee>hi <blink>there</blink></marquee> 1 < 2");
+        assert_eq!(result.html, "hi there 1 &lt; 2");
+        assert_eq!(result.removed, vec![(String::from("<marquee> tag"), 1), (String::from("<blink> tag"), 1)]);
+    }
+}
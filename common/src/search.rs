@@ -3,76 +3,111 @@ use crate::constants::*;
 use crate::forms::*;
 use crate::forum::can_delete_thread;
 use crate::forum::can_edit_thread;
-use crate::prefab::*;
+use crate::view::Category;
+use crate::response::Error;
+use contentapi::conversion::*;
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
 
-/// Generate the complicated FullRequest for the given search. Could be a "From" if 
-/// the search included a per-page I guess...
-pub fn get_search_request(search: &PageSearch, per_page: i32) -> FullRequest
+/// Build the query for everything that limits WHICH pages are found by the search (not ordering or paging).
+/// Values which change per facet are prefixed with the given key so many of these can live in one request;
+/// requires the values and "submissions" request from get_search_request
+fn get_search_query(request: &mut FullRequest, search: &PageSearch, key: &str) -> String
 {
-    //Build up the request based on the search, then render
-    let mut request = FullRequest::new();
-    add_value!(request, "type", ContentType::PAGE);
-    add_value!(request, "systemtype", ContentType::SYSTEM);
-    add_value!(request, "forcontent", SBSValue::FORCONTENT);
-    add_value!(request, "submissions_type", SBSPageType::SUBMISSIONS);
-
-    let mut parent_request = build_request!(
-        RequestType::content, 
-        String::from("id,literalType,contentType"), 
-        String::from("literalType = @submissions_type and contentType = @systemtype")
-    ); 
-    parent_request.name = Some("submissions".to_string());
-    request.requests.push(parent_request);
-
     let mut query = String::from("contentType = @type and !notdeleted() and parentId in @submissions.id"); 
 
     if let Some(stext) = &search.search {
-        add_value!(request, "text", format!("%{}%", stext));
-        query.push_str(" and (name like @text or !keywordlike(@text))");
-    }
-
-    if let Some(category) = search.category {
-        if category != 0 {
-            add_value!(request, "categoryTag", vec![format!("{}{}", CATEGORYPREFIX, category)]);
-            query.push_str(" and !valuekeyin(@categoryTag)");
-        }
+        let text_key = format!("{}_text", key);
+        request.values.insert(text_key.clone(), format!("%{}%", stext).into());
+        query.push_str(&format!(" and (name like @{0} or !keywordlike(@{0}))", text_key));
     }
 
     if let Some(user_id) = search.user_id {
         if user_id != 0 {
-            add_value!(request, "userId", user_id);
-            query.push_str(" and createUserId = @userId");
+            let user_key = format!("{}_userId", key);
+            request.values.insert(user_key.clone(), user_id.into());
+            query.push_str(&format!(" and createUserId = @{}", user_key));
         }
     }
 
+    //Multiple categories means the page can be in ANY of them
+    let categories = search.get_categories();
+    if !categories.is_empty() {
+        let category_key = format!("{}_categoryTag", key);
+        request.values.insert(category_key.clone(), categories.iter().map(|c| format!("{}{}", CATEGORYPREFIX, c)).collect::<Vec<String>>().into());
+        query.push_str(&format!(" and !valuekeyin(@{})", category_key));
+    }
+
     // This special request generator can be used in a lot of contexts, so there's lots of optional
     // fields. The system doesn't HAVE to limit by subtype (program/resource/etc)
     if let Some(subtype) = &search.subtype 
     {
         if !subtype.is_empty() {
-            add_value!(request, "systemkey", SBSValue::SYSTEMS);
-            add_value!(request, "subtype", subtype.clone());
-            add_value!(request, "ptcsystem", format!("%{}%", PTCSYSTEM));
-            query.push_str(" and literalType = @subtype");
+            let subtype_key = format!("{}_subtype", key);
+            request.values.insert(subtype_key.clone(), subtype.clone().into());
+            query.push_str(&format!(" and literalType = @{}", subtype_key));
             //Ignore certain search criteria
             if subtype == SBSPageType::PROGRAM {
-                //MUST have a key unless the user specifies otherwise
+                //MUST have a key which hasn't been marked as removed unless the user specifies otherwise
                 if !search.removed {
                     add_value!(request, "dlkeylist", vec![SBSValue::DOWNLOADKEY]);
-                    query.push_str(" and (!valuekeyin(@dlkeylist) or !valuelike(@systemkey, @ptcsystem))");
+                    add_value!(request, "dlkeyremovedlist", vec![SBSValue::KEYREMOVED]);
+                    query.push_str(" and ((!valuekeyin(@dlkeylist) and !valuekeynotin(@dlkeyremovedlist)) or !valuelike(@systemkey, @ptcsystem))");
                 }
 
-                if search.system != ANYSYSTEM {
-                    add_value!(request, "system", format!("%{}%", search.system)); //Systems is actually a json list but this should be fine
-                    query.push_str(" and !valuelike(@systemkey, @system)");
+                //Same as categories, the page can be for ANY of the selected systems
+                let systems = search.get_systems();
+                if !systems.is_empty() {
+                    let mut system_query = Vec::new();
+                    for (i, system) in systems.iter().enumerate() {
+                        let system_key = format!("{}_system{}", key, i);
+                        request.values.insert(system_key.clone(), format!("%{}%", system).into()); //Systems is actually a json list but this should be fine
+                        system_query.push(format!("!valuelike(@systemkey, @{})", system_key));
+                    }
+                    query.push_str(&format!(" and ({})", system_query.join(" or ")));
                 }
             }
         }
     }
 
+    query
+}
+
+/// The values and "submissions" parent request every search query needs
+fn get_search_base_request() -> FullRequest
+{
+    let mut request = FullRequest::new();
+    add_value!(request, "type", ContentType::PAGE);
+    add_value!(request, "systemtype", ContentType::SYSTEM);
+    add_value!(request, "forcontent", SBSValue::FORCONTENT);
+    add_value!(request, "submissions_type", SBSPageType::SUBMISSIONS);
+
+    let mut parent_request = build_request!(
+        RequestType::content, 
+        String::from("id,literalType,contentType"), 
+        String::from("literalType = @submissions_type and contentType = @systemtype")
+    ); 
+    parent_request.name = Some("submissions".to_string());
+    request.requests.push(parent_request);
+
+    add_value!(request, "systemkey", SBSValue::SYSTEMS);
+    add_value!(request, "ptcsystem", format!("%{}%", PTCSYSTEM));
+
+    request
+}
+
+/// Generate the complicated FullRequest for the given search. Could be a "From" if 
+/// the search included a per-page I guess...
+pub fn get_search_request(search: &PageSearch, per_page: i32) -> FullRequest
+{
+    //Build up the request based on the search, then render
+    let mut request = get_search_base_request();
+    let query = get_search_query(&mut request, search, "search");
+
     let main_request = build_request!(
         RequestType::content, 
-        String::from("id,hash,parentId,contentType,literalType,values,name,description,createUserId,createDate,lastRevisionId,popScore1"), 
+        String::from("id,hash,parentId,contentType,literalType,values,keywords,name,description,createUserId,createDate,lastRevisionId,lastActionDate,popScore1"), 
         query, 
         search.order.clone(), 
         per_page,
@@ -87,15 +122,156 @@ pub fn get_search_request(search: &PageSearch, per_page: i32) -> FullRequest
     );
     request.requests.push(user_request);
 
-    //add_value!(request, "categorytype", SBSPageType::CATEGORY);
-    let mut category_request = build_request!(
-        RequestType::content,
-        String::from(CATEGORYFIELDS),
-        get_allcategory_query()
-        //format!("{} and literalType = @categorytype", CATEGORYSEARCHBASE) 
-    );
-    category_request.name = Some(String::from("categories"));
-    request.requests.push(category_request);
+    //Categories aren't asked for here: the facet counts are built from them, so the search page fetches 
+    //them before building this request (see add_search_facets)
+    request
+}
+
+pub const SEARCHCOUNTFIELDS: &str = "specialCount,id,parentId,contentType,literalType,createUserId,name";
+
+/// Request names for each of the search facet counts
+pub struct FacetKey;
+
+impl FacetKey {
+    pub fn total() -> String { String::from("count_total") }
+    pub fn subtype(subtype: &str) -> String { format!("count_subtype_{}", subtype) }
+    pub fn system(system: &str) -> String { format!("count_system_{}", system) }
+    pub fn category(id: i64) -> String { format!("count_category_{}", id) }
+}
+
+/// Add the specialCount requests to an existing search request (from get_search_request) for the total
+/// and for each subtype, category, and system (systems only for programs). Each facet count is the count 
+/// you'd get if you searched for ONLY that value in that facet, keeping the rest of the search the same
+pub fn add_search_facets(request: &mut FullRequest, search: &PageSearch, categories: &Vec<Category>)
+{
+    let mut facets : Vec<(String, PageSearch)> = vec![(FacetKey::total(), search.clone())];
+
+    for (subtype, _) in SEARCHPAGETYPES.iter().filter(|(s,_)| !s.is_empty()) {
+        let mut facet = search.clone();
+        facet.subtype = Some(subtype.to_string());
+        facets.push((FacetKey::subtype(subtype), facet));
+    }
+
+    //Systems mean nothing outside of programs
+    if search.subtype.as_deref() == Some(SBSPageType::PROGRAM) {
+        for (system, _) in SBSSYSTEMS.iter().filter(|(s,_)| *s != ANYSYSTEM) {
+            let mut facet = search.clone();
+            facet.system = system.to_string();
+            facets.push((FacetKey::system(system), facet));
+        }
+    }
+
+    for category in categories {
+        if search.subtype.as_deref().unwrap_or("").is_empty() || search.subtype.as_deref() == Some(&category.forcontent) {
+            let mut facet = search.clone();
+            facet.category = Some(category.id.to_string());
+            facets.push((FacetKey::category(category.id), facet));
+        }
+    }
+
+    for (key, facet) in facets {
+        let query = get_search_query(request, &facet, &key);
+        let mut count_request = build_request!(
+            RequestType::content,
+            String::from(SEARCHCOUNTFIELDS),
+            query
+        );
+        count_request.name = Some(key);
+        request.requests.push(count_request);
+    }
+}
+
+/// The counts parsed out of a search request with facets. Facets which weren't requested
+/// (like systems for non-programs) simply aren't in the maps
+#[derive(Default, Debug, Clone)]
+pub struct SearchFacets {
+    pub total: i32,
+    pub subtypes: HashMap<String, i32>,
+    pub systems: HashMap<String, i32>,
+    pub categories: HashMap<i64, i32>
+}
+
+impl SearchFacets {
+    pub fn from_result(result: &RequestResult, categories: &Vec<Category>) -> Result<Self, Error> {
+        let get_count = |key: String| -> Result<Option<i32>, Error> {
+            Ok(cast_result_safe::<SpecialCount>(result, &key)?.pop().map(|c| c.specialCount))
+        };
+
+        let mut facets = SearchFacets {
+            total: get_count(FacetKey::total())?
+                .ok_or_else(|| Error::Data(String::from("Didn't get total count for search"), format!("{:?}", result)))?,
+            ..Default::default()
+        };
+
+        for (subtype, _) in SEARCHPAGETYPES {
+            if let Some(count) = get_count(FacetKey::subtype(subtype))? {
+                facets.subtypes.insert(subtype.to_string(), count);
+            }
+        }
+        for (system, _) in SBSSYSTEMS {
+            if let Some(count) = get_count(FacetKey::system(system))? {
+                facets.systems.insert(system.to_string(), count);
+            }
+        }
+        for category in categories {
+            if let Some(count) = get_count(FacetKey::category(category.id))? {
+                facets.categories.insert(category.id, count);
+            }
+        }
+
+        Ok(facets)
+    }
+}
+
+// ----------------------
+//    SAVED SEARCHES
+// ----------------------
+
+/// A named search a user saved for later, stored (as json) in a user variable. The key is 
+/// the user variable key, which isn't part of the stored json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedSearch {
+    #[serde(skip)]
+    pub key: String,
+    pub name: String,
+    pub search: PageSearch,
+    pub last_visit: DateTime<Utc>
+}
+
+impl SavedSearch {
+    pub fn from_variable(variable: UserVariable) -> Result<Self, Error> {
+        let key = variable.key.ok_or_else(|| Error::Other(String::from("User variable missing key!")))?;
+        let value = variable.value.ok_or_else(|| Error::Other(format!("User variable {} missing value!", key)))?;
+        let mut saved = serde_json::from_str::<SavedSearch>(&value)?;
+        saved.key = key;
+        Ok(saved)
+    }
+    pub fn new_key() -> String {
+        format!("{}{}", SAVEDSEARCHPREFIX, crate::random_id("search"))
+    }
+}
+
+pub fn get_saved_search_countkey(index: usize) -> String { format!("savedcount_{}", index) }
+
+/// A request for the amount of pages created since each saved search was last visited. The
+/// counts are keyed by get_saved_search_countkey, where the index is the index into saved
+pub fn get_saved_search_count_request(saved: &[SavedSearch]) -> FullRequest
+{
+    let mut request = get_search_base_request();
+
+    for (index, saved_search) in saved.iter().enumerate() {
+        let key = get_saved_search_countkey(index);
+        let since_key = format!("{}_since", key);
+        let query = get_search_query(&mut request, &saved_search.search, &key);
+        request.values.insert(since_key.clone(), saved_search.last_visit.to_rfc3339().into());
+        let mut count_request = build_request!(
+            RequestType::content,
+            format!("{},createDate", SEARCHCOUNTFIELDS),
+            format!("{} and createDate > @{}", query, since_key)
+        );
+        count_request.name = Some(key);
+        request.requests.push(count_request);
+    }
 
     request
 }
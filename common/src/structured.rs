@@ -0,0 +1,122 @@
+use contentapi::*;
+use serde_json::{json, Value};
+
+use crate::constants::*;
+use crate::view::*;
+use crate::LinkConfig;
+
+//Schema.org structured data (JSON-LD) for the pages search engines care about. Everything
+//linked here must be absolute, so all the site links go through links.absolute
+
+pub const SCHEMACONTEXT: &str = "https://schema.org";
+
+//Votes are just up or down, but ratings want a scale. Map the upvote ratio onto 1-5
+const RATINGWORST: i64 = 1;
+const RATINGBEST: i64 = 5;
+
+/// The json for a ld+json script tag. User text ends up in here, so make sure it can't close the tag early
+pub fn render_json_ld(data: &Value) -> String {
+    serde_json::to_string(data).unwrap_or_default().replace("</", "<\\/")
+}
+
+pub fn person(links: &LinkConfig, user: &User) -> Value {
+    json!({
+        "@type": "Person",
+        "name": user.username,
+        "url": links.absolute(&links.user(user))
+    })
+}
+
+/// The full Person for a user's own page (with the context, since it's the top level item)
+pub fn user_person(links: &LinkConfig, user: &User, description: &str) -> Value {
+    let mut result = person(links, user);
+    result["@context"] = json!(SCHEMACONTEXT);
+    result["image"] = json!(links.absolute(&links.image(&user.avatar, &forms::QueryImage::avatar(200))));
+    if !description.is_empty() {
+        result["description"] = json!(description);
+    }
+    result
+}
+
+/// Programs are software applications. Needs the values and engagement fields to get everything
+pub fn software_application(links: &LinkConfig, program: &Content, author: Option<&User>, description: &str) -> Value {
+    let mut result = json!({
+        "@context": SCHEMACONTEXT,
+        "@type": "SoftwareApplication",
+        "name": program.name.clone().unwrap_or_default(),
+        "url": links.absolute(&links.forum_thread(program)),
+        "applicationCategory": "GameApplication"
+    });
+
+    let systems = get_systems(program).into_iter()
+        .filter(|s| s != ANYSYSTEM)
+        .filter_map(|s| get_sbs_system_title(&s).map(String::from))
+        .collect::<Vec<String>>();
+    if !systems.is_empty() {
+        result["operatingSystem"] = json!(systems.join(", "));
+    }
+    if let Some(version) = program.values.as_ref().and_then(|v| v.get(SBSValue::VERSION)).and_then(|v| v.as_str()) {
+        if !version.is_empty() {
+            result["softwareVersion"] = json!(version);
+        }
+    }
+    if !description.is_empty() {
+        result["description"] = json!(description);
+    }
+    if let Some(hash) = get_thumbnail_hash(program) {
+        result["image"] = json!(links.absolute(&links.image(&hash, &forms::QueryImage::default())));
+    }
+    if let Some(author) = author {
+        result["author"] = person(links, author);
+    }
+    if let Some(date) = program.createDate {
+        result["datePublished"] = json!(date.to_rfc3339());
+    }
+
+    let (upvotes, downvotes) = get_votes(program);
+    let total = upvotes + downvotes;
+    if total > 0 {
+        let rating = RATINGWORST as f64 + (RATINGBEST - RATINGWORST) as f64 * upvotes as f64 / total as f64;
+        result["aggregateRating"] = json!({
+            "@type": "AggregateRating",
+            "ratingValue": format!("{:.1}", rating),
+            "ratingCount": total,
+            "bestRating": RATINGBEST,
+            "worstRating": RATINGWORST
+        });
+    }
+
+    result
+}
+
+/// Forum threads; the text is whatever we know of the first post (it's not always on the page)
+pub fn discussion_forum_posting(links: &LinkConfig, thread: &Content, author: Option<&User>, text: &str) -> Value {
+    let mut result = json!({
+        "@context": SCHEMACONTEXT,
+        "@type": "DiscussionForumPosting",
+        "headline": thread.name.clone().unwrap_or_default(),
+        "url": links.absolute(&links.forum_thread(thread))
+    });
+
+    if !text.is_empty() {
+        result["text"] = json!(text);
+    }
+    if let Some(author) = author {
+        result["author"] = person(links, author);
+    }
+    if let Some(date) = thread.createDate {
+        result["datePublished"] = json!(date.to_rfc3339());
+    }
+    if let Some(date) = thread.lastActionDate {
+        result["dateModified"] = json!(date.to_rfc3339());
+    }
+    if let Some(comments) = thread.commentCount {
+        result["interactionStatistic"] = json!({
+            "@type": "InteractionCounter",
+            "interactionType": "https://schema.org/CommentAction",
+            "userInteractionCount": comments
+        });
+    }
+
+    result
+}
@@ -67,24 +67,115 @@ pub fn get_systems(content: &Content) -> Vec<String>
     return result;
 }
 
+/// The (upvotes, downvotes) on this content. Needs the engagement field
+pub fn get_votes(content: &Content) -> (i64, i64)
+{
+    let votes = content.engagement.as_ref().and_then(|e| e.get(VOTETYPE));
+    let count = |vote: &str| votes.and_then(|v| v.get(vote)).copied().unwrap_or(0);
+    (count(UPVOTE), count(DOWNVOTE))
+}
+
+/// Keys are case insensitive and people love to paste them with spaces, so store them
+/// all the same way: uppercase with no whitespace anywhere
+pub fn normalize_key(key: &str) -> String
+{
+    key.split_whitespace().collect::<String>().to_ascii_uppercase()
+}
+
+/// A like pattern matching the (normalized) key however it was stored: any case, with anything around or
+/// between the characters. Narrow down what it finds with normalize_key
+pub fn key_search_pattern(key: &str) -> String
+{
+    let mut pattern = String::from("%");
+    for c in key.chars() {
+        pattern.push(c);
+        pattern.push('%');
+    }
+    pattern
+}
+
+/// Check the (already normalized) key. There's no official list of what keys look like on each system, so
+/// this only makes sure it's something that could be typed in. Returns the list of problems, which is empty
+/// if the key is fine
+pub fn get_key_format_errors(key: &str) -> Vec<String>
+{
+    let mut errors = Vec::new();
+
+    if key.is_empty() {
+        errors.push(String::from("Key is required!"));
+    }
+    else if !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        errors.push(format!("Key '{}' can only contain letters and numbers", key));
+    }
+
+    errors
+}
+
+/// The date a key was marked removed, as it's stored (YYYY-MM-DD). Nothing (or nothing but whitespace) means today
+pub fn parse_key_removed_date(date: Option<&str>) -> Result<String, String>
+{
+    match date.map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .map_err(|_| format!("Key removal date '{}' isn't a date (use YYYY-MM-DD)", date)),
+        None => Ok(chrono::Utc::now().format("%Y-%m-%d").to_string())
+    }
+}
+
+/// If the download key on this content was marked as removed (dead), get the date it was removed
+pub fn get_key_removed(content: &Content) -> Option<String>
+{
+    content.get_value_string(SBSValue::KEYREMOVED)
+}
+
+/// How related the candidate is to the given page, 0 meaning not at all. Shared keywords and categories
+/// are the best signal, while the same author or systems just nudge things around
+pub fn get_related_score(page: &Content, candidate: &Content) -> i32
+{
+    let mut score = 0;
+
+    if let (Some(keywords), Some(candidate_keywords)) = (&page.keywords, &candidate.keywords) {
+        score += 3 * keywords.iter().filter(|k| candidate_keywords.iter().any(|ck| ck.eq_ignore_ascii_case(k))).count() as i32;
+    }
+
+    let candidate_categories = get_tagged_categories(candidate);
+    score += 2 * get_tagged_categories(page).iter().filter(|c| candidate_categories.contains(c)).count() as i32;
+
+    if page.createUserId.is_some() && page.createUserId == candidate.createUserId {
+        score += 2;
+    }
+
+    let candidate_systems = get_systems(candidate);
+    score += get_systems(page).iter().filter(|s| candidate_systems.contains(s)).count() as i32;
+
+    score
+}
+
 #[derive(Debug)]
 pub struct Category {
     pub id: i64,
     pub name: String,
-    pub forcontent: String
+    pub forcontent: String,
+    pub order: i64,     //Lower goes first; categories without an order are all 0
+    pub hidden: bool    //Hidden categories can't be picked for pages anymore, but old tags stay
 }
 
+/// Categories sorted by their order (then name). Hidden categories are still included
 pub fn map_categories(categories: Vec<Content>) -> Vec<Category>
 {
-    categories.into_iter().map(|c| {
+    let mut result = categories.into_iter().map(|c| {
+        let values = c.values.unwrap_or_default();
         Category {
             id: c.id.unwrap_or(0),
             name: c.name.unwrap_or_else(|| String::from("")), //Only evaluated on failure
-            forcontent: c.values
-                .and_then(|v| v.get(SBSValue::FORCONTENT).and_then(|v2| v2.as_str()).and_then(|v3| Some(String::from(v3))))
-                .unwrap_or_else(|| String::from(""))
+            forcontent: values.get(SBSValue::FORCONTENT).and_then(|v| v.as_str()).map(String::from)
+                .unwrap_or_else(|| String::from("")),
+            order: values.get(SBSValue::ORDER).and_then(|v| v.as_i64()).unwrap_or(0),
+            hidden: values.get(SBSValue::HIDDEN).and_then(|v| v.as_bool()).unwrap_or(false)
         }
-    }).collect::<Vec<Category>>()
+    }).collect::<Vec<Category>>();
+    result.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.name.cmp(&b.name)));
+    result
 }
 
 
@@ -206,4 +297,44 @@ pub fn map_content(content: Vec<Content>) -> HashMap<i64, Content> {
 /// Convert a vector of messages into a hashmap (id is key)
 pub fn map_messages(messages: Vec<Message>) -> HashMap<i64, Message> {
     messages.into_iter().map(|u| (u.id.unwrap_or_else(|| 0), u)).collect::<HashMap<i64, Message>>()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_normalized() {
+        assert_eq!(normalize_key("abc123"), "ABC123");
+        assert_eq!(normalize_key("  4aB 3dE\tk9\n"), "4AB3DEK9");
+        assert_eq!(normalize_key("   "), "");
+        assert_eq!(normalize_key(&normalize_key("x y z")), "XYZ");
+    }
+
+    #[test]
+    fn key_formats() {
+        assert!(get_key_format_errors("4ABC3DEF").is_empty());
+        assert!(get_key_format_errors("E2X5").is_empty());
+        assert_eq!(get_key_format_errors(""), vec![String::from("Key is required!")]);
+        assert_eq!(get_key_format_errors("AB-CD").len(), 1);
+        assert_eq!(get_key_format_errors("ABCÉ").len(), 1);
+        assert_eq!(get_key_format_errors(&normalize_key("abc def")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn key_search_finds_unnormalized_keys() {
+        assert_eq!(key_search_pattern("4AB"), "%4%A%B%");
+        assert_eq!(key_search_pattern(""), "%");
+    }
+
+    #[test]
+    fn key_removed_dates() {
+        assert_eq!(parse_key_removed_date(Some(" 2019-03-07 ")), Ok(String::from("2019-03-07")));
+        assert_eq!(parse_key_removed_date(Some("2019-3-7")), Ok(String::from("2019-03-07")));
+        assert!(parse_key_removed_date(Some("2019-02-30")).is_err());
+        assert!(parse_key_removed_date(Some("yesterday")).is_err());
+        assert!(parse_key_removed_date(Some("2019-03-07' or 1=1")).is_err());
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(parse_key_removed_date(None), Ok(today.clone()));
+        assert_eq!(parse_key_removed_date(Some("  ")), Ok(today));
+    }
+}
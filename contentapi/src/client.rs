@@ -0,0 +1,389 @@
+//! The connection to contentapi shared by every request: one pooled hyper client, how long to wait on it,
+//! how often to retry, and a circuit breaker. When the backend goes down or gets slow, the breaker trips
+//! after enough failures in a row and every call fails immediately for a while instead of every page
+//! hanging until its timeout. After the cooldown one call is let through to see if the backend is back.
+//! The api can be http or https; https is verified against the system's certificates unless you give it
+//! your own bundle (say, for a backend with a self-signed certificate), and can present a client certificate.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+
+pub type HyperClient = hyper::client::Client<HttpsConnector<HttpConnector>>;
+
+/// Where the certificates for talking to the api over https come from. Nothing here matters for an http api
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// Pem file with the certificate authorities to trust instead of the system's
+    pub ca_bundle: Option<PathBuf>,
+    /// Pem files with the certificate (chain) and private key to identify ourselves with, if the api wants that
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>
+}
+
+/// How hard to try talking to the api before giving up
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// How long a single call (including reading the body) can take
+    pub timeout: Duration,
+    /// How many more times calls which are safe to repeat are tried when the backend is unreachable
+    pub retries: u32,
+    /// The first retry waits around this long, doubling each time after
+    pub retry_delay: Duration,
+    /// How many failed calls in a row trip the breaker (0 to never trip it)
+    pub breaker_failures: u32,
+    /// How long the breaker stays tripped before letting a call through to check on the backend
+    pub breaker_cooldown: Duration,
+    pub tls: TlsOptions
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            retry_delay: Duration::from_millis(100),
+            breaker_failures: 5,
+            breaker_cooldown: Duration::from_secs(30),
+            tls: TlsOptions::default()
+        }
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<rustls_pemfile::Item>, String> {
+    let file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))
+}
+
+/// The rustls setup for the given options. Files that are missing or have nothing usable in them are errors
+/// rather than silently falling back, since that would only show up later as every page failing
+fn tls_config(options: &TlsOptions) -> Result<rustls::ClientConfig, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    match &options.ca_bundle {
+        Some(path) => {
+            let certs: Vec<Vec<u8>> = read_pem(path)?.into_iter()
+                .filter_map(|item| match item { rustls_pemfile::Item::X509Certificate(cert) => Some(cert), _ => None })
+                .collect();
+            let (added, _) = roots.add_parsable_certificates(&certs);
+            if added == 0 {
+                return Err(format!("No usable certificates in {}", path.display()));
+            }
+        },
+        None => {
+            //An http only api doesn't need any of these, so not finding them isn't fatal
+            match rustls_native_certs::load_native_certs() {
+                Ok(certs) => {
+                    let certs: Vec<Vec<u8>> = certs.into_iter().map(|c| c.0).collect();
+                    roots.add_parsable_certificates(&certs);
+                },
+                Err(error) => println!("WARN: couldn't load system certificates, https to the api won't work: {}", error)
+            }
+        }
+    }
+
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    match (&options.client_cert, &options.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs: Vec<rustls::Certificate> = read_pem(cert_path)?.into_iter()
+                .filter_map(|item| match item { rustls_pemfile::Item::X509Certificate(cert) => Some(rustls::Certificate(cert)), _ => None })
+                .collect();
+            if certs.is_empty() {
+                return Err(format!("No certificates in {}", cert_path.display()));
+            }
+            let key = read_pem(key_path)?.into_iter()
+                .find_map(|item| match item {
+                    rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                    _ => None
+                })
+                .ok_or_else(|| format!("No private key in {}", key_path.display()))?;
+            builder.with_client_auth_cert(certs, key).map_err(|e| format!("Bad client certificate: {}", e))
+        },
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err(String::from("A client certificate needs both the certificate and the key"))
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// The single call let through after the cooldown is out; nothing else goes until it comes back (or is dropped)
+    probing: bool
+}
+
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>
+}
+
+impl CircuitBreaker {
+    /// Whether a call can go out now, and if so, whether it's the probe
+    fn allow(&self) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => Some(false),
+            Some(until) if Instant::now() >= until && !state.probing => {
+                state.probing = true;
+                Some(true)
+            },
+            Some(_) => None
+        }
+    }
+
+    /// The probe never finished (say, the page asking was closed), so let the next call try instead
+    fn abandon_probe(&self) {
+        self.state.lock().unwrap().probing = false;
+    }
+
+    fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            println!("Backend is reachable again, closing circuit breaker");
+        }
+        *state = BreakerState::default();
+    }
+
+    fn failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.probing || (state.open_until.is_none() && state.failures >= self.threshold) {
+            if !state.probing {
+                println!("Backend failed {} calls in a row, opening circuit breaker for {:?}", state.failures, self.cooldown);
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+            state.probing = false;
+        }
+    }
+}
+
+/// Permission from the breaker for one call. Say how it went with `record`; if it's dropped without that
+/// (the request was cancelled partway), a probe gives up its turn so the breaker isn't stuck waiting on it
+pub struct CallPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool
+}
+
+impl CallPermit<'_> {
+    /// Report how the call went: only failures that mean the backend is down or overloaded count against it
+    pub fn record(mut self, healthy: bool) {
+        self.recorded = true;
+        if healthy { self.breaker.success() } else { self.breaker.failure() }
+    }
+}
+
+impl Drop for CallPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.abandon_probe();
+        }
+    }
+}
+
+/// Everything about the api connection that outlives a single request. Make one and share it
+pub struct ApiClient {
+    pub api_url: String,
+    pub options: ClientOptions,
+    client: HyperClient,
+    breaker: CircuitBreaker
+}
+
+impl ApiClient {
+    /// Fails if the certificate files in the tls options can't be used
+    pub fn new(api_url: String, options: ClientOptions) -> Result<Self, String> {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls_config(&options.tls)?)
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Ok(Self {
+            api_url,
+            client: hyper::client::Client::builder().build(connector),
+            breaker: CircuitBreaker {
+                threshold: options.breaker_failures,
+                cooldown: options.breaker_cooldown,
+                state: Mutex::new(BreakerState::default())
+            },
+            options
+        })
+    }
+
+    pub fn hyper(&self) -> &HyperClient {
+        &self.client
+    }
+
+    /// Permission for a call to go out right now; None means the breaker is tripped
+    pub fn allow(&self) -> Option<CallPermit<'_>> {
+        self.breaker.allow().map(|probe| CallPermit { breaker: &self.breaker, probe, recorded: false })
+    }
+
+    /// How long to wait before the given retry (starting at 1): exponential, with up to half again of jitter
+    /// so a pile of requests that failed together don't all come back at the same moment
+    pub fn retry_wait(&self, retry: u32) -> Duration {
+        let base = self.options.retry_delay * 2u32.saturating_pow(retry.saturating_sub(1));
+        base + base.mul_f64(fastrand::f64() * 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The fixtures are throwaway self-signed certificates, made with:
+    //  openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -keyout client.key -out client.pem \
+    //    -days 36500 -subj "/CN=sbs-test-client" -addext "basicConstraints=critical,CA:FALSE"
+    //(ca.pem is the same, minus the key)
+    fn fixture(name: &str) -> Option<PathBuf> {
+        Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures").join(name))
+    }
+
+    fn options(ca_bundle: Option<PathBuf>, client_cert: Option<PathBuf>, client_key: Option<PathBuf>) -> TlsOptions {
+        TlsOptions { ca_bundle, client_cert, client_key }
+    }
+
+    #[test]
+    fn tls_accepts_a_valid_setup() {
+        assert!(tls_config(&options(fixture("ca.pem"), None, None)).is_ok());
+        let config = tls_config(&options(fixture("ca.pem"), fixture("client.pem"), fixture("client.key"))).unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+    }
+
+    #[test]
+    fn tls_rejects_a_missing_bundle() {
+        let error = tls_config(&options(fixture("nothere.pem"), None, None)).unwrap_err();
+        assert!(error.contains("nothere.pem"), "{}", error);
+        //A file with no certificates in it is just as useless
+        let error = tls_config(&options(fixture("client.key"), None, None)).unwrap_err();
+        assert!(error.starts_with("No usable certificates"), "{}", error);
+    }
+
+    #[test]
+    fn tls_rejects_a_missing_key() {
+        let error = tls_config(&options(fixture("ca.pem"), fixture("client.pem"), fixture("nothere.key"))).unwrap_err();
+        assert!(error.contains("nothere.key"), "{}", error);
+        let error = tls_config(&options(fixture("ca.pem"), fixture("client.pem"), fixture("ca.pem"))).unwrap_err();
+        assert!(error.starts_with("No private key"), "{}", error);
+    }
+
+    #[test]
+    fn tls_rejects_half_a_client_certificate() {
+        let error = tls_config(&options(fixture("ca.pem"), fixture("client.pem"), None)).unwrap_err();
+        assert_eq!(error, "A client certificate needs both the certificate and the key");
+        assert!(tls_config(&options(fixture("ca.pem"), None, fixture("client.key"))).is_err());
+        let error = tls_config(&options(fixture("ca.pem"), fixture("client.key"), fixture("client.key"))).unwrap_err();
+        assert!(error.starts_with("No certificates"), "{}", error);
+    }
+
+    fn breaker(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker { threshold, cooldown, state: Mutex::new(BreakerState::default()) }
+    }
+
+    fn fail(breaker: &CircuitBreaker) {
+        let probe = breaker.allow().expect("breaker should have let the call through");
+        CallPermit { breaker, probe, recorded: false }.record(false);
+    }
+
+    #[test]
+    fn breaker_trips_after_threshold() {
+        let breaker = breaker(3, Duration::from_secs(60));
+        fail(&breaker);
+        fail(&breaker);
+        assert_eq!(breaker.allow(), Some(false));
+        //A success in between starts the count over
+        breaker.success();
+        fail(&breaker);
+        fail(&breaker);
+        assert_eq!(breaker.allow(), Some(false));
+        fail(&breaker);
+        assert_eq!(breaker.allow(), None);
+        assert_eq!(breaker.allow(), None);
+    }
+
+    #[test]
+    fn breaker_never_trips_without_threshold() {
+        let breaker = breaker(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            fail(&breaker);
+        }
+        assert_eq!(breaker.allow(), Some(false));
+    }
+
+    #[test]
+    fn breaker_lets_one_probe_through() {
+        let breaker = breaker(1, Duration::ZERO);
+        fail(&breaker);
+        assert_eq!(breaker.allow(), Some(true));
+        //Everyone else waits on the probe
+        assert_eq!(breaker.allow(), None);
+
+        //A failed probe trips it again for another cooldown
+        CallPermit { breaker: &breaker, probe: true, recorded: false }.record(false);
+        assert_eq!(breaker.allow(), Some(true));
+
+        //A good probe closes it
+        CallPermit { breaker: &breaker, probe: true, recorded: false }.record(true);
+        assert_eq!(breaker.allow(), Some(false));
+        assert_eq!(breaker.allow(), Some(false));
+    }
+
+    #[test]
+    fn failed_probe_waits_the_cooldown_again() {
+        let breaker = breaker(1, Duration::from_millis(50));
+        fail(&breaker);
+        assert_eq!(breaker.allow(), None);
+        std::thread::sleep(Duration::from_millis(60));
+        let probe = breaker.allow();
+        assert_eq!(probe, Some(true));
+        CallPermit { breaker: &breaker, probe: true, recorded: false }.record(false);
+        assert_eq!(breaker.allow(), None);
+    }
+
+    #[test]
+    fn dropped_probe_is_abandoned() {
+        let breaker = breaker(1, Duration::ZERO);
+        fail(&breaker);
+        let permit = CallPermit { breaker: &breaker, probe: breaker.allow().unwrap(), recorded: false };
+        assert!(permit.probe);
+        assert_eq!(breaker.allow(), None);
+        drop(permit);
+        //The next call gets to be the probe instead of the breaker staying stuck
+        assert_eq!(breaker.allow(), Some(true));
+
+        //Dropping a call that isn't the probe changes nothing
+        breaker.success();
+        drop(CallPermit { breaker: &breaker, probe: breaker.allow().unwrap(), recorded: false });
+        assert_eq!(breaker.allow(), Some(false));
+    }
+
+    #[test]
+    fn retry_wait_doubles_with_jitter() {
+        let options = ClientOptions {
+            retry_delay: Duration::from_millis(100),
+            tls: options(fixture("ca.pem"), None, None),
+            ..Default::default()
+        };
+        let client = ApiClient::new(String::from("http://localhost"), options).unwrap();
+        for (retry, base) in [(1, 100), (2, 200), (3, 400), (4, 800)] {
+            for _ in 0..20 {
+                let wait = client.retry_wait(retry);
+                assert!(wait >= Duration::from_millis(base) && wait <= Duration::from_millis(base * 3 / 2), "retry {} waited {:?}", retry, wait);
+            }
+        }
+        //Retry 0 isn't a thing, but it shouldn't be less than the first
+        assert!(client.retry_wait(0) >= Duration::from_millis(100));
+        //Huge retry counts just stop growing instead of overflowing
+        let _ = client.retry_wait(u32::MAX);
+    }
+}
@@ -1,5 +1,7 @@
 use core::fmt::Debug;
 //use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -7,6 +9,7 @@ use serde::de::DeserializeOwned;
 use forms;
 
 use super::*;
+use crate::client::ApiClient;
 
 //There is some "context" that represents a current user and their client connection,
 //as well as the api endpoint to connect to. This is used to craft requests on your behalf
@@ -21,6 +24,8 @@ pub enum ApiError
     NonRequest(AboutRequest, String),   //Something not pertaining to the actual request itself happened!
     Parse(AboutRequest, String, Option<Vec<u8>>),        //Something didn't parse correctly! This is common enough to be its own error
     Network(AboutRequest, String),      //Is the API reachable? Endpoint not necessary most likely; this indicates an error beyond 404
+    Timeout(AboutRequest, Duration),    //The API is there but didn't answer in time
+    Unavailable(AboutRequest),          //The API has been failing so we didn't even try (circuit breaker is open)
     Request(AboutRequest, String, u16), //Oh something went wrong with the request itself! Probably a 400 or 500 error
     Other(String)                       //Avoid this at all costs, if you can
 }
@@ -31,6 +36,8 @@ impl ApiError {
             Self::NonRequest(_,err) => err.clone(),
             Self::Parse(_,err,_) => err.clone(),
             Self::Network(_,err) => err.clone(),
+            Self::Timeout(_,_) => String::from("The backend took too long to respond"),
+            Self::Unavailable(_) => String::from("The backend is unavailable right now"),
             Self::Request(_,err,_) => err.clone(), //May change?
             Self::Other(err) => err.clone()
         }
@@ -40,6 +47,8 @@ impl ApiError {
             Self::NonRequest(_,_) => 500,
             Self::Parse(_,_,_) => 500,
             Self::Network(_,_) => 503,
+            Self::Timeout(_,_) => 504,
+            Self::Unavailable(_) => 503,
             Self::Request(_,_,_) => 400,
             Self::Other(_) => 500
         }
@@ -58,12 +67,20 @@ impl ApiError {
                 },
             Self::Network(about,err) => 
                 format!("[{}]{} - The backend seems to be unreachable: {}", about.verb, about.endpoint, err),
+            Self::Timeout(about,timeout) => 
+                format!("[{}]{} - The backend didn't respond within {:?}", about.verb, about.endpoint, timeout),
+            Self::Unavailable(about) => 
+                format!("[{}]{} - Skipped, the backend has been failing (circuit breaker open)", about.verb, about.endpoint),
             Self::Request(about,err,api_status_code) => 
                 format!("[{}]{} - Bad request to API ({}): {}", about.verb, about.endpoint, api_status_code, err),
             Self::Other(err) =>
                 format!("Generic API error: {}", err)
         }
     }
+    /// Whether this is the backend being down or too slow, rather than something wrong with the request
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, Self::Network(_,_) | Self::Timeout(_,_) | Self::Unavailable(_))
+    }
 }
 
 impl From<Box<dyn std::error::Error>> for ApiError {
@@ -92,13 +109,6 @@ macro_rules! noreqerr {
     };
 }
 
-/// This isn't needed as often: just convert any generic error into a "network" error
-macro_rules! neterr {
-    ($result:expr, $req:ident) => {
-        $result.map_err(|e| ApiError::Network($req.clone(), e.to_string()))
-    };
-}
-
 /// This isn't needed as often: just convert any generic error into a "parse" error
 macro_rules! parseerr {
     ($result:expr, $req:ident) => {
@@ -110,10 +120,9 @@ macro_rules! parseerr {
 }
 
 //You'll want to create a new api context to make multiple requests, as it's more efficient.
-//Maybe one per request?
+//Maybe one per request? They all share the one ApiClient, which holds the actual connections
 pub struct ApiContext {
-    api_url: String,
-    client: hyper::client::Client<hyper::client::HttpConnector>,
+    client: Arc<ApiClient>,
     user_token: Option<String>,
 
     #[cfg(feature = "profiling")]
@@ -121,10 +130,9 @@ pub struct ApiContext {
 }
 
 impl ApiContext {
-    pub fn new(api_url: String, user_token: Option<String>) -> Self {
+    pub fn new(client: Arc<ApiClient>, user_token: Option<String>) -> Self {
         Self {
-            api_url, user_token,
-            client : hyper::client::Client::new(),
+            client, user_token,
 
             #[cfg(feature = "profiling")]
             profiler: onestop::OneList::<onestop::OneDuration>::new()
@@ -132,16 +140,27 @@ impl ApiContext {
     }
 
     #[cfg(feature = "profiling")]
-    pub fn new_with_profiler(api_url: String, user_token: Option<String>, profiler: onestop::OneList<onestop::OneDuration>) -> Self {
+    pub fn new_with_profiler(client: Arc<ApiClient>, user_token: Option<String>, profiler: onestop::OneList<onestop::OneDuration>) -> Self {
         Self {
-            api_url, user_token,
-            client : hyper::client::Client::new(),
+            client, user_token,
             profiler
         }
     }
 
+    /// A context for the same api but without the user, for things which must only ever see public data 
+    /// (like anything cached and shown to everyone)
+    pub fn anonymous(&self) -> Self {
+        Self {
+            user_token: None,
+            client: self.client.clone(),
+
+            #[cfg(feature = "profiling")]
+            profiler: self.profiler.clone()
+        }
+    }
+
     pub fn get_endpoint(&self, endpoint: &str) -> String {
-        format!("{}{}", self.api_url, endpoint)
+        format!("{}{}", self.client.api_url, endpoint)
     }
 
     /// All requests to the API start off the same
@@ -162,12 +181,9 @@ impl ApiContext {
     }
 
     //Once a response comes back from the API, figure out the appropriate errors or data to parse and return
-    async fn handle_response<T: DeserializeOwned>(response: hyper::Response<hyper::Body>, about: AboutRequest) -> Result<T, ApiError> {
-        let status = response.status();
+    fn handle_response<T: DeserializeOwned>(status: hyper::StatusCode, body: hyper::body::Bytes, about: AboutRequest) -> Result<T, ApiError> {
         let u_status = status.as_u16();
 
-        let body = parseerr!(hyper::body::to_bytes(response.into_body()).await, about)?;
-
         //Good status vs all the rest.
         if status.is_success() {
             //At this point, the body isn't needed anymore, since the json will have run before we
@@ -182,38 +198,116 @@ impl ApiContext {
         }
     }
 
-    //Construct a basic GET request to the given endpoint (including ?params) using the given
-    //request context. Automatically add bearer headers and all that. Errors on the appropriate
-    //status codes, message is assumed to be parsed from body
-    pub async fn basic_get_request<T: DeserializeOwned>(&self, request: AboutRequest) -> Result<T, ApiError>
+    /// Make one call to the api and read the whole response, within the timeout and only if the circuit
+    /// breaker allows it. Network errors and timeouts (and the api's gateway saying the same) count 
+    /// against the breaker; anything else that comes back means the api is up
+    async fn send_once(&self, request: &AboutRequest, method: hyper::Method, body: Option<&str>) -> Result<(hyper::StatusCode, hyper::body::Bytes), ApiError>
     {
-        let reqbuilder = self.get_request_builder(&request, hyper::Method::GET)?;
-        let req = noreqerr!(reqbuilder.body(hyper::Body::empty()), request)?;
+        let mut reqbuilder = self.get_request_builder(request, method)?;
+        let req = match body {
+            Some(body) => {
+                reqbuilder = reqbuilder.header("Content-Type", "application/json");
+                noreqerr!(reqbuilder.body(hyper::Body::from(body.to_string())), request)?
+            },
+            None => noreqerr!(reqbuilder.body(hyper::Body::empty()), request)?
+        };
+
+        #[cfg(feature = "postdump")]
+        println!("Request: {:?}", &req);
+
+        let permit = match self.client.allow() {
+            Some(permit) => permit,
+            None => return Err(ApiError::Unavailable(request.clone()))
+        };
+
+        let timeout = self.client.options.timeout;
+        let result = tokio::time::timeout(timeout, async {
+            let response = self.client.hyper().request(req).await?;
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            Ok::<_, hyper::Error>((status, body))
+        }).await;
 
         //Mapping the request error to a string is PERFECTLY ok in this library because these errors are
         //NOT from stuff like 400 or 500 statuses, they're JUST from network errors (it's localhost so
         //it should never happen, and I'm fine with funky output for the few times there are downtimes)
-        let response = neterr!(self.client.request(req).await, request)?;
-        
-        Self::handle_response(response, request).await
+        match result {
+            Ok(Ok((status, body))) => {
+                permit.record(!is_gateway_failure(status));
+                Ok((status, body))
+            },
+            Ok(Err(error)) => {
+                permit.record(false);
+                Err(ApiError::Network(request.clone(), error.to_string()))
+            },
+            Err(_) => {
+                permit.record(false);
+                Err(ApiError::Timeout(request.clone(), timeout))
+            }
+        }
+    }
+
+    /// Send the call, retrying it with backoff if it's safe to repeat (idempotent) and the backend couldn't 
+    /// be reached. Calls that change things are only ever sent once, since a timeout doesn't mean it didn't happen
+    async fn send<T: DeserializeOwned>(&self, request: AboutRequest, method: hyper::Method, body: Option<String>, idempotent: bool) -> Result<T, ApiError>
+    {
+        let retries = if idempotent { self.client.options.retries } else { 0 };
+        let mut retry = 0;
+
+        loop {
+            let result = self.send_once(&request, method.clone(), body.as_deref()).await;
+            if is_retryable(&result) && retry < retries {
+                retry += 1;
+                tokio::time::sleep(self.client.retry_wait(retry)).await;
+                continue;
+            }
+            let (status, body) = result?;
+            return Self::handle_response(status, body, request);
+        }
+    }
+
+    //Construct a basic GET request to the given endpoint (including ?params) using the given
+    //request context. Automatically add bearer headers and all that. Errors on the appropriate
+    //status codes, message is assumed to be parsed from body. Retried when the backend is unreachable
+    pub async fn basic_get_request<T: DeserializeOwned>(&self, request: AboutRequest) -> Result<T, ApiError>
+    {
+        self.send(request, hyper::Method::GET, None, true).await
     }
 
     //Construct a basic POST request to the given endpoint (including ?params) using the given
     //request context. Automatically add bearer headers and all that
     pub async fn basic_post_request<U: Serialize+Debug, T: DeserializeOwned>(&self, request: AboutRequest, data: &U) -> Result<T, ApiError>
     {
-        let reqbuilder = self.get_request_builder(&request, hyper::Method::POST)?
-            .header("Content-Type", "application/json");
         let json = noreqerr!(serde_json::ser::to_string(data), request)?; //Even though this is serde, it's not a parse error because it's before the request
-        let req = noreqerr!(reqbuilder.body(hyper::Body::from(json)), request)?; 
+        self.send(request, hyper::Method::POST, Some(json), false).await
+    }
 
-        #[cfg(feature = "postdump")]
-        println!("Request: {:?}", &req);
+    //Same as basic_post_request, but for POSTs that only read (like /request), so it can be retried
+    pub async fn basic_post_read_request<U: Serialize+Debug, T: DeserializeOwned>(&self, request: AboutRequest, data: &U) -> Result<T, ApiError>
+    {
+        let json = noreqerr!(serde_json::ser::to_string(data), request)?;
+        self.send(request, hyper::Method::POST, Some(json), true).await
+    }
 
-        let response = self.client.request(req).await
-            .map_err(|e| ApiError::Network(request.clone(), e.to_string()))?;
+    //Same as basic_get_request but with the DELETE verb (nothing is sent)
+    pub async fn basic_delete_request<T: DeserializeOwned>(&self, request: AboutRequest) -> Result<T, ApiError>
+    {
+        self.send(request, hyper::Method::DELETE, None, false).await
+    }
+}
+
+/// The statuses a proxy in front of the api gives when it can't reach it or it's overloaded
+fn is_gateway_failure(status: hyper::StatusCode) -> bool {
+    matches!(status.as_u16(), 502..=504)
+}
 
-        Self::handle_response(response, request).await
+/// Whether trying the call again could go differently: the backend couldn't be reached (or didn't answer
+/// in time). Everything else is an answer, even if it's an error, and the breaker being open is on purpose
+fn is_retryable(result: &Result<(hyper::StatusCode, hyper::body::Bytes), ApiError>) -> bool {
+    match result {
+        Ok((status, _)) => is_gateway_failure(*status),
+        Err(ApiError::Network(_,_)) | Err(ApiError::Timeout(_,_)) => true,
+        Err(_) => false
     }
 }
 
@@ -231,8 +325,11 @@ macro_rules! make_get_endpoint {
 
 macro_rules! make_post_endpoint {
     ($name:ident<$intype:ty,$type:ty>($endpoint:literal)) => {
+        make_post_endpoint!{$name<$intype,$type>($endpoint), basic_post_request}
+    };
+    ($name:ident<$intype:ty,$type:ty>($endpoint:literal), $sender:ident) => {
         pub async fn $name(&self, data: &$intype) -> Result<$type, ApiError> {
-            self.basic_post_request(AboutRequest{ 
+            self.$sender(AboutRequest{ 
                 endpoint: String::from($endpoint),
                 verb: String::from("POST"),
                 post_data: Some(format!("{:#?}", data))
@@ -278,7 +375,7 @@ impl ApiContext
     make_post_endpoint!{post_email_recover<String,bool>("/user/sendpasswordrecovery")}
     make_post_endpoint!{post_register_confirm<forms::RegisterConfirm,String>("/user/confirmregistration")}
     make_post_endpoint!{post_usersensitive<forms::UserSensitive,String>("/user/privatedata")} //Returns token now
-    make_post_endpoint!{post_request<FullRequest,RequestResult>("/request")}
+    make_post_endpoint!{post_request<FullRequest,RequestResult>("/request"), basic_post_read_request}
     make_post_endpoint!{post_userupdate<User,User>("/write/user")}
     //make_post_endpoint!{post_content<Content,Content>("/write/content")}
     make_post_endpoint!{post_message<Message,Message>("/write/message")}
@@ -326,6 +423,25 @@ impl ApiContext
         }, &engagement.to_string()).await
     }
 
+    /// Set the user variable with the given key for the current user, creating it if it doesn't exist
+    pub async fn post_uservariable(&self, key: &str, value: &str) -> Result<String, ApiError>
+    {
+        self.basic_post_request(AboutRequest{ 
+            endpoint: format!("/user/variable/{}", key),
+            verb: String::from("POST"),
+            post_data: Some(value.to_string()), 
+        }, &value.to_string()).await
+    }
+
+    pub async fn delete_uservariable(&self, key: &str) -> Result<String, ApiError>
+    {
+        self.basic_delete_request(AboutRequest{ 
+            endpoint: format!("/user/variable/{}", key),
+            verb: String::from("DELETE"),
+            post_data: None, 
+        }).await
+    }
+
     /// This MAY OR MAY NOT profile depending on your featureset!
     pub async fn post_request_profiled_opt(&mut self, request: &FullRequest, _name: &str) -> Result<RequestResult, ApiError> 
     {
@@ -421,4 +537,37 @@ impl ApiContext
 //    
 //    result
 //
-//}
\ No newline at end of file
+//}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::StatusCode;
+
+    fn about() -> AboutRequest {
+        AboutRequest { verb: String::from("GET"), endpoint: String::from("/status"), post_data: None }
+    }
+
+    #[test]
+    fn gateway_failures() {
+        for status in [502, 503, 504] {
+            assert!(is_gateway_failure(StatusCode::from_u16(status).unwrap()), "{}", status);
+        }
+        for status in [200, 400, 401, 404, 429, 500, 501, 505] {
+            assert!(!is_gateway_failure(StatusCode::from_u16(status).unwrap()), "{}", status);
+        }
+    }
+
+    #[test]
+    fn only_unreachable_is_retried() {
+        let response = |status: u16| Ok((StatusCode::from_u16(status).unwrap(), hyper::body::Bytes::new()));
+        assert!(is_retryable(&response(502)));
+        assert!(is_retryable(&response(504)));
+        assert!(!is_retryable(&response(200)));
+        assert!(!is_retryable(&response(500)), "the api answered, so it would answer the same again");
+        assert!(is_retryable(&Err(ApiError::Network(about(), String::from("connection refused")))));
+        assert!(is_retryable(&Err(ApiError::Timeout(about(), Duration::from_secs(10)))));
+        assert!(!is_retryable(&Err(ApiError::Unavailable(about()))), "the breaker is open, that's the point");
+        assert!(!is_retryable(&Err(ApiError::Request(about(), String::from("bad"), 400))));
+        assert!(!is_retryable(&Err(ApiError::Parse(about(), String::from("bad"), None))));
+    }
+}
@@ -8,6 +8,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+pub mod client;
 pub mod endpoints;
 pub mod forms;
 pub mod conversion;
@@ -175,6 +176,14 @@ pub struct SpecialCount
     pub specialCount: i32
 }
 
+/// How many (visible) content use the given keyword
+#[derive(Deserialize, Debug, Clone)]
+pub struct KeywordAggregate
+{
+    pub value: String,
+    pub count: i64
+}
+
 
 // ----------------------------------
 // *     VIEWS (READ AND WRITE)     *
@@ -300,6 +309,28 @@ pub struct ContentEngagement
 }
 
 
+/// A private key/value pair stored per user. You can only ever see your own
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct UserVariable
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id : Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userId: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub createDate : Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editDate : Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editCount : Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value : Option<String>,
+}
+
+
 //#[serde_with::skip_serializing_none] //MUST COME BEFORE
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 #[serde(default)]
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
 use chrono::SecondsFormat;
 use chrono::{DateTime, Utc};
+use bbscope::BBCode;
 use common::*;
+use common::feed::*;
 use common::constants::*;
 use common::render::*;
 use common::render::layout::*;
@@ -28,7 +32,12 @@ pub fn render(data: MainLayoutData, activity: Vec<SbsActivity>, query: ActivityQ
     };
     let newerlink = format!("{}/activity?{}", data.links.http_root, serde_urlencoded::to_string(prev_query).unwrap_or_default());
 
-    layout(&data, html!{
+    let meta = LayoutMeta {
+        feeds: vec![(String::from("Activity"), data.links.activity_feed())],
+        ..Default::default()
+    };
+
+    layout_with_meta(&data, meta, html!{
         (data.links.style("/forpage/activity.css"))
         (data.links.script("/forpage/activity.js"))
         section {
@@ -204,96 +213,98 @@ pub fn get_activity_request(query: &ActivityQuery, per_page: i32) -> FullRequest
 }
 
 macro_rules! getdef {
-    ($default:ident,$map:ident,$idfield:expr) => {
-        {
-            let mut this_thing = &$default;
-            if let Some(id) = &$idfield {
-                if let Some(item) = &$map.get(id) {
-                    this_thing = item;
-                }
-            }
-            this_thing
-        }
+    ($default:expr,$map:expr,$idfield:expr) => {
+        $idfield.as_ref().and_then(|id| $map.get(id)).unwrap_or(&$default)
     };
 }
 
-pub async fn get_render(mut context: PageContext, query: ActivityQuery, per_page: i32) -> Result<Response, Error>
-{
-    let request = get_activity_request(&query, per_page);
-    let response = context.api_context.post_request_profiled_opt(&request, "activity-main").await?;
+/// Everything from the activity request, which the activity items borrow from
+pub struct ActivityData {
+    pub user_activity: Vec<User>,
+    pub post_activity: Vec<Message>,
+    pub content_activity: Vec<Activity>,
+    pub users: HashMap<i64, User>,
+    pub content: HashMap<i64, Content>,
+    pub default_user: User,
+    pub default_content: Content
+}
 
-    let user_activity = cast_result_required::<User>(&response, USERACTIVITYKEY)?;
-    let post_activity = cast_result_required::<Message>(&response, POSTACTIVITYKEY)?;
-    let content_activity = cast_result_required::<Activity>(&response, ACTIVITYKEY)?;
-    let content_raw = cast_result_required::<Content>(&response, "content")?;
-    let users_raw = cast_result_required::<User>(&response, "user")?;
-    let users = map_users(users_raw);
-    let content = map_content(content_raw);
-
-    let mut result : Vec<SbsActivity> = Vec::new();
-
-    for newuser in &user_activity {
-        result.push(SbsActivity { 
-            date: newuser.createDate, 
-            user: newuser, 
-            action_text: String::from("created an account!"), 
-            activity_href: None,
-            extra_text: None
+impl ActivityData {
+    pub fn from_response(response: &RequestResult) -> Result<Self, Error> {
+        Ok(ActivityData {
+            user_activity: cast_result_required::<User>(response, USERACTIVITYKEY)?,
+            post_activity: cast_result_required::<Message>(response, POSTACTIVITYKEY)?,
+            content_activity: cast_result_required::<Activity>(response, ACTIVITYKEY)?,
+            users: map_users(cast_result_required::<User>(response, "user")?),
+            content: map_content(cast_result_required::<Content>(response, "content")?),
+            default_user: user_or_default(None),
+            default_content: content_or_default(None)
         })
     }
 
-    let default_user = user_or_default(None);
-    let default_content = content_or_default(None);
-
-    for post in &post_activity 
+    /// All the activity merged together and sorted (newest first unless going backwards with "end")
+    pub fn to_activity(&self, links: &LinkConfig, bbcode: &mut BBCode, query: &ActivityQuery, per_page: i32) -> Vec<SbsActivity<'_>>
     {
-        let this_user = getdef!(default_user, users, post.createUserId);
-        let this_content = getdef!(default_content, content, post.contentId);
-        result.push(SbsActivity { 
-            date: post.createDate.unwrap_or_default(), 
-            user: this_user,
-            action_text: String::from("posted on"), 
-            activity_href: Some((Some(context.layout_data.links.forum_post(post, &this_content)),String::from(opt_s!(this_content.name)))),
-            extra_text: Some(context.bbcode.parse_profiled_opt(opt_s!(post.text), format!("post-{}", i(&post.id))))
-        })
-    }
+        let mut result : Vec<SbsActivity> = Vec::new();
+
+        for newuser in &self.user_activity {
+            result.push(SbsActivity { 
+                date: newuser.createDate, 
+                user: newuser, 
+                action_text: String::from("created an account!"), 
+                activity_href: None,
+                extra_text: None
+            })
+        }
 
-    for activity in &content_activity 
-    {
-        let this_user = getdef!(default_user, users, activity.userId);
-        let this_content = getdef!(default_content, content, activity.contentId);
-
-        let action_text = format!("{} {}",
-            match activity.action.unwrap_or_else(||0) {
-                UserAction::CREATE => "created",
-                UserAction::UPDATE => "edited",
-                UserAction::DELETE => "deleted",
-                _ => "did SOMETHING UNKNOWN(??)"
-            },
-            {
-                //let lit_type = this_content.literalType.as_ref().and_then(|lt| Some(lt.clone())).unwrap_or_else(||String::new());
-                if this_content.literalType.as_deref() == Some(SBSPageType::PROGRAM) { "program" }
-                else if this_content.literalType.as_deref() == Some(SBSPageType::FORUMTHREAD) { "thread" }
-                else if this_content.literalType.as_deref() == Some(SBSPageType::RESOURCE) { "page" }
-                else { "content" }
-            }
-        );
-
-        result.push(SbsActivity { 
-            date: activity.date.unwrap_or_default(), 
-            user: this_user,
-            action_text,
-            activity_href: if activity.action == Some(UserAction::DELETE) {
-                Some((None, format!("{} ({})", opt_s!(this_content.hash), i(&this_content.id))))
-            } else {
-                Some((Some(context.layout_data.links.forum_thread(&this_content)), String::from(opt_s!(this_content.name))))
-            },
-            //All this is html! macro stuff is to reuse maud as an html escaper
-            extra_text: activity.message.as_ref().and_then(|m| Some(html!((m)).into_string()))
-        })
-    }
+        for post in &self.post_activity 
+        {
+            let this_user = getdef!(self.default_user, self.users, post.createUserId);
+            let this_content = getdef!(self.default_content, self.content, post.contentId);
+            result.push(SbsActivity { 
+                date: post.createDate.unwrap_or_default(), 
+                user: this_user,
+                action_text: String::from("posted on"), 
+                activity_href: Some((Some(links.forum_post(post, this_content)),String::from(opt_s!(this_content.name)))),
+                extra_text: Some(bbcode.parse_profiled_opt(opt_s!(post.text), format!("post-{}", i(&post.id))))
+            })
+        }
+
+        for activity in &self.content_activity 
+        {
+            let this_user = getdef!(self.default_user, self.users, activity.userId);
+            let this_content = getdef!(self.default_content, self.content, activity.contentId);
+
+            let action_text = format!("{} {}",
+                match activity.action.unwrap_or(0) {
+                    UserAction::CREATE => "created",
+                    UserAction::UPDATE => "edited",
+                    UserAction::DELETE => "deleted",
+                    _ => "did SOMETHING UNKNOWN(??)"
+                },
+                {
+                    //let lit_type = this_content.literalType.as_ref().and_then(|lt| Some(lt.clone())).unwrap_or_else(||String::new());
+                    if this_content.literalType.as_deref() == Some(SBSPageType::PROGRAM) { "program" }
+                    else if this_content.literalType.as_deref() == Some(SBSPageType::FORUMTHREAD) { "thread" }
+                    else if this_content.literalType.as_deref() == Some(SBSPageType::RESOURCE) { "page" }
+                    else { "content" }
+                }
+            );
+
+            result.push(SbsActivity { 
+                date: activity.date.unwrap_or_default(), 
+                user: this_user,
+                action_text,
+                activity_href: if activity.action == Some(UserAction::DELETE) {
+                    Some((None, format!("{} ({})", opt_s!(this_content.hash), i(&this_content.id))))
+                } else {
+                    Some((Some(links.forum_thread(this_content)), String::from(opt_s!(this_content.name))))
+                },
+                //All this is html! macro stuff is to reuse maud as an html escaper
+                extra_text: activity.message.as_ref().map(|m| html!((m)).into_string())
+            })
+        }
 
-    let real_activity : Vec<SbsActivity> = 
         if query.end.is_some() {
             result.sort_by(|a, b| a.date.partial_cmp(&b.date).unwrap());
             result.into_iter().take(per_page as usize).rev().collect()
@@ -302,7 +313,48 @@ pub async fn get_render(mut context: PageContext, query: ActivityQuery, per_page
             //Normal ordering, simple take
             result.sort_by(|a, b| b.date.partial_cmp(&a.date).unwrap());
             result.into_iter().take(per_page as usize).collect()
-        };
+        }
+    }
+}
+
+pub async fn get_render(mut context: PageContext, query: ActivityQuery, per_page: i32) -> Result<Response, Error>
+{
+    let request = get_activity_request(&query, per_page);
+    let response = context.api_context.post_request_profiled_opt(&request, "activity-main").await?;
+    let data = ActivityData::from_response(&response)?;
+    let real_activity = data.to_activity(&context.layout_data.links, &mut context.bbcode, &query, per_page);
 
     Ok(Response::Render(render(context.layout_data, real_activity, query)))
-}
\ No newline at end of file
+}
+
+/// The latest site activity as an atom feed
+pub async fn get_feed_render(mut context: PageContext, per_page: i32) -> Result<Response, Error>
+{
+    let query = ActivityQuery::default();
+    let request = get_activity_request(&query, per_page);
+    let response = context.api_context.post_request_profiled_opt(&request, "activity-feed").await?;
+    let data = ActivityData::from_response(&response)?;
+    let links = &context.layout_data.links;
+    let activity_link = links.absolute(&links.activity());
+
+    let entries = data.to_activity(links, &mut context.bbcode, &query, per_page).into_iter().map(|a| {
+        let link = match a.activity_href {
+            Some((Some(ref href), _)) => links.absolute(href),
+            _ => links.absolute(&links.user(a.user))
+        };
+        FeedEntry {
+            //Multiple things happen to the same link, so the id has to be more than just that
+            id: format!("{}#{}-{}", activity_link, a.user.id, a.date.timestamp_millis()),
+            title: format!("{} {} {}", a.user.username, a.action_text, a.activity_href.as_ref().map(|(_, t)| t.as_str()).unwrap_or("")).trim().to_string(),
+            link,
+            updated: a.date,
+            published: Some(a.date),
+            author: Some(FeedAuthor::from_user(links, a.user)),
+            summary: None,
+            content: a.extra_text
+        }
+    }).collect();
+
+    let feed = Feed::new(links, String::from("SmileBASIC Source activity"), &links.activity(), &links.activity_feed(), entries);
+    Ok(Response::Document(feed.render(), String::from(ATOMCONTENTTYPE)))
+}
@@ -1,14 +1,19 @@
 use std::collections::HashMap;
 
 use common::*;
-use common::constants::SBSPageType;
+use common::constants::{SBSPageType, SBSValue};
 use common::forms::AdminSearchParams;
-use common::forms::BasicPage;
+use common::forms::SystemHtmlForm;
+use common::forms::{CategoryForm, RetagForm, ForumCategoryForm};
+use common::forum::{get_category_request, CleanedPreCategory, CATEGORYKEY};
 use common::render::*;
 use common::response::*;
 use common::prefab::*;
 use common::render::layout::*;
-use common::view::map_users;
+use common::cache::RenderCacheStats;
+use common::ratelimit::ThrottledClient;
+use common::sanitize::{sanitize_html, Sanitized};
+use common::view::{map_users, map_categories, Category};
 use contentapi::conversion::cast_result_required;
 use contentapi::forms::*;
 use contentapi::*;
@@ -26,9 +31,30 @@ pub struct AdminRenderData
     pub frontpage_errors: Option<Vec<String>>,
     pub banner_errors: Option<Vec<String>>,
     pub docpage_errors: Option<Vec<String>>,
+    pub category_errors: Option<Vec<String>>,
+    pub retag_errors: Option<Vec<String>>,
+    pub retag_result: Option<String>,
+    pub categories: Vec<Category>,
+    pub category_counts: HashMap<i64, i64>,
+    pub forum_category_errors: Option<Vec<String>>,
+    pub forum_categories: Vec<CleanedPreCategory>,
     pub bans: Vec<UserBan>,
     pub logs: Vec<AdminLog>,
-    pub list_users: HashMap<i64, User>
+    pub list_users: HashMap<i64, User>,
+    pub render_cache: RenderCacheStats,
+    pub throttled: Vec<ThrottledClient>,
+    pub html_report: Option<HtmlReport>
+}
+
+/// What the sanitizer did to one of the raw html system pages, either as a preview or after saving
+pub struct HtmlReport
+{
+    /// Which of the forms this goes with (same as the html id of its header)
+    pub section: &'static str,
+    /// What the admin submitted, so a preview doesn't lose their edits
+    pub text: String,
+    pub sanitized: Sanitized,
+    pub saved: bool
 }
 
 impl AdminRenderData
@@ -44,9 +70,19 @@ impl AdminRenderData
             frontpage_errors: None,
             banner_errors: None,
             docpage_errors: None,
+            category_errors: None,
+            retag_errors: None,
+            retag_result: None,
+            categories: Vec::new(),
+            category_counts: HashMap::new(),
+            forum_category_errors: None,
+            forum_categories: Vec::new(),
             bans: Vec::new(),
             logs: Vec::new(),
-            list_users: HashMap::new()
+            list_users: HashMap::new(),
+            render_cache: RenderCacheStats::default(),
+            throttled: Vec::new(),
+            html_report: None
         }
     }
 
@@ -85,6 +121,18 @@ pub fn render(render_data: AdminRenderData, search_params: AdminSearchParams) ->
         if let Some(id) = docpage.id { docpage_id = id }
         if let Some(text) = docpage.text { docpage_text = text.clone() }
     }
+    //A preview puts back what the admin was working on rather than what's saved
+    if let Some(ref report) = render_data.html_report {
+        if !report.saved {
+            match report.section {
+                "update-frontpage" => frontpage_text = report.text.clone(),
+                "update-alert" => banner_text = report.text.clone(),
+                "update-docpage" => docpage_text = report.text.clone(),
+                _ => {}
+            }
+        }
+    }
+    let html_report = render_data.html_report;
     let data = render_data.data;
     layout(&data, html!{
         (data.links.style("/forpage/admin.css"))
@@ -96,6 +144,7 @@ pub fn render(render_data: AdminRenderData, search_params: AdminSearchParams) ->
                     hr;
                     h3 { "Registration config:" }
                     form method="POST" action={(data.links.http_root)"/admin?registrationconfig=1"} {
+                        (csrf_input(&data))
                         (errorlist(render_data.registrationconfig_errors))
                         label."inline" for="registrationconfig_enabled"{
                             span{"Allow registration:"} 
@@ -108,26 +157,196 @@ pub fn render(render_data: AdminRenderData, search_params: AdminSearchParams) ->
                         input type="submit" value="Set (NO WARNING, BE CAREFUL!)";
                     }
                     hr;
+                    h3 #"categories" { "Program categories:" }
+                    (errorlist(render_data.category_errors))
+                    p."aside" {
+                        "Lower order goes first. Hidden categories can't be picked for new pages but stay on pages that already have them. "
+                        "Deleting a category leaves its tag on pages; retag them first!"
+                    }
+                    table."categorytable" {
+                        tr { th { "Id" } th { "Name" } th { "For" } th { "Order" } th { "Hidden" } th { "Pages" } th {} }
+                        @for category in &render_data.categories {
+                            @let form_id = format!("category_{}", category.id);
+                            @let count = render_data.category_counts.get(&category.id).copied().unwrap_or(0);
+                            tr {
+                                td { (category.id) }
+                                td { input."smallinput" form=(form_id) type="text" name="name" required="" value=(category.name); }
+                                td { (category_forcontent_select(&form_id, &category.forcontent)) }
+                                td { input."tinyinput" form=(form_id) type="number" name="order" value=(category.order); }
+                                td { input form=(form_id) type="checkbox" name="hidden" value="true" checked[category.hidden]; }
+                                td { (count) }
+                                td."smallseparate" {
+                                    form #(form_id) method="POST" action={(data.links.http_root)"/admin?category=1#categories"} {
+                                        (csrf_input(&data))
+                                        input type="hidden" name="id" value=(category.id);
+                                        input type="submit" value="Save";
+                                    }
+                                    form method="POST" action={(data.links.http_root)"/admin?category=1#categories"} {
+                                        (csrf_input(&data))
+                                        input type="hidden" name="id" value=(category.id);
+                                        input type="hidden" name="name" value=(category.name);
+                                        input type="hidden" name="forcontent" value=(category.forcontent);
+                                        input type="hidden" name="delete" value="true";
+                                        input type="submit" value="Delete" data-confirmdelete={"category '" (category.name) "' (tagged on " (count) " pages)"};
+                                    }
+                                }
+                            }
+                        }
+                        tr {
+                            td { "New" }
+                            td { input."smallinput" form="category_0" type="text" name="name" required="" placeholder="Name"; }
+                            td { (category_forcontent_select("category_0", SBSPageType::PROGRAM)) }
+                            td { input."tinyinput" form="category_0" type="number" name="order" value="0"; }
+                            td { input form="category_0" type="checkbox" name="hidden" value="true"; }
+                            td {}
+                            td {
+                                form #"category_0" method="POST" action={(data.links.http_root)"/admin?category=1#categories"} {
+                                    (csrf_input(&data))
+                                    input type="hidden" name="id" value="0";
+                                    input type="submit" value="Create";
+                                }
+                            }
+                        }
+                    }
+                    h3 #"retag" { "Retag pages:" }
+                    form."smallseparate compactform" method="POST" action={(data.links.http_root)"/admin?retag=1#retag"} {
+                        (csrf_input(&data))
+                        (errorlist(render_data.retag_errors))
+                        @if let Some(result) = render_data.retag_result {
+                            p."success" { (result) }
+                        }
+                        div."inline smallseparate" {
+                            label for="retag_from" { "Move all pages from:" }
+                            select #"retag_from" name="from" {
+                                @for category in &render_data.categories {
+                                    option value=(category.id) { (category_label(category, &render_data.category_counts)) }
+                                }
+                            }
+                        }
+                        div."inline smallseparate" {
+                            label for="retag_to" { "To:" }
+                            select #"retag_to" name="to" {
+                                option value="0" { "(Just remove the category)" }
+                                @for category in &render_data.categories {
+                                    option value=(category.id) { (category_label(category, &render_data.category_counts)) }
+                                }
+                            }
+                        }
+                        input type="submit" value="Retag (NO UNDO!)";
+                    }
+                    hr;
+                    h3 #"forumcategories" { "Forum categories:" }
+                    (errorlist(render_data.forum_category_errors))
+                    p."aside" {
+                        "Lower order goes first on the forum. Categories with no order fall back to the order in the "
+                        "server config, after all the ordered ones."
+                    }
+                    table."categorytable" {
+                        tr { th { "Id" } th { "Name" } th { "Description" } th { "Order" } th { "Permissions" } th {} }
+                        @for category in &render_data.forum_categories {
+                            @let form_id = format!("forumcategory_{}", category.id);
+                            tr {
+                                td { a href=(data.links.forum_category(&category.category)) { (category.id) } }
+                                td { input."smallinput" form=(form_id) type="text" name="name" required="" value=(category.name); }
+                                td { input form=(form_id) type="text" name="description" value=(opt_s!(category.category.description)); }
+                                td { input."tinyinput" form=(form_id) type="number" name="order" value=[category.order]; }
+                                td { (forum_permission_select(&form_id, get_forum_permission_preset(&category.category))) }
+                                td {
+                                    form #(form_id) method="POST" action={(data.links.http_root)"/admin?forumcategory=1#forumcategories"} {
+                                        (csrf_input(&data))
+                                        input type="hidden" name="id" value=(category.id);
+                                        input type="submit" value="Save";
+                                    }
+                                }
+                            }
+                        }
+                        tr {
+                            td { "New" }
+                            td { input."smallinput" form="forumcategory_0" type="text" name="name" required="" placeholder="Name"; }
+                            td { input form="forumcategory_0" type="text" name="description" placeholder="Description"; }
+                            td { input."tinyinput" form="forumcategory_0" type="number" name="order"; }
+                            td { (forum_permission_select("forumcategory_0", FORUMPERMISSIONOPEN)) }
+                            td {
+                                form #"forumcategory_0" method="POST" action={(data.links.http_root)"/admin?forumcategory=1#forumcategories"} {
+                                    (csrf_input(&data))
+                                    input type="hidden" name="id" value="0";
+                                    input type="submit" value="Create";
+                                }
+                            }
+                        }
+                    }
+                    hr;
                     h3 #"update-frontpage" {"Set frontpage (HTML!):"}
                     form."editor" method="POST" action={(data.links.http_root)"/admin?frontpage=1#update-frontpage"} {
+                        (csrf_input(&data))
                         (errorlist(render_data.frontpage_errors))
                         input type="hidden" name="id" value=(frontpage_id);
                         textarea type="text" name="text"{(frontpage_text)}
-                        input type="submit" value="Update";
+                        div."inline smallseparate" {
+                            input type="submit" value="Update";
+                            button type="submit" name="preview" value="true" { "Preview" }
+                        }
+                        (html_report_render(&html_report, "update-frontpage"))
                     }
                     h3 #"update-alert" {"Set alert banner (HTML!):"}
                     form."editor" method="POST" action={(data.links.http_root)"/admin?alert=1#update-alert"} {
+                        (csrf_input(&data))
                         (errorlist(render_data.banner_errors))
                         input type="hidden" name="id" value=(banner_id);
                         textarea type="text" name="text"{(banner_text)}
-                        input type="submit" value="Update";
+                        div."inline smallseparate" {
+                            input type="submit" value="Update";
+                            button type="submit" name="preview" value="true" { "Preview" }
+                        }
+                        (html_report_render(&html_report, "update-alert"))
                     }
                     h3 #"update-docpage" {"Set Documentation preamble (HTML!):"}
                     form."editor" method="POST" action={(data.links.http_root)"/admin?docscustom=1#update-docpage"} {
+                        (csrf_input(&data))
                         (errorlist(render_data.docpage_errors))
                         input type="hidden" name="id" value=(docpage_id);
                         textarea type="text" name="text"{(docpage_text)}
-                        input type="submit" value="Update";
+                        div."inline smallseparate" {
+                            input type="submit" value="Update";
+                            button type="submit" name="preview" value="true" { "Preview" }
+                        }
+                        (html_report_render(&html_report, "update-docpage"))
+                    }
+                    hr;
+                    h3 #"rendercache" { "Render cache:" }
+                    @let cache = &render_data.render_cache;
+                    @let lookups = cache.hits + cache.disk_hits + cache.misses;
+                    table."categorytable" {
+                        tr { td { "Entries" } td { (cache.entries) " / " (cache.capacity) } }
+                        tr { td { "Memory hits" } td { (cache.hits) } }
+                        @if cache.disk_enabled {
+                            tr { td { "Disk hits" } td { (cache.disk_hits) } }
+                        }
+                        tr { td { "Misses (rendered)" } td { (cache.misses) } }
+                        tr { td { "Evictions" } td { (cache.evictions) } }
+                        @if lookups > 0 {
+                            tr { td { "Hit rate" } td { (format!("{:.1}%", 100.0 * (cache.hits + cache.disk_hits) as f64 / lookups as f64)) } }
+                        }
+                    }
+                    p."aside" { "Counts are since the server started" }
+                    hr;
+                    h3 #"throttled" { "Rate limited clients:" }
+                    @if render_data.throttled.is_empty() {
+                        p."aside" { "Nobody has been turned away in the last 10 minutes" }
+                    }
+                    @else {
+                        table."categorytable" {
+                            tr { th { "Limit" } th { "Client" } th { "Turned away" } th { "Last" } th { "Can retry in" } }
+                            @for client in &render_data.throttled {
+                                tr {
+                                    td { (client.group) }
+                                    td { (client.client) }
+                                    td { (client.rejected) }
+                                    td { (client.last_rejected.as_secs()) "s ago" }
+                                    td { @if client.retry_after.is_zero() { "now" } @else { (client.retry_after.as_secs()) "s" } }
+                                }
+                            }
+                        }
                     }
                     hr;
                     h3 #"adminlogs" { "Admin log:" }
@@ -192,6 +411,102 @@ pub fn render(render_data: AdminRenderData, search_params: AdminSearchParams) ->
     }).into_string()
 }
 
+fn category_forcontent_select(form_id: &str, current: &str) -> Markup
+{
+    html!{
+        select form=(form_id) name="forcontent" {
+            @for subtype in [SBSPageType::PROGRAM, SBSPageType::RESOURCE] {
+                option value=(subtype) selected[subtype == current] { (subtype) }
+            }
+        }
+    }
+}
+
+const FORUMPERMISSIONUNCHANGED: &str = "unchanged";
+const FORUMPERMISSIONOPEN: &str = "open";
+const FORUMPERMISSIONREADONLY: &str = "readonly";
+const FORUMPERMISSIONPRESETS: &[(&str,&str)] = &[
+    (FORUMPERMISSIONUNCHANGED, "Keep current permissions"),
+    (FORUMPERMISSIONOPEN, "Anyone can post threads"),
+    (FORUMPERMISSIONREADONLY, "Only admins can post threads")
+];
+
+/// Apply the given forum category preset from the admin page. The presets are only about whether everyone
+/// can post threads, so all they touch is the 'C' on the everyone ("0") entry; every other group and flag
+/// is kept. Categories not everyone can read (private, staff-only) can only be left unchanged
+fn apply_forum_permission_preset(permissions: &mut HashMap<String, String>, preset: &str) -> Result<(), Error>
+{
+    let create = match preset {
+        FORUMPERMISSIONUNCHANGED => return Ok(()),
+        FORUMPERMISSIONOPEN => true,
+        FORUMPERMISSIONREADONLY => false,
+        _ => return Err(Error::User(format!("Unknown permission preset {}", preset)))
+    };
+
+    match permissions.get_mut("0") {
+        Some(everyone) if everyone.contains('R') => {
+            everyone.retain(|c| c != 'C');
+            if create { everyone.insert(0, 'C'); }
+            Ok(())
+        },
+        _ => Err(Error::User(String::from("Not everyone can read this category, so its permissions can't be changed from here")))
+    }
+}
+
+fn html_report_render(report: &Option<HtmlReport>, section: &str) -> Markup
+{
+    html!{
+        @if let Some(report) = report {
+            @if report.section == section {
+                div."htmlreport" {
+                    @if report.sanitized.removed.is_empty() {
+                        p."aside" { (if report.saved { "Nothing had to be removed" } else { "Nothing will be removed" }) }
+                    }
+                    @else {
+                        p { (if report.saved { "Removed before saving:" } else { "Will be removed when saved:" }) }
+                        ul {
+                            @for (what, count) in &report.sanitized.removed {
+                                li { (what) @if *count > 1 { " (x" (count) ")" } }
+                            }
+                        }
+                    }
+                    @if !report.saved {
+                        p { "Preview:" }
+                        div."htmlpreview" { (PreEscaped(&report.sanitized.html)) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Which preset the forum category's current permissions look like (based on whether everyone can create in it).
+/// Categories everyone can't even read don't look like either, so they're shown as unchanged
+fn get_forum_permission_preset(category: &Content) -> &'static str
+{
+    match category.permissions.as_ref().and_then(|p| p.get("0")) {
+        Some(everyone) if everyone.contains('R') && everyone.contains('C') => FORUMPERMISSIONOPEN,
+        Some(everyone) if everyone.contains('R') => FORUMPERMISSIONREADONLY,
+        _ => FORUMPERMISSIONUNCHANGED
+    }
+}
+
+fn forum_permission_select(form_id: &str, current: &str) -> Markup
+{
+    html!{
+        select form=(form_id) name="permissions" {
+            @for (preset, label) in FORUMPERMISSIONPRESETS {
+                option value=(preset) selected[*preset == current] { (label) }
+            }
+        }
+    }
+}
+
+fn category_label(category: &Category, counts: &HashMap<i64, i64>) -> String
+{
+    format!("{} ({}, {} pages)", category.name, category.forcontent, counts.get(&category.id).copied().unwrap_or(0))
+}
+
 //So, usually we pass this value in from the config, but I'm rushing and this is just the admin page so it doesn't matter too much
 const PERPAGE: i64 = 100;
 
@@ -259,14 +574,27 @@ async fn get_render_data(mut context: PageContext, search: &AdminSearchParams) -
 
     //TODO: link users to bans and then actually find a way to display them!
 
-    Ok(AdminRenderData::new(
+    let categories = map_categories(get_all_categories(&mut context.api_context, None).await?);
+    let category_counts = get_category_counts(&mut context.api_context, &categories).await?;
+
+    let forum_result = context.api_context.post_request_profiled_opt(&get_category_request(None, None), "forum_categories").await?;
+    let mut forum_categories = CleanedPreCategory::from_many(cast_result_required::<Content>(&forum_result, CATEGORYKEY)?)?;
+    forum_categories.sort_by_key(|c| (c.order.is_none(), c.order.unwrap_or(0), c.name.clone()));
+
+    let mut render_data = AdminRenderData::new(
         context.layout_data,
         context.api_context.get_registrationconfig().await?,
         get_system_frontpage(&mut context.api_context).await?,
         get_system_alert(&mut context.api_context).await?,
         get_system_docscustom(&mut context.api_context).await?,
         bans, logs, map_users(users)
-    ))
+    );
+    render_data.categories = categories;
+    render_data.category_counts = category_counts;
+    render_data.forum_categories = forum_categories;
+    render_data.render_cache = context.render_cache.stats();
+    render_data.throttled = context.rate_limiter.throttled();
+    Ok(render_data)
 }
 
 async fn get_base_render_data(context: PageContext) -> Result<AdminRenderData, Error>
@@ -296,10 +624,10 @@ pub async fn post_registrationconfig(context: PageContext, form: RegistrationCon
     Ok(render_nosearch(render_data))
 }
 
-async fn to_system_content(form: BasicPage, name: String, literal_type: String) -> Result<Content, Error> {
+async fn to_system_content(form: SystemHtmlForm, name: String, literal_type: String) -> Result<Content, Error> {
     let mut content = Content::default();
     //note: the hash it autogenerated from the name (hopefully)
-    content.text = Some(form.text);
+    content.text = Some(sanitize_html(&form.text).html);
     content.id = Some(form.id);
     content.contentType = Some(ContentType::SYSTEM);
     content.name = Some(name);
@@ -309,9 +637,16 @@ async fn to_system_content(form: BasicPage, name: String, literal_type: String)
     Ok(content)
 }
 
-pub async fn post_frontpage(context: PageContext, form: BasicPage) -> Result<Response, Error>
+pub async fn post_frontpage(context: PageContext, form: SystemHtmlForm) -> Result<Response, Error>
 {
     let mut errors = Vec::new();
+    let report = HtmlReport { section: "update-frontpage", sanitized: sanitize_html(&form.text), text: form.text.clone(), saved: !form.preview };
+
+    if form.preview {
+        let mut render_data = get_base_render_data(context).await?;
+        render_data.html_report = Some(report);
+        return Ok(render_nosearch(render_data));
+    }
 
     let content = to_system_content(form, String::from("frontpage"), SBSPageType::FRONTPAGE.to_string()).await?;
 
@@ -320,13 +655,21 @@ pub async fn post_frontpage(context: PageContext, form: BasicPage) -> Result<Res
         Err(error) => { errors.push(error.to_user_string()) }
     };
     let mut render_data = get_base_render_data(context).await?;
+    if errors.is_empty() { render_data.html_report = Some(report); }
     render_data.frontpage_errors = Some(errors);
     Ok(render_nosearch(render_data))
 }
 
-pub async fn post_alert(mut context: PageContext, form: BasicPage) -> Result<Response, Error>
+pub async fn post_alert(mut context: PageContext, form: SystemHtmlForm) -> Result<Response, Error>
 {
     let mut errors = Vec::new();
+    let report = HtmlReport { section: "update-alert", sanitized: sanitize_html(&form.text), text: form.text.clone(), saved: !form.preview };
+
+    if form.preview {
+        let mut render_data = get_base_render_data(context).await?;
+        render_data.html_report = Some(report);
+        return Ok(render_nosearch(render_data));
+    }
 
     let content = to_system_content(form, String::from("alert"), SBSPageType::ALERT.to_string()).await?;
 
@@ -335,13 +678,21 @@ pub async fn post_alert(mut context: PageContext, form: BasicPage) -> Result<Res
         Err(error) => { errors.push(error.to_user_string()) }
     };
     let mut render_data = get_base_render_data(context).await?;
+    if errors.is_empty() { render_data.html_report = Some(report); }
     render_data.banner_errors = Some(errors);
     Ok(render_nosearch(render_data))
 }
 
-pub async fn post_docscustom(context: PageContext, form: BasicPage) -> Result<Response, Error>
+pub async fn post_docscustom(context: PageContext, form: SystemHtmlForm) -> Result<Response, Error>
 {
     let mut errors = Vec::new();
+    let report = HtmlReport { section: "update-docpage", sanitized: sanitize_html(&form.text), text: form.text.clone(), saved: !form.preview };
+
+    if form.preview {
+        let mut render_data = get_base_render_data(context).await?;
+        render_data.html_report = Some(report);
+        return Ok(render_nosearch(render_data));
+    }
 
     let content = to_system_content(form, String::from("docscustom"), SBSPageType::DOCSCUSTOM.to_string()).await?;
 
@@ -350,6 +701,137 @@ pub async fn post_docscustom(context: PageContext, form: BasicPage) -> Result<Re
         Err(error) => { errors.push(error.to_user_string()) }
     };
     let mut render_data = get_base_render_data(context).await?;
+    if errors.is_empty() { render_data.html_report = Some(report); }
     render_data.docpage_errors = Some(errors);
     Ok(render_nosearch(render_data))
 }
+
+/// Create, edit, or delete a program category. Existing categories are edited in place so nothing else
+/// stored on them is lost
+pub async fn post_category(context: PageContext, form: CategoryForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+
+    let result = async {
+        if form.delete {
+            context.api_context.post_delete_content(form.id).await?;
+            return Ok(());
+        }
+
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err(Error::User(String::from("Categories need a name!")));
+        }
+        if form.forcontent != SBSPageType::PROGRAM && form.forcontent != SBSPageType::RESOURCE {
+            return Err(Error::User(format!("Categories can't be for {}", form.forcontent)));
+        }
+
+        let mut content = if form.id != 0 {
+            context.api_context.get_content_by_id(form.id, "*").await?
+        }
+        else {
+            Content {
+                id: Some(0),
+                text: Some(String::new()),
+                contentType: Some(ContentType::SYSTEM),
+                literalType: Some(SBSPageType::CATEGORY.to_string()),
+                permissions: Some(make_permissions! { "0": "R" }),
+                ..Default::default()
+            }
+        };
+
+        content.name = Some(name.to_string());
+        let values = content.values.get_or_insert_with(HashMap::new);
+        values.insert(SBSValue::FORCONTENT.to_string(), form.forcontent.clone().into());
+        values.insert(SBSValue::ORDER.to_string(), form.order.into());
+        values.insert(SBSValue::HIDDEN.to_string(), form.hidden.into());
+
+        context.api_context.post_content(&content, None).await?;
+        Ok(())
+    }.await;
+
+    if let Err(error) = result {
+        errors.push(error.to_user_string());
+    }
+
+    let mut render_data = get_base_render_data(context).await?;
+    render_data.category_errors = Some(errors);
+    Ok(render_nosearch(render_data))
+}
+
+/// Move all pages from one category to another
+pub async fn post_retag(mut context: PageContext, form: RetagForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+    let mut retag_result = None;
+
+    if form.from == form.to {
+        errors.push(String::from("Can't retag a category to itself!"));
+    }
+    else {
+        match retag_pages(&mut context.api_context, form.from, form.to).await {
+            Ok(count) => { retag_result = Some(format!("Retagged {} pages", count)) },
+            Err(error) => { errors.push(error.to_user_string()) }
+        }
+    }
+
+    let mut render_data = get_base_render_data(context).await?;
+    render_data.retag_errors = Some(errors);
+    render_data.retag_result = retag_result;
+    Ok(render_nosearch(render_data))
+}
+
+/// Create or edit a forum category. Existing categories are edited in place so stickies and such aren't lost
+pub async fn post_forumcategory(context: PageContext, form: ForumCategoryForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+
+    let result = async {
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err(Error::User(String::from("Forum categories need a name!")));
+        }
+        let order = match form.order.trim() {
+            "" => None,
+            order => Some(order.parse::<i64>().map_err(|_| Error::User(format!("Order must be a number, got {}", order)))?)
+        };
+
+        let mut content = if form.id != 0 {
+            context.api_context.get_content_by_id(form.id, "*").await?
+        }
+        else {
+            Content {
+                id: Some(0),
+                text: Some(String::new()),
+                contentType: Some(ContentType::PAGE),
+                literalType: Some(SBSPageType::FORUMCATEGORY.to_string()),
+                //New categories start out readable by everyone; the preset decides the rest
+                permissions: Some(make_permissions! { "0": "R" }),
+                ..Default::default()
+            }
+        };
+
+        content.name = Some(name.to_string());
+        content.description = Some(form.description.trim().to_string());
+        match content.permissions.as_mut() {
+            Some(permissions) => apply_forum_permission_preset(permissions, &form.permissions)?,
+            None => apply_forum_permission_preset(&mut HashMap::new(), &form.permissions)? //Only ok if unchanged
+        }
+        let values = content.values.get_or_insert_with(HashMap::new);
+        match order {
+            Some(order) => { values.insert(SBSValue::ORDER.to_string(), order.into()); },
+            None => { values.remove(SBSValue::ORDER); }
+        }
+
+        context.api_context.post_content(&content, None).await?;
+        Ok(())
+    }.await;
+
+    if let Err(error) = result {
+        errors.push(error.to_user_string());
+    }
+
+    let mut render_data = get_base_render_data(context).await?;
+    render_data.forum_category_errors = Some(errors);
+    Ok(render_nosearch(render_data))
+}
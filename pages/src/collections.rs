@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use common::*;
+use common::collections::*;
+use common::forms::CollectionForm;
+use common::render::*;
+use common::render::layout::*;
+use common::render::submissions::*;
+use common::response::*;
+use common::view::*;
+use maud::*;
+
+use contentapi::*;
+use contentapi::conversion::*;
+
+/// The current user's list of collections, where they can rename, publish, or delete them
+pub fn render(data: MainLayoutData, collections: Vec<Collection>, errors: Option<Vec<String>>) -> String
+{
+    layout(&data, html!{
+        (data.links.style("/forpage/collections.css"))
+        section {
+            h1 { "Collections" }
+            @if let Some(ref user) = data.user {
+                p."aside" { "Add pages to collections from the page itself. Public collections show up on your user page." }
+                (errorlist(errors))
+                @if collections.is_empty() {
+                    p."aside" { "You don't have any collections yet!" }
+                }
+                @for collection in &collections {
+                    div."collectionitem smallseparate" {
+                        a."flatlink" href=(data.links.collection(user, &collection.key)) { (collection.name) }
+                        span."aside" { "(" (collection.pages.len()) ")" }
+                        form."compactform smallseparate inline" method="POST" action=(data.links.collections()) {
+                            (csrf_input(&data))
+                            input type="hidden" name="key" value=(collection.key);
+                            input."smallinput" type="text" name="name" required="" value=(collection.name);
+                            label."inline" {
+                                input type="checkbox" name="public" value="true" checked[collection.public];
+                                "Public"
+                            }
+                            input type="submit" value="Save";
+                        }
+                        form."compactform inline" method="POST" action=(data.links.collections()) {
+                            (csrf_input(&data))
+                            input type="hidden" name="key" value=(collection.key);
+                            input type="hidden" name="name" value=(collection.name);
+                            input type="hidden" name="delete" value="true";
+                            input type="submit" value="Delete" data-confirmdelete={"collection '" (collection.name) "'"};
+                        }
+                    }
+                }
+            }
+            @else {
+                p."error" { "You must be logged in to see your collections!" }
+            }
+        }
+    }).into_string()
+}
+
+/// A single collection, rendered just like search results
+pub fn render_collection(data: MainLayoutData, owner: User, collection: Collection, pages: Vec<Content>, users: HashMap<i64, User>) -> String
+{
+    let is_owner = data.user.as_ref().map(|u| u.id) == Some(owner.id);
+    layout(&data, html!{
+        section {
+            h1 { (collection.name) }
+            div."smallseparate aside" {
+                span { "Collection by " a."flatlink" href=(data.links.user(&owner)) { (owner.username) } }
+                @if !collection.public { span { "(private)" } }
+                @if is_owner { a."flatlink" href=(data.links.collections()) { "Manage" } }
+            }
+        }
+        section."results" {
+            @if pages.is_empty() {
+                p."aside" { "Nothing in this collection!" }
+            }
+            div."cardslist" {
+                @for page in &pages {
+                    (page_card(&data.links, page, &users))
+                }
+            }
+        }
+    }).into_string()
+}
+
+async fn get_render_internal(mut context: PageContext, errors: Option<Vec<String>>) -> Result<Response, Error>
+{
+    let collections = match context.layout_data.user {
+        Some(ref user) => prefab::get_collections(&mut context.api_context, user.id).await?,
+        None => Vec::new()
+    };
+    Ok(Response::Render(render(context.layout_data, collections, errors)))
+}
+
+pub async fn get_render(context: PageContext) -> Result<Response, Error>
+{
+    get_render_internal(context, None).await
+}
+
+/// Rename, publish/unpublish, or delete one of the current user's collections
+pub async fn post_render(mut context: PageContext, form: CollectionForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+
+    if let Some(user) = context.layout_data.user.clone() {
+        let mut collections = prefab::get_collections(&mut context.api_context, user.id).await?;
+
+        let result = async {
+            let index = collections.iter().position(|c| c.key == form.key)
+                .ok_or_else(|| Error::User(format!("Couldn't find collection {}", form.key)))?;
+
+            if form.delete {
+                let mut removed = collections.remove(index);
+                context.api_context.delete_uservariable(&removed.key).await?;
+                if removed.public {
+                    removed.public = false;
+                    prefab::publish_collection(&mut context.api_context, user.id, &removed).await?;
+                }
+                //Anything that was only in this collection isn't a favorite anymore
+                for page in removed.pages.iter().filter(|p| !collections.iter().any(|c| c.pages.contains(p))) {
+                    prefab::sync_favorite(&context.api_context, *page, &collections).await?;
+                }
+            }
+            else {
+                let name = form.name.trim();
+                if name.is_empty() {
+                    return Err(Error::User(String::from("Collections need a name!")));
+                }
+                let was_public = collections[index].public;
+                collections[index].name = name.to_string();
+                collections[index].public = form.public;
+                prefab::post_collection(&context.api_context, &collections[index]).await?;
+                if was_public || form.public {
+                    prefab::publish_collection(&mut context.api_context, user.id, &collections[index]).await?;
+                }
+            }
+
+            Ok(())
+        }.await;
+
+        if let Err(error) = result {
+            errors.push(error.to_user_string());
+        }
+    }
+    else {
+        errors.push(String::from("You must be logged in to change collections!"));
+    }
+
+    get_render_internal(context, Some(errors)).await
+}
+
+/// View a collection. Owners can always see their own collections, everyone else can only see the
+/// public ones
+pub async fn get_collection_render(mut context: PageContext, username: String, key: String) -> Result<Response, Error>
+{
+    let notfound = || Error::NotFound(String::from("Collection not found (it may be private)"));
+    let owner = context.api_context.get_user_by_username(&username, "*").await?;
+
+    let collection = if context.layout_data.user.as_ref().map(|u| u.id) == Some(owner.id) {
+        prefab::get_collections(&mut context.api_context, owner.id).await?
+    }
+    else {
+        prefab::get_public_collections(&mut context.api_context, owner.id).await?
+    }.into_iter().find(|c| c.key == key).ok_or_else(notfound)?;
+
+    let mut pages = Vec::new();
+    let mut users = HashMap::new();
+
+    if !collection.pages.is_empty() {
+        let mut request = FullRequest::new();
+        add_value!(request, "ids", collection.pages.clone());
+        request.requests.push(build_request!(
+            RequestType::content,
+            String::from(prefab::RELATEDFIELDS),
+            String::from("id in @ids and !notdeleted()")
+        ));
+        request.requests.push(build_request!(
+            RequestType::user,
+            String::from("*"),
+            String::from("id in @content.createUserId")
+        ));
+        let result = context.api_context.post_request_profiled_opt(&request, "collection").await?;
+        let mut content = map_content(cast_result_required::<Content>(&result, &RequestType::content.to_string())?);
+        users = map_users(cast_result_required::<User>(&result, &RequestType::user.to_string())?);
+        //Keep the order they were added in (newest first)
+        pages = collection.pages.iter().rev().filter_map(|id| content.remove(id)).collect();
+    }
+
+    Ok(Response::Render(render_collection(context.layout_data, owner, collection, pages, users)))
+}
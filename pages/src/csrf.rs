@@ -0,0 +1,32 @@
+use common::*;
+use common::render::layout::*;
+use common::response::*;
+use maud::*;
+
+/// Shown instead of running a POST whose csrf token doesn't match the session. It's almost always an old tab
+/// (the tokens change when you log in again or the site restarts), so say how to fix that first
+pub fn render(data: MainLayoutData) -> String {
+    let body = html! {
+        section {
+            h1 { "This form has expired" }
+            p { 
+                "The form you submitted didn't come with the right security token for your session, so nothing was done. "
+                "This usually means the page was open from before you last logged in, or from before the site was updated."
+            }
+            p { "Go back, reload the page, and try again. If you didn't submit anything, someone else's site may have tried to do it for you." }
+        }
+    };
+
+    //Widgets are iframed into other pages, so keep them small
+    if data.current_path.starts_with("/widget/") {
+        basic_skeleton(&data, html! { title { "Form expired" } }, body).into_string()
+    }
+    else {
+        layout(&data, body).into_string()
+    }
+}
+
+pub async fn get_render(context: PageContext) -> Result<Response, Error>
+{
+    Ok(Response::RenderWithStatus(render(context.layout_data), 403))
+}
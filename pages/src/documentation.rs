@@ -17,7 +17,7 @@ pub fn render(data: MainLayoutData, documentation: &Vec<Content>, docparent: Con
         (data.links.script("/forpage/forum.js"))
         section {
             @if let Some(docscustom) = docscustom {
-                (PreEscaped(opt_s!(docscustom.text)))
+                (PreEscaped(common::sanitize::clean_html(opt_s!(docscustom.text))))
             }
             (display_doctree(&data, documentation, 1))
             @if let Some(ref user) = data.user {
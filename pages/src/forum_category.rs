@@ -8,6 +8,7 @@ use contentapi::endpoints::ApiContext;
 
 use common::*;
 use common::constants::*;
+use common::feed::*;
 use common::forum::*;
 use common::render::*;
 use common::render::forum::*;
@@ -34,7 +35,12 @@ pub fn render(mut data: MainLayoutData, category: ForumCategory, path: Vec<Forum
         }
     }
 
-    layout(&data, html!{
+    let meta = LayoutMeta {
+        feeds: vec![(format!("{} (threads)", opt_s!(category.category.name)), data.links.forum_category_feed(&category.category))],
+        ..Default::default()
+    };
+
+    layout_with_meta(&data, meta, html!{
         (data.links.style("/forpage/forum.css"))
         section {
             h1 { (opt_s!(category.category.name)) }
@@ -153,4 +159,36 @@ pub async fn get_fcid_render(context: PageContext, fcid: i64, per_page: i32, pag
     Result<Response, Error> 
 {
     render_threads(context, get_category_request(None, Some(fcid)), per_page, page).await
-}
\ No newline at end of file
+}
+
+/// The most recently active threads in the category as an atom feed, each with the latest post as the content
+pub async fn get_feed_render(mut context: PageContext, hash: String, per_page: i32) -> Result<Response, Error>
+{
+    let category_result = context.api_context.post_request_profiled_opt(&get_category_request(Some(hash), None), "getcategory").await?;
+    let categories_cleaned = CleanedPreCategory::from_many(cast_result_required::<Content>(&category_result, CATEGORYKEY)?)?;
+    let category = build_categories_with_threads(&mut context.api_context, categories_cleaned, per_page, 0).await?
+        .pop().ok_or(Error::NotFound(String::from("Couldn't find that category")))?;
+
+    let links = &context.layout_data.links;
+    let entries = category.threads.iter().map(|thread| {
+        let link = links.absolute(&links.forum_thread(&thread.thread));
+        let latest = thread.posts.first();
+        FeedEntry {
+            id: link.clone(),
+            title: String::from(opt_s!(thread.thread.name)),
+            link: match latest {
+                Some(post) => links.absolute(&links.forum_post(post, &thread.thread)),
+                None => link
+            },
+            updated: latest.and_then(|p| p.createDate).or(thread.thread.lastActionDate).or(thread.thread.createDate).unwrap_or_default(),
+            published: thread.thread.createDate,
+            author: Some(FeedAuthor::from_user(links, &user_or_default(category.users.get(&thread.thread.createUserId.unwrap_or(0))))),
+            summary: None,
+            content: latest.map(|post| context.bbcode.parse_profiled_opt(opt_s!(post.text), format!("post-{}", i(&post.id))))
+        }
+    }).collect();
+
+    let feed = Feed::new(links, format!("{} - SmileBASIC Source", opt_s!(category.category.name)), 
+        &links.forum_category(&category.category), &links.forum_category_feed(&category.category), entries);
+    Ok(Response::Document(feed.render(), String::from(ATOMCONTENTTYPE)))
+}
@@ -34,6 +34,7 @@ pub fn render(data: MainLayoutData, form: ThreadForm, category_info: Option<Cont
                 h1 { (title) }
                 //NOTE: NO ACTION! These kinds of pages always post to themselves
                 form."editor" #"threadedit_form" method="POST" {
+                    (csrf_input(&data))
                     (errorlist(errors))
                     input #"threadedit_parent_id" type="hidden" name="parent_id" value=(form.parent_id);
                     label for="threadedit_title"{"Thread title:"}
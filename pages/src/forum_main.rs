@@ -69,11 +69,12 @@ pub async fn get_render(mut context: PageContext, order: &Vec<String>, show_thre
     let category_result = context.api_context.post_request_profiled_opt(&request, "categories").await?;
     let mut categories_cleaned = CleanedPreCategory::from_many(cast_result_required::<Content>(&category_result, CATEGORYKEY)?)?;
 
-    //Sort the categories by their name AGAINST the default list in the config. So, it should sort the categories
-    //by the order defined in the config, with stuff not present going at the end. Tiebreakers are resolved alphabetically
+    //Categories with an order stored on them (set from the admin page) go first, by that order. Everything else is
+    //sorted by their name AGAINST the default list in the config, with stuff not present going at the end. 
+    //Tiebreakers are resolved alphabetically
     categories_cleaned.sort_by_key(|category| {
         //Nicole made this a tuple so tiebreakers are sorted alphabetically, which is coool
-        (order.iter().position(
+        (category.order.is_none(), category.order.unwrap_or(0), order.iter().position(
             |prefix| category.name.starts_with(prefix)).unwrap_or(usize::MAX), category.name.clone())
     });
 
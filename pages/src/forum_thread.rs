@@ -1,4 +1,5 @@
 use common::*;
+use common::feed::*;
 use common::render::*;
 use common::constants::SBSPageType;
 use common::render::layout::*;
@@ -20,15 +21,26 @@ pub fn render(mut context: PageContext, config: PostsConfig) -> String {
         description : short_description(&config.thread.thread),
         image : get_thumbnail_hash(&config.thread.thread).and_then(|h| 
             Some(context.layout_data.links.image(&h, &contentapi::forms::QueryImage { size: Some(200), crop: None }))),
-        canonical: Some(context.layout_data.links.forum_thread(&config.thread.thread))
+        canonical: Some(context.layout_data.links.forum_thread(&config.thread.thread)),
+        feeds: vec![(format!("{} (posts)", opt_s!(config.thread.thread.name)), context.layout_data.links.forum_thread_feed(&config.thread.thread))],
+        og_type: String::from("article"),
+        ..Default::default()
     };
 
+    let links = &context.layout_data.links;
+    let author = config.users.get(&config.thread.thread.createUserId.unwrap_or(0));
+
+    if CARDTYPES.contains(&config.thread.thread.literalType.as_deref().unwrap_or("")) {
+        meta.oembed = Some(links.oembed(&links.absolute(&links.forum_thread(&config.thread.thread))));
+    }
+
     //If the literal type is a forumthread, we generally want to pull text from posts
     if config.thread.thread.literalType.as_deref() == Some(SBSPageType::FORUMTHREAD) {
+        let mut first_post = String::new();
         if let Some(selected_id) = config.selected_post_id {
             if let Some(post) = config.thread.posts.iter().find(|p| p.id == Some(selected_id)) {
                 meta.description = short_post(post); 
-                meta.canonical = Some(context.layout_data.links.forum_post(&post, &config.thread.thread));
+                meta.canonical = Some(links.forum_post(post, &config.thread.thread));
             }
         }
         else if let Some(start) = config.start_num {
@@ -36,9 +48,15 @@ pub fn render(mut context: PageContext, config: PostsConfig) -> String {
                 //We KNOW this is the first page, so we can actually give the post as the description!
                 if let Some(post) = config.thread.posts.get(0) {
                     meta.description = short_post(post); 
+                    first_post = meta.description.clone();
                 }
             }
         }
+        meta.structured_data = Some(common::structured::discussion_forum_posting(links, &config.thread.thread, author, &first_post));
+    }
+    else if config.thread.thread.literalType.as_deref() == Some(SBSPageType::PROGRAM) {
+        meta.og_type = String::from("website");
+        meta.structured_data = Some(common::structured::software_application(links, &config.thread.thread, author, &meta.description));
     }
 
     let main_page = render_posts(&mut context, config);
@@ -46,7 +64,7 @@ pub fn render(mut context: PageContext, config: PostsConfig) -> String {
 }
 
 async fn render_thread(mut context: PageContext, pre_request: FullRequest, per_page: i32, 
-    page: Option<i32>) -> Result<Response, Error> 
+    page: Option<i32>, related_cache: &RelatedCache) -> Result<Response, Error> 
 {
     let mut page = page.unwrap_or(1) - 1; //we assume 1-based pages
 
@@ -103,39 +121,90 @@ async fn render_thread(mut context: PageContext, pre_request: FullRequest, per_p
     if post_config.thread.thread.literalType.as_deref() == Some(SBSPageType::DOCUMENTATION) {
         post_config.docs_content = Some(get_all_documentation(&mut context.api_context).await?);
     }
+    else if post_config.thread.thread.literalType.as_deref() == Some(SBSPageType::PROGRAM) ||
+            post_config.thread.thread.literalType.as_deref() == Some(SBSPageType::RESOURCE) {
+        let cache_key = (thread_id, post_config.thread.thread.lastRevisionId.unwrap_or(0));
+        post_config.related_pages = Some(match related_cache.get(&cache_key) {
+            Some(related) => related,
+            None => {
+                //Shared by everyone who views the page, so it can only ever have what anyone could see
+                let mut anonymous = context.api_context.anonymous();
+                let related = get_related_pages(&mut anonymous, &post_config.thread.thread, RELATEDCOUNT).await?;
+                related_cache.insert(cache_key, related.clone());
+                related
+            }
+        });
+    }
     Ok(Response::Render(render(context, post_config)))
 }
 
 
 
 /// The normal endpoint for listing a thread
-pub async fn get_hash_render(context: PageContext, hash: String, per_page: i32, page: Option<i32>) -> Result<Response, Error> 
+pub async fn get_hash_render(context: PageContext, hash: String, per_page: i32, page: Option<i32>, related_cache: &RelatedCache) -> Result<Response, Error> 
 {
     render_thread(context,
         get_prepost_request(None, None, None, Some(hash)), 
-        per_page, page).await
+        per_page, page, related_cache).await
 }
 
 /// The normal endpoint for pinpointing a post
-pub async fn get_hash_postid_render(context: PageContext, hash: String, post_id: i64, per_page: i32) -> Result<Response, Error> 
+pub async fn get_hash_postid_render(context: PageContext, hash: String, post_id: i64, per_page: i32, related_cache: &RelatedCache) -> Result<Response, Error> 
 {
     render_thread(context,
         get_prepost_request(None, Some(post_id), None, Some(hash)), 
-        per_page, None).await
+        per_page, None, related_cache).await
 }
 
-pub async fn get_ftid_render(context: PageContext, ftid: i64, per_page: i32, page: Option<i32>) -> Result<Response, Error> 
+pub async fn get_ftid_render(context: PageContext, ftid: i64, per_page: i32, page: Option<i32>, related_cache: &RelatedCache) -> Result<Response, Error> 
 {
     render_thread(context,
         get_prepost_request(None, None, Some(ftid), None), 
-        per_page, page).await
+        per_page, page, related_cache).await
 }
 
 //Most old links may be to posts directly? idk
-pub async fn get_fpid_render(context: PageContext, fpid: i64, per_page: i32) -> Result<Response, Error> 
+pub async fn get_fpid_render(context: PageContext, fpid: i64, per_page: i32, related_cache: &RelatedCache) -> Result<Response, Error> 
 {
     //println!("WOW FPID: {}", fpid);
     render_thread(context,
         get_prepost_request(Some(fpid), None, None, None), 
-        per_page, None).await
+        per_page, None, related_cache).await
+}
+
+/// The newest posts in the thread as an atom feed
+pub async fn get_feed_render(mut context: PageContext, hash: String, per_page: i32) -> Result<Response, Error>
+{
+    let pre_result = context.api_context.post_request_profiled_opt(&get_prepost_request(None, None, None, Some(hash)), "prepost").await?;
+    let thread = cast_result_required::<Content>(&pre_result, THREADKEY)?.pop()
+        .ok_or(Error::NotFound(String::from("Could not find thread!")))?;
+
+    let thread_id = thread.id.ok_or(Error::Other(String::from("Thread result did not have id field?!")))?;
+    let comment_count = thread.commentCount.unwrap_or(0) as i32;
+
+    //Posts are only ever ordered oldest first, so skip to the end to get the newest
+    let post_request = get_finishpost_request(thread_id, Vec::new(), per_page, (comment_count - per_page).max(0));
+    let post_result = context.api_context.post_request_profiled_opt(&post_request, "finishpost").await?;
+    let posts = cast_result_required::<Message>(&post_result, "message")?;
+    let users = map_users(cast_result_required::<User>(&post_result, "user")?);
+
+    let links = &context.layout_data.links;
+    let entries = posts.iter().rev().map(|post| {
+        let link = links.absolute(&links.forum_post(post, &thread));
+        let user = user_or_default(users.get(&post.createUserId.unwrap_or(0)));
+        FeedEntry {
+            id: link.clone(),
+            title: format!("{} on {}", user.username, opt_s!(thread.name)),
+            link,
+            updated: post.editDate.or(post.createDate).unwrap_or_default(),
+            published: post.createDate,
+            author: Some(FeedAuthor::from_user(links, &user)),
+            summary: None,
+            content: Some(context.bbcode.parse_profiled_opt(opt_s!(post.text), format!("post-{}", i(&post.id))))
+        }
+    }).collect();
+
+    let feed = Feed::new(links, format!("{} - SmileBASIC Source", opt_s!(thread.name)), 
+        &links.forum_thread(&thread), &links.forum_thread_feed(&thread), entries);
+    Ok(Response::Document(feed.render(), String::from(ATOMCONTENTTYPE)))
 }
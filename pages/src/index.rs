@@ -9,7 +9,7 @@ pub fn render(data: MainLayoutData, page_raw: Option<String>) -> String {
         //This is the body of index
         section {
             @if let Some(page) = page_raw {
-                (PreEscaped(page))
+                (PreEscaped(common::sanitize::clean_html(&page)))
             }
             @else {
                 h1 { "Welcome to SmileBASIC Source!" }
@@ -16,7 +16,7 @@ pub fn render(data: MainLayoutData) -> String
     }
 
     layout(&data, html!{
-        style { r#"
+        style nonce=(data.csp_nonce) { r#"
             #testframe {
                 width: 100%;
                 height: 60vh;
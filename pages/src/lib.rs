@@ -12,6 +12,7 @@ pub mod widget_contentpreview;
 pub mod widget_thread;
 pub mod widget_votes;
 pub mod widget_qr;
+pub mod widget_collections;
 pub mod userhome;
 pub mod recover;
 pub mod register;
@@ -27,8 +28,17 @@ pub mod integrationtest;
 pub mod forum_edit_thread;
 pub mod forum_edit_post;
 pub mod page_edit;
+pub mod page_ptc;
 pub mod documentation;
 pub mod searchall;
+pub mod tags;
+pub mod collections;
+pub mod sitemap;
+pub mod oembed;
+pub mod widget_card;
+pub mod csrf;
+pub mod ratelimited;
+pub mod unavailable;
 
 //Email errors are weird with their true/false return. 
 macro_rules! email_errors {
@@ -15,6 +15,7 @@ pub fn render(data: MainLayoutData, login_errors: Option<Vec<String>>, recover_e
         section {
             h1{"Login"}
             form method="POST" action={(data.links.http_root)"/login"} {
+                (csrf_input(&data))
                 (errorlist(login_errors))
                 label for="login_username"{"Username:"}
                 input #"login_username" type="text" required="" name="username";
@@ -30,6 +31,7 @@ pub fn render(data: MainLayoutData, login_errors: Option<Vec<String>>, recover_e
             h2{"Password expired / forgotten?"}
             p.""{"Send an email with a temporary recovery code, which you can use to reset your password"}
             form method="POST" action={(data.links.http_root)"/login?recover=1"} {
+                (csrf_input(&data))
                 (errorlist(recover_errors))
                 label for="recover_email" {"Email"}
                 input #"recover_email" type="email" name="email" required="" value=[email];
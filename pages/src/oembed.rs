@@ -0,0 +1,102 @@
+use common::*;
+use common::prefab::*;
+use common::response::*;
+use common::view::get_thumbnail_hash;
+use contentapi::forms::QueryImage;
+use serde::{Serialize, Deserialize};
+
+//The oembed spec: https://oembed.com/. We only ever give out "rich" embeds, which are the card widget in an iframe
+
+pub const OEMBEDCONTENTTYPE: &str = "application/json; charset=utf-8";
+pub const CARDWIDTH: i32 = 400;
+pub const CARDHEIGHT: i32 = 160;
+const THUMBNAILSIZE: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct OEmbedQuery {
+    pub url: String,
+    pub maxwidth: Option<i32>,
+    pub maxheight: Option<i32>,
+    pub format: Option<String>
+}
+
+#[derive(Serialize, Debug)]
+pub struct OEmbed {
+    pub version: &'static str,
+    #[serde(rename = "type")]
+    pub oembed_type: &'static str,
+    pub title: String,
+    pub author_name: String,
+    pub author_url: String,
+    pub provider_name: &'static str,
+    pub provider_url: String,
+    pub html: String,
+    pub width: i32,
+    pub height: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_width: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_height: Option<i64>
+}
+
+/// Pull the page hash out of one of our own absolute thread urls. The scheme doesn't matter, since
+/// people paste both
+pub fn parse_thread_url(links: &LinkConfig, url: &str) -> Option<String>
+{
+    let strip_scheme = |u: &str| u.split_once("://").map(|(_, rest)| rest.to_string()).unwrap_or(u.to_string());
+    let thread_root = strip_scheme(&links.absolute(&links.forum_thread(&Default::default())));
+    let rest = strip_scheme(url);
+    let hash = rest.strip_prefix(&thread_root)?
+        .split(['/', '?', '#']).next()
+        .unwrap_or("");
+    if hash.is_empty() { None } else { Some(hash.to_string()) }
+}
+
+pub async fn get_render(mut context: PageContext, query: OEmbedQuery) -> Result<Response, Error>
+{
+    //The spec says to use exactly this code for formats we don't do
+    if let Some(ref format) = query.format {
+        if format != "json" {
+            return Ok(Response::MessageWithStatus(format!("Format '{}' not supported, only json", format), 501));
+        }
+    }
+
+    let links = &context.layout_data.links;
+    let hash = parse_thread_url(links, &query.url)
+        .ok_or_else(|| Error::NotFound(format!("Not an embeddable url: {}", query.url)))?;
+    let card = get_card_page(&mut context.api_context, &hash).await?;
+    let author = user_or_default(card.users.get(&card.page.createUserId.unwrap_or(0)));
+
+    let width = query.maxwidth.map(|w| w.min(CARDWIDTH)).unwrap_or(CARDWIDTH);
+    let height = query.maxheight.map(|h| h.min(CARDHEIGHT)).unwrap_or(CARDHEIGHT);
+    let widget = links.absolute(&links.card_widget(&card.page));
+    let title = opt_s!(card.page.name).to_string();
+    let thumbnail = get_thumbnail_hash(&card.page)
+        .map(|h| links.absolute(&links.image(&h, &QueryImage { size: Some(THUMBNAILSIZE), crop: Some(true) })));
+
+    let oembed = OEmbed {
+        version: "1.0",
+        oembed_type: "rich",
+        html: format!(r#"<iframe src="{}" width="{}" height="{}" title="{}" style="border:none" loading="lazy"></iframe>"#,
+            html_escape(&widget), width, height, html_escape(&title)),
+        title,
+        author_name: author.username.clone(),
+        author_url: links.absolute(&links.user(&author)),
+        provider_name: "SmileBASIC Source",
+        provider_url: links.absolute(&format!("{}/", links.http_root)),
+        width,
+        height,
+        thumbnail_width: thumbnail.as_ref().map(|_| THUMBNAILSIZE),
+        thumbnail_height: thumbnail.as_ref().map(|_| THUMBNAILSIZE),
+        thumbnail_url: thumbnail
+    };
+
+    Ok(Response::Document(serde_json::to_string(&oembed)?, String::from(OEMBEDCONTENTTYPE)))
+}
+
+/// Maud escapes quotes too, so this is safe for attributes
+fn html_escape(text: &str) -> String {
+    maud::html!{ (text) }.into_string()
+}
@@ -53,6 +53,7 @@ pub fn render(data: MainLayoutData, form: PageForm, mode: Option<String>, all_ca
                 h1 { (title) }
                 //NOTE: NO ACTION! These kinds of pages always post to themselves
                 form."editor" #"pageedit_form" data-mode=(real_mode) data-noupgrade method="POST" {
+                    (csrf_input(&data))
                     (errorlist(errors))
                     input #"pageedit_id" type="hidden" name="id" value=(form.id);
                     input #"pageedit_subtype" type="hidden" name="subtype" value=(form.subtype);
@@ -100,6 +101,16 @@ pub fn render(data: MainLayoutData, form: PageForm, mode: Option<String>, all_ca
                         @else {
                             label for="pageedit_key" { "Key:" }
                             input #"pageedit_key" type="text" name="key" value=(opt_s!(form.key)) required placeholder="The key for people to download your program!";
+                            div."inline smallseparate" {
+                                label for="pageedit_key_removed" { "Key removed:" }
+                                input #"pageedit_key_removed" type="checkbox" name="key_removed" value="true" checked[form.key_removed.unwrap_or(false)];
+                                label for="pageedit_key_removed_date" { "Removed on:" }
+                                input #"pageedit_key_removed_date" type="date" name="key_removed_date" value=(opt_s!(form.key_removed_date));
+                            }
+                            p."aside" { 
+                                "Keys are checked against the format for each system and against other pages. If the key no longer works "
+                                "(it was taken down from the SmileBASIC servers), mark it as removed instead of deleting it."
+                            }
                             label for="pageedit_systems" { "Systems:" }
                             input #"pageedit_systems" type="text" name="systems" value=(opt_s!(form.systems)) required placeholder="What console does this go on?";
                             details."editorinstructions" #"systems_instructions"{
@@ -168,7 +179,7 @@ pub fn render(data: MainLayoutData, form: PageForm, mode: Option<String>, all_ca
 pub async fn get_render_categories(api_context: &mut ApiContext, subtype: &str) -> Result<Vec<Category>, Error> {
     let all_categories = map_categories(get_all_categories(api_context, None).await?);
     //let cloned_subtype = String::from(subtype); //.clone();
-    Ok(all_categories.into_iter().filter(move |c| &c.forcontent == subtype).collect())
+    Ok(all_categories.into_iter().filter(move |c| c.forcontent == subtype && !c.hidden).collect())
 }
 
 pub async fn get_render_docpaths(api_context: &mut ApiContext) -> Result<Vec<String>, Error> {
@@ -213,6 +224,8 @@ pub async fn get_render(mut context: PageContext, mode: Option<String>, page_has
         }
         //Don't need to check the page type for these, we just pass them through based on if they are Some or not
         form.key = page.get_value_string(SBSValue::DOWNLOADKEY);
+        form.key_removed_date = get_key_removed(&page);
+        form.key_removed = form.key_removed_date.as_ref().map(|_| true);
         form.size = page.get_value_string(SBSValue::SIZE); 
         form.version = page.get_value_string(SBSValue::VERSION); 
         form.markup = page.get_value_string(SBSValue::MARKUP);
@@ -352,6 +365,14 @@ pub async fn construct_post_content_full(context: &mut ApiContext, form: &PageFo
         }
         if let Some(ref key) = form.key {
             values.insert(SBSValue::DOWNLOADKEY.to_string(), key.clone().into());
+            //The removed marker only makes sense alongside a key, so it's only touched when the key is
+            if form.key_removed.unwrap_or(false) {
+                let removed_date = parse_key_removed_date(form.key_removed_date.as_deref()).map_err(Error::User)?;
+                values.insert(SBSValue::KEYREMOVED.to_string(), removed_date.into());
+            }
+            else {
+                values.remove(SBSValue::KEYREMOVED);
+            }
         }
         if let Some(ref size) = form.size {
             values.insert(SBSValue::SIZE.to_string(), size.clone().into());
@@ -383,7 +404,36 @@ pub async fn construct_post_content_full(context: &mut ApiContext, form: &PageFo
     Ok(fullpage)
 }
 
-pub async fn post_render(mut context: PageContext, form: PageForm) ->
+/// Check the (already normalized) key on the form and make sure no other page has it. Keys marked as removed
+/// aren't checked, they're dead anyway; only the date they were removed on is
+pub async fn get_key_errors(context: &mut ApiContext, form: &PageForm) -> Result<Vec<String>, Error>
+{
+    let mut errors = Vec::new();
+
+    if let Some(ref key) = form.key {
+        if form.key_removed.unwrap_or(false) {
+            if let Err(error) = parse_key_removed_date(form.key_removed_date.as_deref()) {
+                errors.push(error);
+            }
+            return Ok(errors);
+        }
+
+        errors.extend(get_key_format_errors(key));
+
+        //No point looking for duplicates of a key that can't exist
+        if errors.is_empty() {
+            for page in get_pages_with_key(context, key).await? {
+                if page.id != Some(form.id) && get_key_removed(&page).is_none() {
+                    errors.push(format!("Key '{}' is already used by page '{}'", key, opt_s!(page.name)));
+                }
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+pub async fn post_render(mut context: PageContext, mut form: PageForm) ->
     Result<Response, Error>
 {
     //println!("form: {:#?}", form);
@@ -391,31 +441,36 @@ pub async fn post_render(mut context: PageContext, form: PageForm) ->
     {
         //This one, we throw all the way, since we can't re-render the page without the parent anyway
         let mut written_page : Option<Content> = None;
-        let mut errors = Vec::new();
+
+        //Keys are normalized and checked before anything is constructed, nothing is written with a bad key
+        form.key = form.key.map(|k| normalize_key(&k));
+        let mut errors = get_key_errors(&mut context.api_context, &form).await?;
 
         //Get all the content that will be stored in the database for this form. There may be more than
         //one content to store, but we'll start with main (see next match)
-        match construct_post_content_full(&mut context.api_context, &form).await {
-            Ok(mut fullpage) =>
-            {
-                //Store the main content. This is most of the time all that is required, however there are some
-                //page types that have more data, which we'll check for within
-                match context.api_context.post_content(&fullpage.main, form.edit_message.clone()).await { 
-                    Ok(posted_page) => {
-                        //Still have to write the subpages if they exist
-                        if let Some(ref mut ptc_page) = fullpage.ptc {
-                            ptc_page.parentId = posted_page.id; //Make sure it's pointing to the right place
-                            match context.api_context.post_content(&ptc_page, None).await { 
-                                Ok(p) => { println!("Wrote PTC page: {}", i(&p.id)); }, //might do something more later idk
-                                Err(e) => { errors.push(e.to_user_string()); }
+        if errors.is_empty() {
+            match construct_post_content_full(&mut context.api_context, &form).await {
+                Ok(mut fullpage) =>
+                {
+                    //Store the main content. This is most of the time all that is required, however there are some
+                    //page types that have more data, which we'll check for within
+                    match context.api_context.post_content(&fullpage.main, form.edit_message.clone()).await { 
+                        Ok(posted_page) => {
+                            //Still have to write the subpages if they exist
+                            if let Some(ref mut ptc_page) = fullpage.ptc {
+                                ptc_page.parentId = posted_page.id; //Make sure it's pointing to the right place
+                                match context.api_context.post_content(ptc_page, None).await { 
+                                    Ok(p) => { println!("Wrote PTC page: {}", i(&p.id)); }, //might do something more later idk
+                                    Err(e) => { errors.push(e.to_user_string()); }
+                                }
                             }
-                        }
-                        written_page = Some(posted_page);
-                    },
-                    Err(e) => { errors.push(e.to_user_string()); }
-                }
-            },
-            Err(e) => { errors.push(e.to_user_string()); }
+                            written_page = Some(posted_page);
+                        },
+                        Err(e) => { errors.push(e.to_user_string()); }
+                    }
+                },
+                Err(e) => { errors.push(e.to_user_string()); }
+            }
         }
 
         if errors.is_empty() {
@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use common::*;
+use common::prefab::*;
+use common::response::*;
+use zip::write::FileOptions;
+
+use base64::{Engine as _, engine::general_purpose};
+
+use crate::widget_qr::PtcData;
+
+// Petit Computer files on the SD card have a 36 byte header which the page editor strips off
+// before storing the data, so we have to rebuild it here for the file to be usable again
+
+const PTCMAGIC: &[u8] = b"PX01";
+const PTCMD5PREFIX: &[u8] = b"PETITCOM";
+const PTCCONTENTTYPE: &str = "application/octet-stream";
+const ZIPCONTENTTYPE: &str = "application/zip";
+
+/// Parse the list of ptc files out of the given page. Errors if the page has no ptc files at all
+pub fn get_ptc_data(page: &FullPage) -> Result<Vec<PtcData>, Error>
+{
+    let ptc_files = page.ptc.as_ref().ok_or_else(|| Error::NotFound(String::from("This page doesn't have any petit computer files!!")))?;
+    let ptc_data = ptc_files.text.as_ref().ok_or_else(|| Error::Other(String::from("Something went seriously wrong! No text in ptc content!")))?;
+    Ok(serde_json::de::from_str::<Vec<PtcData>>(ptc_data)?)
+}
+
+/// The filename (including extension) for the given ptc file. Only characters petit computer itself
+/// allows in a filename are kept, so this is safe to put in a header
+pub fn get_ptc_filename(ptc_file: &PtcData) -> String
+{
+    let name : String = ptc_file.name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .take(8)
+        .collect();
+    format!("{}.PTC", if name.is_empty() { "UNNAMED" } else { &name }).to_ascii_uppercase()
+}
+
+/// Reconstruct the full .PTC file (as it would be on the SD card) from the stored data
+pub fn get_ptc_file(ptc_file: &PtcData) -> Result<Vec<u8>, Error>
+{
+    let raw = general_purpose::STANDARD.decode(&ptc_file.base64).map_err(|e| Error::Other(e.to_string()))?;
+
+    let mut md5data : Vec<u8> = Vec::with_capacity(PTCMD5PREFIX.len() + raw.len());
+    md5data.extend_from_slice(PTCMD5PREFIX);
+    md5data.extend_from_slice(&raw);
+    let rawmd5 : [u8;16] = md5::compute(&md5data).into();
+
+    let mut result : Vec<u8> = Vec::with_capacity(36 + raw.len());
+    result.extend_from_slice(PTCMAGIC);
+    result.extend((raw.len() as u32).to_le_bytes());
+    result.extend(0u32.to_le_bytes());
+    result.extend_from_slice(ptc_file.name.as_bytes().get(..8).unwrap_or(ptc_file.name.as_bytes()));
+    //Same as the qr generator, the name is padded with zeroes
+    while result.len() < 20 { result.push(0); }
+    result.extend(rawmd5);
+    result.extend(raw);
+
+    Ok(result)
+}
+
+/// Pack all the given ptc files into a single zip archive. Files with duplicate names get a number
+/// added so they don't overwrite each other when extracted
+pub fn get_ptc_zip(ptc_files: &Vec<PtcData>) -> Result<Vec<u8>, Error>
+{
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut used_names : Vec<String> = Vec::new();
+
+    for ptc_file in ptc_files {
+        let mut filename = get_ptc_filename(ptc_file);
+        let mut duplicate = 1;
+        while used_names.contains(&filename) {
+            duplicate += 1;
+            filename = get_ptc_filename(ptc_file).replace(".PTC", &format!("_{}.PTC", duplicate));
+        }
+        zip.start_file(filename.clone(), options).map_err(|e| Error::Other(e.to_string()))?;
+        zip.write_all(&get_ptc_file(ptc_file)?).map_err(|e| Error::Other(e.to_string()))?;
+        used_names.push(filename);
+    }
+
+    let cursor = zip.finish().map_err(|e| Error::Other(e.to_string()))?;
+    Ok(cursor.into_inner())
+}
+
+/// Download a single ptc file (by index into the page's file list)
+pub async fn get_file(mut context: PageContext, hash: &str, index: usize) -> Result<Response, Error>
+{
+    let page = get_fullpage_by_hash(&mut context.api_context, hash).await?;
+    let ptc_files = get_ptc_data(&page)?;
+    let ptc_file = ptc_files.get(index).ok_or_else(|| Error::NotFound(format!("No petit computer file at index {}", index)))?;
+
+    Ok(Response::File(get_ptc_file(ptc_file)?, String::from(PTCCONTENTTYPE), get_ptc_filename(ptc_file)))
+}
+
+/// Download every ptc file on the page as a single zip
+pub async fn get_zip(mut context: PageContext, hash: &str) -> Result<Response, Error>
+{
+    let page = get_fullpage_by_hash(&mut context.api_context, hash).await?;
+    let ptc_files = get_ptc_data(&page)?;
+    let filename = format!("{}.zip", opt_s!(page.main.hash, "ptcfiles"));
+
+    Ok(Response::File(get_ptc_zip(&ptc_files)?, String::from(ZIPCONTENTTYPE), filename))
+}
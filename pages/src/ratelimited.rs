@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use common::*;
+use common::render::layout::*;
+use maud::*;
+
+/// Shown instead of running a request when the client has made too many of them too fast (see common::ratelimit).
+/// It might be shown before anything is asked of the backend, so the layout data may be the offline kind
+pub fn render(data: MainLayoutData, wait: Duration) -> String {
+    let seconds = wait.as_secs().max(1);
+    let body = html! {
+        section {
+            h1 { "Slow down!" }
+            p { 
+                "You've been doing that a lot in a short amount of time, so we didn't do it this time. "
+                "You can try again in " 
+                @if seconds < 120 { (seconds) " seconds" } @else { (seconds.div_ceil(60)) " minutes" }
+                "."
+            }
+            p."aside" { "Nothing you typed was saved, so you may want to go back and copy it somewhere." }
+        }
+    };
+
+    //Voting happens in a little iframe, there's no room for the whole site
+    if data.current_path.starts_with("/widget/") {
+        basic_skeleton(&data, html! { title { "Slow down!" } }, body).into_string()
+    }
+    else {
+        layout(&data, body).into_string()
+    }
+}
@@ -12,6 +12,7 @@ pub fn render(data: MainLayoutData, errors: Option<Vec<String>>, email: Option<S
             h1 {"Recover account"}
             p {"You'll receive an email shortly with the code to recover your account!"}
             form method="POST" action={(data.links.http_root)"/recover"} { //Must be exact!
+                (csrf_input(&data))
                 (errorlist(errors))
                 label for="recover_email"{"Email (to identify account):"}
                 input #"recover_email" type="email" required="" name="currentEmail" value=[&email];
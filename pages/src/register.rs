@@ -10,6 +10,7 @@ pub fn render(data: MainLayoutData, errors: Option<Vec<String>>, username: Optio
         section {
             h1 { "Register" }
             form #"register_form" method="POST" action={(data.links.http_root)"/register"} {
+                (csrf_input(&data))
                 (errorlist(errors))
                 label for="register_username" {"Username:"}
                 input #"register_username" type="text" name="username" value=[username];
@@ -30,6 +30,7 @@ pub fn render(data: MainLayoutData, confirm_errors: Option<Vec<String>>, email_e
                    "Re-enter your email and the confirmation code to complete your registration." }
             }
             form #"complete_form" method="POST" action={(data.links.http_root)"/register/confirm"} {
+                (csrf_input(&data))
                 (errorlist(confirm_errors))
                 label for="complete_email" {"Email:"}
                 input #"complete_email" type="text" name="email" required="" value=[&email];
@@ -55,6 +56,7 @@ pub fn render(data: MainLayoutData, confirm_errors: Option<Vec<String>>, email_e
                "to get through email filters. If you didn't receive it, you can send it again here:" }
             //Post to the special endpoint still under the "confirm" umbrella, so errors will be rendered "on the same page"
             form #"resend_form" method="POST" action={(data.links.http_root)"/register/confirm?resend=1"} {
+                (csrf_input(&data))
                 (errorlist(email_errors))
                 @if resend_success {
                     p."success"{"Email resent!"}
@@ -8,16 +8,23 @@ use common::forms::*;
 use common::search::*;
 use common::constants::*;
 use common::response::*;
+use common::render::csrf_input;
 use common::render::layout::*;
 use common::render::submissions::*;
 use maud::*;
 
 pub fn render(data: MainLayoutData, pages: Vec<Content>, users: HashMap<i64, User>, search: PageSearch,
-    categories: Vec<Category>) -> String 
+    categories: Vec<Category>, facets: SearchFacets) -> String 
 {
+    let search_categories = search.get_categories();
+    let search_systems = search.get_systems();
     //Need to split category search into parts 
     //let search_system = match &search.system { Some(system) => system, None => };
-    layout(&data, html!{
+    let meta = LayoutMeta {
+        feeds: vec![(String::from("This search"), data.links.search_feed(&PageSearch { page: 0, ..search.clone() }))],
+        ..Default::default()
+    };
+    layout_with_meta(&data, meta, html!{
         (data.links.style("/forpage/search.css"))
         (data.links.script("/forpage/search.js"))
         section {
@@ -27,32 +34,31 @@ pub fn render(data: MainLayoutData, pages: Vec<Content>, users: HashMap<i64, Use
                     label for="search-type" {"Type: "}
                     select #"search-type" name="subtype" {
                         @for (value,text) in SEARCHPAGETYPES {
-                            option value=(value) selected[Some(*value) == search.subtype.as_deref()] { (text) }
+                            option value=(value) selected[Some(*value) == search.subtype.as_deref()] { 
+                                (text) (facet_count(facets.subtypes.get(*value)))
+                            }
                         }
                     }
                 }
                 //THIS needs to come from parameters! Don't know the categories available unless
-                //we look at the database!
-                div."smallseparate inline" for="search-category" 
+                //we look at the database! Nothing checked means any category
+                div."smallseparate inline" #"search-categories" 
                 {
-                    label for="search-category" {"Category:"}
-                    select #"search-category" name="category" {
-                        option value="0" { "Any" }
-                        @for category in &categories {
-                            //NOTE: this "selected" actually does work, it adds the attribute when appropriate. But
-                            //the select is not showing this when the page loads, so it may be js or something
-                            option data-for=(category.forcontent) value=(category.id) selected[Some(category.id) == search.category] { 
-                                (category.name) 
-                            }
+                    span { "Categories:" }
+                    @for category in &categories {
+                        label."inline facet" data-for=(category.forcontent) {
+                            input type="checkbox" name="category" value=(category.id) checked[search_categories.contains(&category.id)];
+                            span { (category.name) (facet_count(facets.categories.get(&category.id))) }
                         }
                     }
                 }
                 @if search.subtype.as_deref() == Some(SBSPageType::PROGRAM) {
-                    div."smallseparate inline" {
-                        label for="search-system" {"System: "}
-                        select #"search-system" name="system" {
-                            @for (value,text) in SBSSYSTEMS {
-                                option value=(value) selected[*value == search.system] { (text) }
+                    div."smallseparate inline" #"search-systems" {
+                        span { "Systems: " }
+                        @for (value,text) in SBSSYSTEMS.iter().filter(|(s,_)| *s != ANYSYSTEM) {
+                            label."inline facet" {
+                                input type="checkbox" name="system" value=(value) checked[search_systems.iter().any(|s| s == value)];
+                                span { (text) (facet_count(facets.systems.get(*value))) }
                             }
                         }
                     }
@@ -83,8 +89,20 @@ pub fn render(data: MainLayoutData, pages: Vec<Content>, users: HashMap<i64, Use
                 input type="submit" value="Update search";
             }
         }
+        @if data.user.is_some() {
+            section {
+                form."smallseparate compactform" method="POST" action=(data.links.search_save()) #"savesearchform" {
+                    (csrf_input(&data))
+                    label for="savesearch-name" { "Save this search as: " }
+                    input #"savesearch-name" type="text" name="name" required="" placeholder="Name";
+                    input type="hidden" name="query" value=(serde_urlencoded::to_string(&search).unwrap_or_default());
+                    input type="submit" value="Save";
+                }
+            }
+        }
         // All the pages (directly in the section?)
         section."results" {
+            div."aside" #"search-total" { (facets.total) " result" @if facets.total != 1 { "s" } }
             div."cardslist" {
                 //Or maybe in here
                 @for page in &pages {
@@ -105,6 +123,15 @@ pub fn render(data: MainLayoutData, pages: Vec<Content>, users: HashMap<i64, Use
 }
 
 
+/// The little count shown next to each search filter value (nothing if we didn't get a count)
+fn facet_count(count: Option<&i32>) -> Markup {
+    html! {
+        @if let Some(count) = count {
+            " (" (count) ")"
+        }
+    }
+}
+
 // TODO: Make this generic across imagebrowse and here? Search has to impl some trait with get/set 
 // page functions and clone, and .browsepagenav might need to go in base.css
 fn page_navigation(data: &MainLayoutData, search: &PageSearch) -> Markup {
@@ -125,20 +152,79 @@ fn page_navigation(data: &MainLayoutData, search: &PageSearch) -> Markup {
 }
 
 
-pub async fn get_render(context: PageContext, search: PageSearch, per_page: i32) -> Result<Response, Error> 
+pub async fn get_render(mut context: PageContext, search: PageSearch, per_page: i32) -> Result<Response, Error> 
 {
-    let request = get_search_request(&search, per_page);
+    //Facet counts need to know the categories ahead of time. Hidden categories aren't offered, but old pages
+    //still have them (and link to them), so one that's already being searched stays in the list
+    let search_categories = search.get_categories();
+    let categories = map_categories(prefab::get_all_categories(&mut context.api_context, None).await?)
+        .into_iter().filter(|c| !c.hidden || search_categories.contains(&c.id)).collect::<Vec<_>>();
 
-    let result = context.api_context.post_request(&request).await?;
+    let mut request = get_search_request(&search, per_page);
+    add_search_facets(&mut request, &search, &categories);
+
+    let result = context.api_context.post_request_profiled_opt(&request, "search").await?;
     //println!("RESULT: {:#?}", &result);
     let pages = conversion::cast_result_safe::<Content>(&result, "content")?;
     let users = conversion::cast_result_safe::<User>(&result, "user")?;
-    let categories = conversion::cast_result_safe::<Content>(&result, "categories")?;
     let users = map_users(users);
-
-    let categories = map_categories(categories);
+    let facets = SearchFacets::from_result(&result, &categories)?;
 
     //Manually parse the search, because of the tag magic (no javascript)
     //Err(Error::Other(String::from("wow")))
-    Ok(Response::Render(render(context.layout_data, pages,  users, search, categories)))
-}
\ No newline at end of file
+    Ok(Response::Render(render(context.layout_data, pages,  users, search, categories, facets)))
+}
+
+
+/// Save the search (given as the raw query) for the current user, then send them to their saved searches
+pub async fn post_save_render(context: PageContext, form: SaveSearchForm) -> Result<Response, Error>
+{
+    if context.layout_data.user.is_none() {
+        return Err(Error::User(String::from("You must be logged in to save searches!")));
+    }
+
+    let mut search = parse_query_compound::<PageSearch>(&form.query).map_err(|e| Error::User(format!("Bad search: {}", e)))?;
+    search.page = 0; //Always start from the beginning
+
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(Error::User(String::from("Saved searches need a name!")));
+    }
+
+    let saved = SavedSearch {
+        key: SavedSearch::new_key(),
+        name: name.to_string(),
+        search,
+        last_visit: chrono::Utc::now()
+    };
+
+    prefab::post_saved_search(&context.api_context, &saved).await?;
+    Ok(Response::Redirect(format!("{}#saved-searches", context.layout_data.links.userhome())))
+}
+
+/// Go to the given saved search, marking it as visited now
+pub async fn post_saved_redirect(mut context: PageContext, key: &str) -> Result<Response, Error>
+{
+    let user = context.layout_data.user.as_ref().ok_or_else(|| Error::User(String::from("You must be logged in to use saved searches!")))?;
+    let mut saved = prefab::get_saved_searches(&mut context.api_context, user.id).await?
+        .into_iter().find(|s| s.key == key)
+        .ok_or_else(|| Error::NotFound(format!("No saved search {}", key)))?;
+
+    saved.last_visit = chrono::Utc::now();
+    prefab::post_saved_search(&context.api_context, &saved).await?;
+    Ok(Response::Redirect(context.layout_data.links.search(&saved.search)))
+}
+
+/// Any search as an atom feed. The search order is kept, so you probably want a "newest" order for this
+pub async fn get_feed_render(mut context: PageContext, search: PageSearch, per_page: i32) -> Result<Response, Error>
+{
+    let result = context.api_context.post_request_profiled_opt(&get_search_request(&search, per_page), "searchfeed").await?;
+    let pages = conversion::cast_result_safe::<Content>(&result, "content")?;
+    let users = map_users(conversion::cast_result_safe::<User>(&result, "user")?);
+
+    let links = &context.layout_data.links;
+    let entries = pages.iter().map(|p| common::feed::page_entry(links, p, &users)).collect();
+    let feed = common::feed::Feed::new(links, String::from("SmileBASIC Source search"), 
+        &links.search(&search), &links.search_feed(&search), entries);
+    Ok(Response::Document(feed.render(), String::from(common::feed::ATOMCONTENTTYPE)))
+}
@@ -14,6 +14,7 @@ pub fn render(data: MainLayoutData, errors: Option<Vec<String>>) -> String
         section {
             h1 { "Local session settings" }
             form method="POST" action={(data.links.http_root)"/sessionsettings"} {
+                (csrf_input(&data))
                 (errorlist(errors))
                 div."inline smallseparate" {
                     label for="settings-theme" {"Theme:"}
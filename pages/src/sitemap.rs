@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use common::*;
+use common::feed::atom_date;
+use common::prefab::*;
+use common::response::*;
+use maud::*;
+
+//The sitemap protocol won't take more than this many urls in one file, past that we have to use an index
+pub const SITEMAPMAXURLS: usize = 50000;
+pub const SITEMAPCONTENTTYPE: &str = "application/xml; charset=utf-8";
+const SITEMAPXMLNS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+//Like the feeds, these are xml through maud, so every element needs a body
+
+pub fn render_urlset(urls: &[SitemapUrl]) -> String
+{
+    html! {
+        (PreEscaped("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"))
+        urlset xmlns=(SITEMAPXMLNS) {
+            @for url in urls {
+                url {
+                    loc { (url.loc) }
+                    @if let Some(ref lastmod) = url.lastmod {
+                        lastmod { (atom_date(lastmod)) }
+                    }
+                }
+            }
+        }
+    }.into_string()
+}
+
+pub fn render_index(links: &LinkConfig, pages: usize) -> String
+{
+    html! {
+        (PreEscaped("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"))
+        sitemapindex xmlns=(SITEMAPXMLNS) {
+            @for page in 1..=pages {
+                sitemap {
+                    loc { (links.absolute(&links.sitemap_page(page))) }
+                }
+            }
+        }
+    }.into_string()
+}
+
+/// The sitemap urls from the last time they were built, which happens in the background (see prefab::refresh_sitemap)
+fn get_urls(cache: &SitemapCache) -> Result<Arc<Vec<SitemapUrl>>, Response>
+{
+    cache.get().ok_or_else(|| Response::MessageWithStatus(String::from("The sitemap is still being built, try again soon"), 503))
+}
+
+/// The main sitemap. If there are too many urls for one file, this is an index of the numbered sitemaps instead
+pub async fn get_render(context: PageContext, cache: &SitemapCache) -> Result<Response, Error>
+{
+    let urls = match get_urls(cache) {
+        Ok(urls) => urls,
+        Err(response) => return Ok(response)
+    };
+    let text = if urls.len() <= SITEMAPMAXURLS {
+        render_urlset(&urls)
+    }
+    else {
+        render_index(&context.layout_data.links, urls.len().div_ceil(SITEMAPMAXURLS))
+    };
+    Ok(Response::Document(text, String::from(SITEMAPCONTENTTYPE)))
+}
+
+/// One of the numbered sitemaps from the index (1 based)
+pub async fn get_page_render(cache: &SitemapCache, page: usize) -> Result<Response, Error>
+{
+    let urls = match get_urls(cache) {
+        Ok(urls) => urls,
+        Err(response) => return Ok(response)
+    };
+    let start = page.saturating_sub(1) * SITEMAPMAXURLS;
+    if page == 0 || start >= urls.len() {
+        return Err(Error::NotFound(format!("No sitemap {}", page)));
+    }
+    let end = (start + SITEMAPMAXURLS).min(urls.len());
+    Ok(Response::Document(render_urlset(&urls[start..end]), String::from(SITEMAPCONTENTTYPE)))
+}
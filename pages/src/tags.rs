@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use contentapi::conversion::*;
+use contentapi::*;
+
+use common::*;
+use common::constants::*;
+use common::forum::*;
+use common::render::layout::*;
+use common::render::submissions::*;
+use common::pagination::*;
+use common::response::*;
+use common::view::*;
+use maud::*;
+use serde::{Serialize, Deserialize};
+
+use crate::forum_category::thread_item;
+
+//Only these show up on the tag page; things like direct messages and documentation stay out
+const TAGPAGETYPES: &[&str] = &[ SBSPageType::PROGRAM, SBSPageType::RESOURCE ];
+const TAGTHREADTYPES: &[&str] = &[ SBSPageType::FORUMTHREAD ];
+const TAGCLOUDSIZES: i64 = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TagQuery {
+    pub page: Option<i32>
+}
+
+/// Which size class (1 to TAGCLOUDSIZES) the keyword gets in the cloud. Log scale, otherwise
+/// the few really popular keywords make everything else tiny
+fn tag_size(count: i64, max: i64) -> i64 {
+    if max <= 1 { return 1; }
+    let scale = (count.max(1) as f64).ln() / (max as f64).ln();
+    1 + (scale * (TAGCLOUDSIZES - 1) as f64).round() as i64
+}
+
+pub fn render_cloud(data: MainLayoutData, keywords: Vec<KeywordAggregate>) -> String
+{
+    let max = keywords.iter().map(|k| k.count).max().unwrap_or(1);
+    layout(&data, html!{
+        (data.links.style("/forpage/tags.css"))
+        section {
+            h1 { "Tags" }
+            @if keywords.is_empty() {
+                p."aside" { "No tags yet!" }
+            }
+            div #"tagcloud" {
+                @for keyword in &keywords {
+                    a.{"flatlink tagsize" (tag_size(keyword.count, max))} href=(data.links.tag(&keyword.value)) title={(keyword.count) " uses"} {
+                        (keyword.value)
+                    }
+                }
+            }
+        }
+    }).into_string()
+}
+
+pub fn render_tag(data: MainLayoutData, keyword: &str, pages: Vec<Content>, threads: Vec<ForumThread>,
+    users: HashMap<i64, User>, pagelist: Vec<PagelistItem>) -> String
+{
+    layout(&data, html!{
+        (data.links.style("/forpage/tags.css"))
+        (data.links.style("/forpage/forum.css"))
+        section {
+            h1 { "#" (keyword) }
+            a."flatlink" href=(data.links.tags()) { "All tags" }
+        }
+        @if !pages.is_empty() {
+            section."results" {
+                h3 { "Programs and resources" }
+                div."cardslist" {
+                    @for page in &pages {
+                        (page_card(&data.links, page, &users))
+                    }
+                }
+            }
+        }
+        @if !threads.is_empty() {
+            section {
+                h3 { "Threads" }
+                @for thread in &threads {
+                    (thread_item(&data.links, thread, &users))
+                }
+            }
+        }
+        @if pages.is_empty() && threads.is_empty() {
+            section { p."aside" { "Nothing is tagged with that!" } }
+        }
+        section {
+            div."smallseparate pagelist" {
+                @for page in pagelist {
+                    a."current"[page.current] href={(data.links.tag(keyword))"?page="(page.page)} { (page.text) }
+                }
+            }
+        }
+    }).into_string()
+}
+
+pub async fn get_render(mut context: PageContext, count: i32) -> Result<Response, Error>
+{
+    let mut request = FullRequest::new();
+    let mut tag_request = build_request!(
+        RequestType::keyword_aggregate,
+        String::from("value,count")
+    );
+    tag_request.order = Some(String::from("count_desc"));
+    tag_request.limit = count.into();
+    request.requests.push(tag_request);
+
+    let result = context.api_context.post_request_profiled_opt(&request, "tagcloud").await?;
+    let mut keywords = cast_result_required::<KeywordAggregate>(&result, &RequestType::keyword_aggregate.to_string())?;
+    keywords.sort_by_key(|k| k.value.to_ascii_lowercase());
+
+    Ok(Response::Render(render_cloud(context.layout_data, keywords)))
+}
+
+pub async fn get_tag_render(mut context: PageContext, keyword: String, query: TagQuery, per_page: i32) -> Result<Response, Error>
+{
+    let page = query.page.unwrap_or(1).max(1) - 1;
+
+    let mut request = FullRequest::new();
+    add_value!(request, "keyword", vec![keyword.clone()]);
+    add_value!(request, "pagetypes", TAGPAGETYPES);
+    add_value!(request, "threadtypes", TAGTHREADTYPES);
+
+    let page_query = String::from("!notdeleted() and literalType in @pagetypes and !keywordin(@keyword)");
+    let thread_query = String::from("!notdeleted() and literalType in @threadtypes and !keywordin(@keyword)");
+
+    let mut page_request = build_request!(
+        RequestType::content,
+        String::from("id,hash,parentId,contentType,literalType,values,keywords,name,description,createUserId,createDate,lastRevisionId"),
+        page_query.clone(),
+        String::from("id_desc"),
+        per_page,
+        page * per_page
+    );
+    page_request.name = Some(String::from("pages"));
+    request.requests.push(page_request);
+
+    let mut thread_request = build_request!(
+        RequestType::content,
+        String::from(THREADFIELDS),
+        thread_query.clone(),
+        String::from("lastCommentId_desc"),
+        per_page,
+        page * per_page
+    );
+    thread_request.name = Some(String::from("threads"));
+    request.requests.push(thread_request);
+
+    //Pagination is shared, so it goes as far as the longer of the two lists
+    for (name, query) in [("pagecount", page_query), ("threadcount", thread_query)] {
+        let mut count_request = build_request!(
+            RequestType::content,
+            String::from("specialCount,id,literalType,keywords"),
+            query
+        );
+        count_request.name = Some(String::from(name));
+        request.requests.push(count_request);
+    }
+
+    request.requests.push(build_request!(
+        RequestType::user,
+        String::from("*"),
+        String::from("id in @pages.createUserId or id in @threads.createUserId")
+    ));
+
+    let result = context.api_context.post_request_profiled_opt(&request, "tag").await?;
+    let pages = cast_result_required::<Content>(&result, "pages")?;
+    let threads_raw = cast_result_required::<Content>(&result, "threads")?;
+    let users = map_users(cast_result_required::<User>(&result, &RequestType::user.to_string())?);
+    let mut total = 0;
+    for name in ["pagecount", "threadcount"] {
+        if let Some(count) = cast_result_safe::<SpecialCount>(&result, name)?.pop() {
+            total = total.max(count.specialCount);
+        }
+    }
+
+    let threads = threads_raw.into_iter().map(|t| ForumThread::from_content(t, &Vec::new(), &Vec::new())).collect::<Result<Vec<_>,_>>()?;
+
+    Ok(Response::Render(render_tag(context.layout_data, &keyword, pages, threads, users, get_pagelist(total, per_page, page))))
+}
@@ -0,0 +1,22 @@
+use common::*;
+use common::render::layout::*;
+use maud::*;
+
+/// Shown when the backend is down or too slow to answer. There's no api to ask anything, so the layout
+/// data given here is only what the frontend knows on its own: no user, no alert, no api version
+pub fn render(data: MainLayoutData) -> String {
+    let body = html! {
+        section {
+            h1 { "We'll be right back" }
+            p { "The site can't reach its backend right now, so this page couldn't be loaded. This is usually brief; try again in a minute." }
+            p."aside" { "If you were submitting something, it may not have gone through. You may want to copy what you typed somewhere before retrying." }
+        }
+    };
+
+    if data.current_path.starts_with("/widget/") {
+        basic_skeleton(&data, html! { title { "Unavailable" } }, body).into_string()
+    }
+    else {
+        layout(&data, body).into_string()
+    }
+}
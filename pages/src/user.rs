@@ -18,6 +18,7 @@ use maud::*;
 pub struct UserPackage {
     pub user: User,
     pub userpage: Option<Content>,
+    pub collections: Vec<common::collections::Collection>,
     pub users: HashMap<i64, User>,
     pub submissions: Vec<Content>,
     pub badges: Vec<Content>,
@@ -33,12 +34,17 @@ pub fn render(data: MainLayoutData, mut bbcode: BBCode, user_package: UserPackag
     ban_errors: Option<Vec<String>>, unban_errors: Option<Vec<String>>, userset_errors: Option<Vec<String>>) -> String 
 {
     let user = user_package.user;
+    let collections = &user_package.collections;
 
     let meta = LayoutMeta {
         title : format!("SBS ⦁ {}", user.username),
         description : short_description_opt(user_package.userpage.as_ref()),
         image : Some(data.links.image(&user.avatar, &QueryImage::avatar(200))),
-        canonical: Some(data.links.user(&user))
+        canonical: Some(data.links.user(&user)),
+        feeds: vec![(format!("{}'s submissions", user.username), data.links.user_feed(&user))],
+        og_type: String::from("profile"),
+        structured_data: Some(common::structured::user_person(&data.links, &user, &short_description_opt(user_package.userpage.as_ref()))),
+        ..Default::default()
     };
 
     layout_with_meta(&data, meta, html!{
@@ -60,12 +66,22 @@ pub fn render(data: MainLayoutData, mut bbcode: BBCode, user_package: UserPackag
                         }
                     }
                     //If the user has no bio, that's ok! 
-                    @if let Some(userpage) = user_package.userpage {
+                    @if let Some(ref userpage) = user_package.userpage {
                         div."content" #"userbio" { (PreEscaped(bbcode.parse_profiled_opt(opt_s!(userpage.text), format!("userpage-{}", i(&userpage.id))))) } 
                     }
                 }
             }
         }
+        @if !collections.is_empty() {
+            section {
+                h1 { "Collections:" }
+                div."smallseparate" {
+                    @for collection in collections {
+                        a."flatlink" href=(data.links.collection(&user, &collection.key)) { (collection.name) }
+                    }
+                }
+            }
+        }
         section {
             h1 { "Submissions:" }
             @if user_package.submissions.len() == 0 {
@@ -96,6 +112,7 @@ pub fn render(data: MainLayoutData, mut bbcode: BBCode, user_package: UserPackag
                     h3 { "Ban controls:" }
                     @if let Some(ban) = &user_package.ban {
                         form #"unbanform" method="POST" action={(data.links.user(&user))"?unban=1#admincontrols"} {
+                            (csrf_input(&data))
                             (errorlist(unban_errors))
                             p."error" { 
                                 "ALREADY" 
@@ -113,6 +130,7 @@ pub fn render(data: MainLayoutData, mut bbcode: BBCode, user_package: UserPackag
                     }
                     @else {
                         form #"banform" method="POST" action={(data.links.user(&user))"?ban=1#admincontrols"} {
+                            (csrf_input(&data))
                             (errorlist(ban_errors))
                             label for="ban_hours"{"Ban hours:"}
                             input #"ban_hours" type="text" required="" name="hours" placeholder="0 = 100 years";
@@ -129,6 +147,7 @@ pub fn render(data: MainLayoutData, mut bbcode: BBCode, user_package: UserPackag
                     hr;
                     h3 #"update-user" {"Update user info:"}
                     form method="POST" action={(data.links.user(&user))"?userinfo=1#update-user"} { 
+                        (csrf_input(&data))
                         p."aside" { 
                             "You can override a user's username and avatar here. Note that they'll be able to change it back "
                             "by default unless you 'full ban' them. So, this form is only useful when full banning a user."
@@ -209,11 +228,13 @@ pub async fn get_render_internal(mut context: PageContext, username: String, ban
 
         let result = context.api_context.post_request(&request).await?;
         let docsgroup = get_documentation_group(&mut context.api_context).await?;
+        let collections = prefab::get_public_collections(&mut context.api_context, user.id).await?;
         //let docparent = get_documentation_parent(&mut context.api_context, DOCPARENTMINIMALFIELDS).await?;
 
         let package = UserPackage {
             user,
             userpage: content_raw.pop(),
+            collections,
             badges: badges_raw,
             ban: bans_raw.pop(),
             submissions: conversion::cast_result_safe::<Content>(&result, "content")?,
@@ -333,4 +354,26 @@ pub async fn post_userinfo(context: PageContext, username: String, update: UserU
 
     //If you get here, it's almost certainly an error
     get_render_internal(context, username, None, None, Some(errors)).await
-}
\ No newline at end of file
+}
+
+/// The user's newest submissions as an atom feed
+pub async fn get_feed_render(mut context: PageContext, username: String, per_page: i32) -> Result<Response, Error>
+{
+    let user = context.api_context.get_user_by_username(&username, "*").await?;
+
+    let search = PageSearch {
+        subtype: None, //Same as the user page: ALL submissions
+        user_id: Some(user.id),
+        order: "id_desc".to_string(),
+        ..Default::default()
+    };
+    let result = context.api_context.post_request_profiled_opt(&get_search_request(&search, per_page), "userfeed").await?;
+    let pages = conversion::cast_result_safe::<Content>(&result, "content")?;
+    let users = common::view::map_users(conversion::cast_result_safe::<User>(&result, "user")?);
+
+    let links = &context.layout_data.links;
+    let entries = pages.iter().map(|p| common::feed::page_entry(links, p, &users)).collect();
+    let feed = common::feed::Feed::new(links, format!("{}'s submissions - SmileBASIC Source", user.username), 
+        &links.user(&user), &links.user_feed(&user), entries);
+    Ok(Response::Document(feed.render(), String::from(common::feed::ATOMCONTENTTYPE)))
+}
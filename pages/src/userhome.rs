@@ -1,6 +1,8 @@
 use common::*;
 use common::forms::BasicPage;
 use common::forms::UserUpdate;
+use common::forms::SavedSearchForm;
+use common::search::*;
 use common::render::*;
 use common::render::layout::*;
 use common::response::*;
@@ -10,7 +12,13 @@ use contentapi::forms::*;
 use maud::*;
 
 
-pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, userbio: Option<Content>,
+/// The saved searches section: each search with how many new results it has, and whatever went wrong changing them
+pub struct SavedSearches {
+    pub searches: Vec<(SavedSearch, i32)>,
+    pub errors: Option<Vec<String>>
+}
+
+pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, userbio: Option<Content>, saved_searches: SavedSearches,
     update_errors: Option<Vec<String>>, bio_errors: Option<Vec<String>>, private_errors: Option<Vec<String>>) -> String 
 {
     let mut bio_id: i64 = 0;
@@ -53,6 +61,7 @@ pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, us
                 // "Editor" forms are special forms which are meant for editing content instead of whatever other 
                 //  forms do.
                 form."editor" method="POST" action={(data.links.http_root)"/userhome?bio=1#update-userbio"} {
+                    (csrf_input(&data))
                     (errorlist(bio_errors))
                     input type="hidden" name="id" value=(bio_id);
                     textarea #"update_userbio" type="text" name="text"{(bio_text)}
@@ -61,6 +70,7 @@ pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, us
                 hr;
                 h3 #"update-user"{"Update info:"}
                 form method="POST" action={(data.links.http_root)"/userhome#update-user"} { 
+                    (csrf_input(&data))
                     (errorlist(update_errors))
                     label for="update_username"{"Username:"}
                     input #"update_username" type="text" name="username" value=(user.username);
@@ -70,6 +80,38 @@ pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, us
                     input type="submit" value="Update";
                 }
             }
+            section {
+                h3 #"saved-searches" {"Saved searches:"}
+                (errorlist(saved_searches.errors))
+                @if saved_searches.searches.is_empty() {
+                    p."aside" {"Nothing saved yet; use 'Save this search' on the search page"}
+                }
+                @for (saved, new_count) in &saved_searches.searches {
+                    div."savedsearch smallseparate" {
+                        //Going to a saved search marks it visited, so it's a POST; prefetchers and crawlers don't reset the count
+                        form."compactform inline" method="POST" action=(data.links.saved_search(&saved.key)) {
+                            (csrf_input(&data))
+                            input."flatlink" type="submit" value=(saved.name);
+                        }
+                        @if *new_count > 0 {
+                            span."newcount" title={"New since " (saved.last_visit.to_rfc3339())} { (new_count) " new" }
+                        }
+                        form."compactform smallseparate inline" method="POST" action={(data.links.http_root)"/userhome?savedsearch=1#saved-searches"} {
+                            (csrf_input(&data))
+                            input type="hidden" name="key" value=(saved.key);
+                            input."smallinput" type="text" name="name" required="" value=(saved.name);
+                            input type="submit" value="Rename";
+                        }
+                        form."compactform inline" method="POST" action={(data.links.http_root)"/userhome?savedsearch=1#saved-searches"} {
+                            (csrf_input(&data))
+                            input type="hidden" name="key" value=(saved.key);
+                            input type="hidden" name="name" value=(saved.name);
+                            input type="hidden" name="delete" value="true";
+                            input type="submit" value="Delete";
+                        }
+                    }
+                }
+            }
             section {
                 iframe."imagebrowser" src={(data.links.imagebrowser())} {}
             }
@@ -77,6 +119,7 @@ pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, us
                 h3 #"update-sensitive"{"Update sensitive info"}
                 p{"Only set the fields you want to change, except 'current password', which is required"}
                 form method="POST" action={(data.links.http_root)"/userhome?sensitive=1#update-sensitive"} autocomplete="off" {
+                    (csrf_input(&data))
                     (errorlist(private_errors))
                     //<label for="sensitive_username">New Username:</label>
                     //<input id="sensitve_username" type="text" autocomplete="new-password" name="username" value="">
@@ -100,11 +143,13 @@ pub fn render(data: MainLayoutData, private: Option<contentapi::UserPrivate>, us
 }
 
 
-async fn get_render_internal(context: PageContext, 
-    update_errors: Option<Vec<String>>, bio_errors: Option<Vec<String>>, private_errors: Option<Vec<String>>) -> Result<Response,Error> 
+async fn get_render_internal(mut context: PageContext, 
+    update_errors: Option<Vec<String>>, bio_errors: Option<Vec<String>>, private_errors: Option<Vec<String>>,
+    saved_errors: Option<Vec<String>>) -> Result<Response,Error> 
 {
     let private = context.api_context.get_user_private_safe().await;
     let mut userpage : Option<Content> = None;
+    let mut saved_searches : Vec<(SavedSearch, i32)> = Vec::new();
 
     if let Some(user) = &context.layout_data.user {
         let mut request = FullRequest::new();
@@ -121,13 +166,26 @@ async fn get_render_internal(context: PageContext,
 
         let mut userpage_raw = conversion::cast_result_safe::<Content>(&result, "userpage")?;
         userpage = userpage_raw.pop(); //Doesn't matter if it's none
+
+        //Saved searches need a second request, since the counts are built from the searches themselves
+        let saved = prefab::get_saved_searches(&mut context.api_context, user.id).await?;
+        if !saved.is_empty() {
+            let count_request = get_saved_search_count_request(&saved);
+            let count_result = context.api_context.post_request_profiled_opt(&count_request, "saved_search_counts").await?;
+            for (index, saved_search) in saved.into_iter().enumerate() {
+                let count = conversion::cast_result_safe::<SpecialCount>(&count_result, &get_saved_search_countkey(index))?
+                    .pop().map(|c| c.specialCount).unwrap_or(0);
+                saved_searches.push((saved_search, count));
+            }
+        }
     }
 
-    Ok(Response::Render(render(context.layout_data, private, userpage, update_errors, bio_errors, private_errors)))
+    Ok(Response::Render(render(context.layout_data, private, userpage, SavedSearches { searches: saved_searches, errors: saved_errors }, 
+        update_errors, bio_errors, private_errors)))
 }
 
 pub async fn get_render(context: PageContext) -> Result<Response, Error> {
-    get_render_internal(context, None, None, None).await
+    get_render_internal(context, None, None, None, None).await
 }
 
 
@@ -151,49 +209,19 @@ pub async fn post_info_render(mut context: PageContext, update: UserUpdate) -> R
         errors.push(String::from("Couldn't pull user data, are you still logged in?"));
     }
 
-    get_render_internal(context, Some(errors), None, None).await 
+    get_render_internal(context, Some(errors), None, None, None).await 
 }
 
-/// Complicated function for posting a simple user bio yeesh
-pub async fn post_userbio(data: &MainLayoutData, context: &ApiContext, form: &BasicPage) -> Result<Content, Error>
+/// Complicated function for posting a simple user bio yeesh. Anything else stored on the userpage is kept
+pub async fn post_userbio(data: &MainLayoutData, context: &mut ApiContext, form: &BasicPage) -> Result<Content, Error>
 {
     if let Some(ref user) = data.user {
-        let mut request = FullRequest::new();
-        add_value!(request, "type", "userpages"); //Need the parent
-
-        let mut parent_request = build_request!(
-            RequestType::content, 
-            String::from("id,parentId,literalType"), 
-            String::from("literalType = @type")
-        ); 
-        parent_request.name = Some(String::from("parent"));
-        request.requests.push(parent_request);
-
-        let result = context.post_request(&request).await?;
-
-        let mut parents_raw = conversion::cast_result_required::<Content>(&result, "parent")?;
-
-        match parents_raw.pop() {
-            Some(parent) => {
-                let mut content = Content::default();
-                //note: the hash it autogenerated from the name (hopefully)
-                content.text = Some(form.text.clone());
-                content.id = Some(form.id);
-                content.parentId = parent.id;
-                content.contentType = Some(ContentType::USERPAGE);
-                content.name = Some(format!("{}'s userpage", user.username));
-                content.permissions = Some(make_permissions! {
-                    "0": "CR" //Create so people can post on your "wall" (idk if that'll ever happen)
-                });
-                content.values = Some(make_values! {
-                    "markup": "bbcode"
-                });
-                context.post_content(&content, None).await.map_err(|e| e.into())
-            }
-            None => {
-                Err(Error::Other(String::from("Couldn't find the userpage parent! This is a programming error!")))
-            }
-        }
+        let mut content = match prefab::get_userpage(context, user.id).await? {
+            Some(userpage) => userpage,
+            None => prefab::new_userpage(context, user).await?
+        };
+        content.text = Some(form.text.clone());
+        context.post_content(&content, None).await.map_err(|e| e.into())
     }
     else {
         Err(Error::Other(String::from("Not logged in!")))
@@ -202,17 +230,17 @@ pub async fn post_userbio(data: &MainLayoutData, context: &ApiContext, form: &Ba
 
 /// Post to update user bio. It's a bit of a complicated process, but you call this function to perform
 /// everything and render the resulting page afterwards, error or not
-pub async fn post_bio_render(context: PageContext, bio: BasicPage) -> Result<Response, Error>
+pub async fn post_bio_render(mut context: PageContext, bio: BasicPage) -> Result<Response, Error>
 {
     //Both go to the same place, AND the userhome renderer reads the data after this write anyway,
     //so you just have to handle the errors
     let mut errors = Vec::new();
-    match post_userbio(&context.layout_data, &context.api_context, &bio).await {
+    match post_userbio(&context.layout_data, &mut context.api_context, &bio).await {
         Ok(_content) => {},
         Err(error) => { errors.push(error.to_user_string()) }
     };
 
-    get_render_internal(context, None, Some(errors), None).await 
+    get_render_internal(context, None, Some(errors), None, None).await 
 }
 
 pub async fn post_sensitive_render(context: PageContext, sensitive: UserSensitive) -> Result<Response, Error>
@@ -223,5 +251,37 @@ pub async fn post_sensitive_render(context: PageContext, sensitive: UserSensitiv
         Err(error) => { errors.push(error.to_user_string()) }
     };
 
-    get_render_internal(context, None, None, Some(errors)).await 
-}
\ No newline at end of file
+    get_render_internal(context, None, None, Some(errors), None).await 
+}
+
+/// Rename or delete one of the user's saved searches
+pub async fn post_savedsearch_render(mut context: PageContext, form: SavedSearchForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+
+    if let Some(user) = &context.layout_data.user {
+        match prefab::get_saved_searches(&mut context.api_context, user.id).await?.into_iter().find(|s| s.key == form.key) {
+            Some(mut saved) => {
+                let result = if form.delete {
+                    context.api_context.delete_uservariable(&saved.key).await.map(|_| ()).map_err(|e| e.into())
+                }
+                else if form.name.trim().is_empty() {
+                    Err(Error::User(String::from("Saved searches need a name!")))
+                }
+                else {
+                    saved.name = form.name.trim().to_string();
+                    prefab::post_saved_search(&context.api_context, &saved).await
+                };
+                if let Err(error) = result {
+                    errors.push(error.to_user_string());
+                }
+            },
+            None => errors.push(format!("Couldn't find saved search {}", form.key))
+        }
+    }
+    else {
+        errors.push(String::from("Couldn't pull user data, are you still logged in?"));
+    }
+
+    get_render_internal(context, None, None, None, Some(errors)).await 
+}
@@ -1,6 +1,7 @@
 use bbscope::BBCode;
 
 use common::*;
+use common::render::csrf_input;
 use common::render::layout::*;
 use common::response::*;
 use maud::*;
@@ -17,6 +18,7 @@ pub fn render(data: MainLayoutData, bbcode: &BBCode, text: Option<String>) -> St
         }
         @else {
             form method="POST" action={(data.links.http_root)"/widget/bbcodepreview"} {
+                (csrf_input(&data))
                 textarea placeholder="Enter text to test here" name="text"{}
                 input type="submit" value="Test";
             }
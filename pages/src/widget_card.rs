@@ -0,0 +1,36 @@
+use common::*;
+use common::prefab::*;
+use common::render::*;
+use common::render::layout::*;
+use common::render::submissions::*;
+use common::response::*;
+use common::view::get_votes;
+use maud::*;
+
+/// A page card all by itself, meant for embedding in other sites (see oembed)
+pub fn render(data: MainLayoutData, card: CardPage) -> String
+{
+    let (upvotes, downvotes) = get_votes(&card.page);
+    let totalvotes = upvotes + downvotes;
+
+    basic_skeleton(&data, html! {
+        title { "SBS ⦁ " (opt_s!(card.page.name)) }
+        meta name="description" content=(short_description(&card.page));
+        //We're inside someone else's page, all links have to go out to the real site
+        base target="_blank";
+        (data.links.style("/forpage/cardwidget.css"))
+    }, html! {
+        div #"main" {
+            (page_card(&data.links, &card.page, &card.users))
+            div #"votebar" data-votes=(totalvotes) title={(upvotes) " up, " (downvotes) " down"} {
+                div #"voteline" style=(format!("width:{}%", (upvotes as f32) / (totalvotes.max(1) as f32) * 100.0)) { }
+            }
+        }
+    }).into_string()
+}
+
+pub async fn get_render(mut context: PageContext, hash: &str) -> Result<Response, Error>
+{
+    let card = get_card_page(&mut context.api_context, hash).await?;
+    Ok(Response::Render(render(context.layout_data, card)))
+}
@@ -0,0 +1,111 @@
+use common::*;
+use common::collections::*;
+use common::forms::CollectionWidgetForm;
+use common::render::*;
+use common::render::layout::*;
+use common::response::*;
+use maud::*;
+
+use contentapi::*;
+
+pub fn render(data: MainLayoutData, content: Content, collections: Vec<Collection>, errors: Option<Vec<String>>) -> String
+{
+    let content_id = content.id.unwrap_or_default();
+    let favorites = get_favorite_count(&content);
+
+    basic_skeleton(&data, html! {
+        title { "SmileBASIC Source Collections Widget" }
+        meta name="description" content="A small widget to add pages to collections without reloading a main page";
+        (data.links.style("/forpage/collectionswidget.css"))
+    }, html! {
+        div #"main" {
+            div #"favoritecount" { "Favorited by " (favorites) " user" @if favorites != 1 { "s" } }
+            @if data.user.is_some() {
+                (errorlist(errors))
+                div #"collectionlist" {
+                    @for collection in &collections {
+                        @let contains = collection.pages.contains(&content_id);
+                        form."nospacing collection" method="POST" action=(data.current()) {
+                            (csrf_input(&data))
+                            input type="hidden" name="key" value=(collection.key);
+                            @if contains { input type="hidden" name="remove" value="true"; }
+                            input."notheme" type="submit" data-current[contains]
+                                value={ @if contains { "✔ " } @else { "+ " } (collection.name) }
+                                title={ @if contains { "Remove from " } @else { "Add to " } (collection.name) };
+                        }
+                    }
+                }
+                form."nospacing" #"newcollection" method="POST" action=(data.current()) {
+                    (csrf_input(&data))
+                    input type="text" name="name" required="" placeholder="New collection";
+                    input type="submit" value="Add";
+                }
+                a."flatlink" #"managelink" target="_top" href=(data.links.collections()) { "Manage collections" }
+            }
+        }
+    }).into_string()
+}
+
+async fn get_render_internal(mut context: PageContext, content_id: i64, errors: Option<Vec<String>>) -> Result<Response, Error>
+{
+    let content = context.api_context.get_content_by_id(content_id, "id,name,engagement").await?;
+    let collections = match context.layout_data.user {
+        Some(ref user) => prefab::get_collections(&mut context.api_context, user.id).await?,
+        None => Vec::new()
+    };
+
+    Ok(Response::Render(render(context.layout_data, content, collections, errors)))
+}
+
+pub async fn get_render(context: PageContext, content_id: i64) -> Result<Response, Error>
+{
+    get_render_internal(context, content_id, None).await
+}
+
+/// Add or remove the page from one of the user's collections (or add it to a brand new one)
+pub async fn post_render(mut context: PageContext, content_id: i64, form: CollectionWidgetForm) -> Result<Response, Error>
+{
+    let mut errors = Vec::new();
+
+    if let Some(user) = context.layout_data.user.clone() {
+        let mut collections = prefab::get_collections(&mut context.api_context, user.id).await?;
+
+        let index = if let Some(ref key) = form.key {
+            collections.iter().position(|c| &c.key == key)
+        }
+        else if let Some(name) = form.name.as_deref().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            collections.push(Collection { key: Collection::new_key(), name: name.to_string(), ..Default::default() });
+            Some(collections.len() - 1)
+        }
+        else {
+            None
+        };
+
+        match index {
+            Some(index) => {
+                let collection = &mut collections[index];
+                collection.pages.retain(|p| *p != content_id);
+                if !form.remove {
+                    collection.pages.push(content_id);
+                }
+                let result = async {
+                    prefab::post_collection(&context.api_context, &collections[index]).await?;
+                    prefab::sync_favorite(&context.api_context, content_id, &collections).await?;
+                    if collections[index].public {
+                        prefab::publish_collection(&mut context.api_context, user.id, &collections[index]).await?;
+                    }
+                    Ok::<(), Error>(())
+                }.await;
+                if let Err(error) = result {
+                    errors.push(error.to_user_string());
+                }
+            },
+            None => errors.push(String::from("Couldn't find that collection (or the new collection had no name)"))
+        }
+    }
+    else {
+        errors.push(String::from("You must be logged in to use collections!"));
+    }
+
+    get_render_internal(context, content_id, Some(errors)).await
+}
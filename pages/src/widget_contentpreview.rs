@@ -21,7 +21,7 @@ pub fn render(mut context: PageContext, form: ContentPreviewForm) -> String
         meta name="description" content="Show the rendered content (unless it's js rendering)";
         //(data.links.style("/forpage/bbcodepreview.css"))
     }, html! {
-        (render_content_nocontent(form.text, form.markup, &mut context.bbcode))
+        (render_content_nocontent(form.text, form.markup, &mut context.bbcode, &context.layout_data.links))
     }).into_string()
 }
 
@@ -59,9 +59,13 @@ pub async fn get_render(mut context: PageContext, hash: &str, high_density: bool
                 @if let Some(ptc_files) = page.ptc {
                     @if let Some(ptc_data) = ptc_files.text {
                         @let parsed_data = serde_json::de::from_str::<Vec<PtcData>>(&ptc_data)?;
-                        @for ptc_file in parsed_data {
+                        div."controls mediumseparate" {
+                            a href=(context.layout_data.links.ptc_download_all(&page.main)) { "Download all files (.zip)" }
+                        }
+                        @for (index, ptc_file) in parsed_data.into_iter().enumerate() {
                             hr;
                             h3 { (ptc_file.name) }
+                            a href=(context.layout_data.links.ptc_download(&page.main, index)) { "Download " (crate::page_ptc::get_ptc_filename(&ptc_file)) }
                             @if let Some(ref description) = ptc_file.description {
                                 p { (description)}
                             }
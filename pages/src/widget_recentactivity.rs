@@ -34,7 +34,7 @@ pub async fn get_render(mut context: PageContext, config: RecentActivityConfig)
 
     let content_request = build_request!(
         RequestType::content,
-        String::from("id,name,description,values,lastActionDate,hash,literalType,contentType"), 
+        String::from("id,name,description,values,lastActionDate,lastRevisionId,hash,literalType,contentType"), 
         String::from("literalType in @allowed_types"),
         String::from("lastActionDate_desc"),
         count
@@ -46,6 +46,11 @@ pub async fn get_render(mut context: PageContext, config: RecentActivityConfig)
 
     //content_revision_request.name = Some(String::from(""));
 
+    //Any new post or edit bumps lastActionDate, and the order of the list comes along with the ids
+    let etag = content.iter()
+        .fold(data.etag(), |etag, c| etag.with(&c.id).with(&c.lastActionDate).with(&c.lastRevisionId))
+        .finish();
+
     Ok(Response::Render(
         basic_skeleton(data, html! {
             title { "SmileBASIC Source Recent Activity" }
@@ -60,5 +65,5 @@ pub async fn get_render(mut context: PageContext, config: RecentActivityConfig)
                 }
             }
         }).into_string()
-    ))
+    ).with_etag(etag))
 }
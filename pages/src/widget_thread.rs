@@ -18,7 +18,7 @@ pub fn render(context: &mut PageContext, config: PostsConfig) -> String {
         title { "SmileBASIC Source Image Browser" }
         meta name="description" content="Simple image browser widget";
         (context.layout_data.links.style("/forpage/forum.css"))
-        style { r#"
+        style nonce=(context.layout_data.csp_nonce) { r#"
             body { 
                 /* This shrinks the WHOLE page! */
                 font-size: 0.85rem; 
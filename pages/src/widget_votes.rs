@@ -2,6 +2,7 @@ use common::*;
 use common::prefab::*;
 use common::constants::{DOWNVOTE, UPVOTE, VOTETYPE};
 use common::forms::VoteForm;
+use common::render::csrf_input;
 use common::render::layout::*;
 use common::response::*;
 use maud::*;
@@ -18,19 +19,7 @@ pub fn render(data: MainLayoutData, content: Content, user_vote: Option<ContentE
         }
     }
 
-    let mut downvotes = 0;
-    let mut upvotes = 0;
-
-    if let Some(ref engagement) = content.engagement {
-        if let Some(vote_engagements) = engagement.get(VOTETYPE) {
-            if let Some(downvote_count) = vote_engagements.get(DOWNVOTE) {
-                downvotes = *downvote_count;
-            }
-            if let Some(upvote_count) = vote_engagements.get(UPVOTE) {
-                upvotes = *upvote_count;
-            }
-        }
-    }
+    let (upvotes, downvotes) = common::view::get_votes(&content);
 
     let totalvotes = downvotes + upvotes;
 
@@ -42,6 +31,7 @@ pub fn render(data: MainLayoutData, content: Content, user_vote: Option<ContentE
         div #"main" {
             @if data.user.is_some() {
                 form."nospacing" #"downvote" method="POST" action=(data.current()) { 
+                    (csrf_input(&data))
                     input type="hidden" name="vote" value=(DOWNVOTE);
                     input."notheme" type="submit" value="-" title="Downvote" data-current[real_vote==DOWNVOTE];
                 }
@@ -52,6 +42,7 @@ pub fn render(data: MainLayoutData, content: Content, user_vote: Option<ContentE
             }
             @if data.user.is_some() {
                 form."nospacing" #"upvote" method="POST" action=(data.current()) { 
+                    (csrf_input(&data))
                     input type="hidden" name="vote" value=(UPVOTE);
                     input."notheme" type="submit" value="+" title="Upvote" data-current[real_vote==UPVOTE];
                 }
@@ -70,7 +61,14 @@ pub async fn get_render(context: PageContext, content_id: i64) -> Result<Respons
     let content = context.api_context.get_content_by_id(content_id, "id,name,engagement").await?;
     let engagement = get_content_vote(&context.api_context, content_id).await?; //context.api_context.get_content_by_id(id, "id,name,engagement").await?;
 
-    Ok(Response::Render(render(context.layout_data, content, engagement)))
+    //The widget is nothing but the counts and which button you pressed, so that's all the tag needs
+    let etag = context.layout_data.etag()
+        .with(&content.id)
+        .with(&common::view::get_votes(&content))
+        .with(&engagement.as_ref().and_then(|e| e.engagement.clone()))
+        .finish();
+
+    Ok(Response::Render(render(context.layout_data, content, engagement)).with_etag(etag))
 }
 
 pub async fn post_render(context: PageContext, content_id: i64, form: VoteForm) -> Result<Response, Error>
@@ -0,0 +1,52 @@
+//! Conditional GET. Pages that know what their output depends on attach an ETag (see Response::with_etag);
+//! when the browser sends that same tag back in If-None-Match, the page is swapped for an empty 304. The
+//! page still runs its queries to build the tag, but nothing gets rendered out over the wire. This mostly
+//! matters for the widgets, which are iframed on every page and reloaded constantly.
+
+use axum::{
+    body::{self, Empty},
+    http::{HeaderValue, Method, Request, StatusCode, header},
+    middleware::Next,
+    response::Response
+};
+
+/// Whether the request's If-None-Match has the given (already formatted) ETag, using the weak comparison
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    match (if_none_match.to_str(), etag.to_str()) {
+        (Ok(if_none_match), Ok(etag)) => {
+            let etag = etag.trim_start_matches("W/");
+            if_none_match.split(',').map(|t| t.trim()).any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+        },
+        _ => false
+    }
+}
+
+pub async fn not_modified_layer<B>(request: Request<B>, next: Next<B>) -> Response
+{
+    let if_none_match = match *request.method() {
+        Method::GET | Method::HEAD => request.headers().get(header::IF_NONE_MATCH).cloned(),
+        _ => None
+    };
+
+    let response = next.run(request).await;
+
+    let if_none_match = match if_none_match {
+        Some(value) if response.status() == StatusCode::OK => value,
+        _ => return response
+    };
+
+    match response.headers().get(header::ETAG) {
+        Some(etag) if etag_matches(&if_none_match, etag) => {
+            //A 304 has to carry the same caching headers the 200 would have
+            let mut result = Response::new(body::boxed(Empty::new()));
+            *result.status_mut() = StatusCode::NOT_MODIFIED;
+            for name in [header::ETAG, header::CACHE_CONTROL, header::VARY] {
+                if let Some(value) = response.headers().get(&name) {
+                    result.headers_mut().insert(name, value.clone());
+                }
+            }
+            result
+        },
+        _ => response
+    }
+}
@@ -0,0 +1,118 @@
+//! Cross site request forgery protection. Every POST made with a session cookie has to carry the token for
+//! that session, which only our own pages know (see common::render::csrf_input). The token is a keyed hash 
+//! of the session itself, so there's nothing to store: the key is made fresh on startup, which means a form 
+//! left open across a restart gets the "expired" page once. Requests without a session aren't checked, there's
+//! nothing for another site to ride on and those pages need to stay cacheable.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderValue, Method, Request, header},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+use common::constants::CSRFFIELD;
+use tower_cookies::Cookies;
+
+use crate::state::{GlobalState, RequestContext};
+use crate::routing::{SESSIONCOOKIE, SETTINGSCOOKIE};
+use crate::security::CspNonce;
+
+/// Lets scripts send the token without building a form body
+pub static CSRFHEADER: &str = "x-csrf-token";
+
+/// The secret the tokens are derived from. RandomState is seeded from the OS, and siphash with a secret
+/// key is exactly the kind of keyed hash this needs
+pub struct CsrfKey(RandomState);
+
+impl CsrfKey {
+    pub fn new() -> Self {
+        Self(RandomState::new())
+    }
+
+    pub fn token(&self, session: &str) -> String {
+        let part = |n: u8| {
+            let mut hasher = self.0.build_hasher();
+            n.hash(&mut hasher);
+            session.hash(&mut hasher);
+            hasher.finish()
+        };
+        format!("{:016x}{:016x}", part(0), part(1))
+    }
+
+    /// Compare without bailing at the first wrong character
+    pub fn verify(&self, session: &str, token: &str) -> bool {
+        let expected = self.token(session);
+        expected.len() == token.len() && 
+            expected.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+/// Pull the token out of the header or the urlencoded body. The body has to be read to do that, so it's put
+/// back together afterwards for the real handler
+async fn find_token(request: Request<Body>, max_size: usize) -> (Request<Body>, Option<String>)
+{
+    if let Some(token) = request.headers().get(CSRFHEADER).and_then(|t| t.to_str().ok()) {
+        let token = token.to_string();
+        return (request, Some(token));
+    }
+
+    let urlencoded = request.headers().get(header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .map(|c| c.starts_with("application/x-www-form-urlencoded"))
+        .unwrap_or(false);
+
+    if !urlencoded {
+        return (request, None);
+    }
+
+    let (parts, body) = request.into_parts();
+
+    //Same limit the handlers get; anything bigger wasn't going to be accepted anyway
+    match hyper::body::to_bytes(http_body::Limited::new(body, max_size)).await {
+        Ok(bytes) => {
+            let token = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&bytes).ok()
+                .and_then(|fields| fields.into_iter().find(|(k,_)| k == CSRFFIELD).map(|(_,v)| v));
+            (Request::from_parts(parts, Body::from(bytes)), token)
+        },
+        Err(_) => (Request::from_parts(parts, Body::empty()), None)
+    }
+}
+
+pub async fn csrf_layer(State(state): State<Arc<GlobalState>>, cookies: Cookies, request: Request<Body>, next: Next<Body>) -> Response
+{
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    let session = match cookies.get(SESSIONCOOKIE) {
+        Some(cookie) => cookie.value().to_string(),
+        None => return next.run(request).await
+    };
+
+    let (request, token) = find_token(request, state.config.body_maxsize as usize).await;
+
+    if let Some(ref token) = token {
+        if state.csrf_key.verify(&session, token) {
+            return next.run(request).await;
+        }
+    }
+
+    println!("Rejected {} {} with {} csrf token", request.method(), request.uri().path(), if token.is_some() { "a bad" } else { "no" });
+
+    //Render the explanation like any other page, for whoever the session says this is
+    let config_raw = cookies.get(SETTINGSCOOKIE).map(|c| c.value().to_string());
+    let nonce = request.extensions().get::<CspNonce>().map(|n| n.0.clone()).unwrap_or_default();
+    let result = match RequestContext::generate(state.clone(), request.uri().path(), Some(session), config_raw, nonce).await {
+        Ok(context) => pages::csrf::get_render(context.page_context).await,
+        Err(error) => Err(error)
+    };
+
+    let mut response = common::response::flatten(result).into_response();
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
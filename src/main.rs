@@ -1,22 +1,39 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
-use bbscope::{BBCode, BBCodeTagConfig, BBCodeLinkTarget};
+use bbscope::{BBCode, BBCodeTagConfig, BBCodeLinkTarget, ScopeInfo};
 use chrono::SecondsFormat;
 use common::LinkConfig;
 
 mod state;
 mod routing;
+mod pagecache;
+mod conditional;
+mod csrf;
+mod security;
+mod ratelimit;
+mod unavailable;
 
 use crate::state::*;
 
 static CONFIGNAME : &str = "settings";
+/// The sitemap is never rebuilt more often than this, and a failed build is retried after this long
+const SITEMAPMINREFRESH : u64 = 60;
 
 //The standard config we want here in this application. This macro is ugly but 
 //it produces a config object that can load from a chain of json files
 onestop::create_config!{
     Config, OptConfig => {
         api_endpoint: String,
+        api_timeout_seconds: u64,
+        api_retries: u32,
+        api_retry_delay_ms: u64,
+        api_breaker_failures: u32,
+        api_breaker_seconds: u64,
+        api_ca_bundle: String,
+        api_client_cert: String,
+        api_client_key: String,
         http_root: String,
+        public_url: String,
         api_fileraw : String,
         default_cookie_expire: i32,
         long_cookie_expire: i32,
@@ -26,6 +43,22 @@ onestop::create_config!{
         default_display_posts : i32,
         default_display_pages : i32,
         default_activity_count: i32,
+        default_tagcloud_count: i32,
+        default_feed_count: i32,
+        related_cache_seconds: u64,
+        related_cache_capacity: usize,
+        sitemap_refresh_seconds: u64,
+        render_cache_capacity: usize,
+        render_cache_dir: String,
+        page_cache_seconds: HashMap<String, u64>,
+        page_cache_capacity: usize,
+        rate_limits: HashMap<String, common::ratelimit::RateLimit>,
+        trusted_proxies: Vec<String>,
+        static_dir: String,
+        static_max_age: u64,
+        compress_responses: bool,
+        csp_report_only: bool,
+        hsts_seconds: u64,
         forum_category_order: Vec<String>,
         //file_maxsize: i32,
         body_maxsize: i32, //this can be used for a lot of things, I don't really care
@@ -50,7 +83,21 @@ async fn main()
         let mut config = BBCodeTagConfig::extended();
         config.link_target = BBCodeLinkTarget::None;
         config.newline_to_br = false;
-        BBCode::from_config(config, None).unwrap()
+        //Code blocks are highlighted here rather than in the browser, so swap out the stock [code]
+        config.accepted_tags.retain(|t| t != "code");
+        let mut matchers = Vec::new();
+        BBCode::add_tagmatcher(&mut matchers, "code", ScopeInfo {
+            only: Some(BBCode::plaintext_ids()),
+            double_closes: false,
+            emit: Arc::new(|o, b, _c| {
+                let lang = o.as_ref().and_then(|o| o.name("attr")).map(|a| a.as_str());
+                let code = html_escape::decode_html_entities(b);
+                format!(r#"<pre class="code"{} data-highlighted>{}</pre>"#, 
+                    lang.map(|l| format!(" data-code=\"{}\"", html_escape::encode_quoted_attribute(l))).unwrap_or_default(),
+                    common::highlight::highlight_code(&code, lang))
+            })
+        }, Some((0,1)), Some((0,1))).unwrap();
+        BBCode::from_config(config, Some(matchers)).unwrap()
     };
 
     //Set up the SINGULAR global state, which will be passed around with a counting reference.
@@ -66,17 +113,68 @@ async fn main()
                 file_root: format!("{}/raw", config.api_fileraw),
                 file_upload_root: format!("{}/low", config.api_fileraw),
                 http_root: root,
+                public_root: config.public_url.trim_end_matches('/').to_string(),
                 cache_bust : chrono::offset::Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true) //.to_string()
             }
         },
+        related_cache: common::prefab::RelatedCache::new(
+            std::time::Duration::from_secs(config.related_cache_seconds), 
+            config.related_cache_capacity
+        ),
+        sitemap_cache: common::prefab::SitemapCache::default(),
+        page_cache: pagecache::PageCache::new(&config.page_cache_seconds, config.page_cache_capacity),
+        csrf_key: csrf::CsrfKey::new(),
+        nonce_source: security::NonceSource::new(),
+        rate_limiter: Arc::new(common::ratelimit::RateLimiter::new(&config.rate_limits)),
+        api_client: Arc::new(contentapi::client::ApiClient::new(
+            config.api_endpoint.clone(),
+            contentapi::client::ClientOptions {
+                timeout: std::time::Duration::from_secs(config.api_timeout_seconds),
+                retries: config.api_retries,
+                retry_delay: std::time::Duration::from_millis(config.api_retry_delay_ms),
+                breaker_failures: config.api_breaker_failures,
+                breaker_cooldown: std::time::Duration::from_secs(config.api_breaker_seconds),
+                tls: contentapi::client::TlsOptions {
+                    ca_bundle: Some(config.api_ca_bundle.clone()).filter(|p| !p.is_empty()).map(std::path::PathBuf::from),
+                    client_cert: Some(config.api_client_cert.clone()).filter(|p| !p.is_empty()).map(std::path::PathBuf::from),
+                    client_key: Some(config.api_client_key.clone()).filter(|p| !p.is_empty()).map(std::path::PathBuf::from)
+                }
+            }
+        ).unwrap_or_else(|error| panic!("Couldn't set up the api connection: {}", error))),
+        render_cache: Arc::new(common::cache::RenderCache::new(
+            config.render_cache_capacity,
+            Some(config.render_cache_dir.clone()).filter(|d| !d.is_empty()).map(std::path::PathBuf::from)
+        )),
         config
     });
 
+    //Building the sitemap goes through the whole site, far too long to make a crawler wait on, so it's kept
+    //fresh in the background. Until the first build is done, the sitemap just says to come back later
+    let sitemap_state = global_state.clone();
+    tokio::spawn(async move {
+        let refresh = std::time::Duration::from_secs(sitemap_state.config.sitemap_refresh_seconds.max(SITEMAPMINREFRESH));
+        loop {
+            //Everyone sees the same sitemap, so it must never have anything only some user can see
+            let mut context = contentapi::endpoints::ApiContext::new(sitemap_state.api_client.clone(), None);
+            let wait = match common::prefab::refresh_sitemap(&mut context, &sitemap_state.link_config, &sitemap_state.sitemap_cache).await {
+                Ok(count) => {
+                    println!("Rebuilt sitemap with {} urls", count);
+                    refresh
+                },
+                Err(error) => {
+                    println!("Couldn't rebuild the sitemap, keeping the old one: {}", error.to_user_string());
+                    refresh.min(std::time::Duration::from_secs(SITEMAPMINREFRESH))
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    });
+
     let address = global_state.config.host_address.parse::<SocketAddr>().unwrap();
     let app = routing::get_all_routes(global_state.clone());
 
     axum::Server::bind(&address)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 
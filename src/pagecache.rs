@@ -0,0 +1,187 @@
+//! Whole-response cache for logged out visitors. Most of our traffic is people without an account reading
+//! the same threads and pages, and every one of those hits would otherwise generate a full request context
+//! and run the page queries. Only anonymous GETs of a few route groups are cached, each with its own
+//! lifetime, and any write made through this frontend throws out the groups it could have changed.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::{
+    body::{Bytes, Full},
+    extract::State,
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+use common::cache::TimedCache;
+use tower_cookies::Cookies;
+
+use crate::state::GlobalState;
+use crate::routing::{SESSIONCOOKIE, SETTINGSCOOKIE};
+use crate::security::CspNonce;
+
+/// Every group of routes that can be cached. The names are what go in page_cache_seconds
+const GROUPS: [&str; 4] = ["forum", "thread", "search", "documentation"];
+
+/// Which cache group (if any) a GET for the given path belongs to. Program and documentation pages are 
+/// threads as far as the routes are concerned, so they're in "thread"
+fn route_group(path: &str) -> Option<&'static str> {
+    if path == "/forum" || path.starts_with("/forum/category/") {
+        Some("forum")
+    }
+    else if path.starts_with("/forum/thread/") {
+        Some("thread")
+    }
+    else if path == "/search" || path == "/search/feed" {
+        Some("search")
+    }
+    else if path == "/documentation" {
+        Some("documentation")
+    }
+    else {
+        None
+    }
+}
+
+/// Which groups a successful write (any non-GET) to the given path could have changed. Writes we don't
+/// know about throw everything out: the alert banner, avatars, usernames etc. show up on every page
+fn invalidated_groups(path: &str) -> &'static [&'static str] {
+    if path.starts_with("/forum/edit/post") || path.starts_with("/forum/delete/post") ||
+       path.starts_with("/forum/edit/thread") || path.starts_with("/forum/delete/thread") {
+        &["forum", "thread"]
+    }
+    else if path.starts_with("/page/") {
+        &["thread", "search", "documentation"]
+    }
+    else if path.starts_with("/widget/votes/") {
+        &["search"]
+    }
+    else if path.starts_with("/widget/bbcodepreview") || path.starts_with("/widget/contentpreview") ||
+            path.starts_with("/widget/collections/") || path == "/collections" || path == "/search/save" ||
+            path == "/login" || path == "/register" || path == "/register/confirm" || path == "/recover" ||
+            path == "/sessionsettings" {
+        &[]
+    }
+    else {
+        &GROUPS
+    }
+}
+
+#[derive(Clone)]
+pub struct CachedPage {
+    status: StatusCode,
+    headers: HeaderMap,
+    /// The body cut up wherever the csp nonce it was rendered with went, so every hit gets put back
+    /// together with the fresh nonce for its own request. Reusing the old one would let anyone who saw
+    /// it once get script past the policy for as long as the page is cached
+    body: Vec<Bytes>
+}
+
+/// Cut the body up around every copy of the nonce
+fn split_nonce(body: &Bytes, nonce: &str) -> Vec<Bytes> {
+    let nonce = nonce.as_bytes();
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while !nonce.is_empty() && i + nonce.len() <= body.len() {
+        if &body[i..i + nonce.len()] == nonce {
+            pieces.push(body.slice(start..i));
+            i += nonce.len();
+            start = i;
+        }
+        else {
+            i += 1;
+        }
+    }
+    pieces.push(body.slice(start..));
+    pieces
+}
+
+pub struct PageCache {
+    groups: HashMap<&'static str, TimedCache<String, CachedPage>>,
+    /// Bumped on every invalidation, so a page that started rendering before a write isn't stored after it
+    generation: AtomicU64
+}
+
+impl PageCache {
+    /// Groups without a lifetime (or a lifetime of 0) aren't cached at all
+    pub fn new(seconds: &HashMap<String, u64>, capacity: usize) -> Self {
+        let mut groups = HashMap::new();
+        for (name, seconds) in seconds {
+            match GROUPS.iter().find(|g| *g == name) {
+                Some(group) if *seconds > 0 => {
+                    groups.insert(*group, TimedCache::new(Duration::from_secs(*seconds), capacity));
+                },
+                Some(_) => {},
+                None => println!("WARN: unknown page cache group '{}', valid groups are {:?}", name, GROUPS)
+            }
+        }
+        PageCache { groups, generation: AtomicU64::new(0) }
+    }
+
+    pub fn invalidate(&self, groups: &[&str]) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        for group in groups {
+            if let Some(cache) = self.groups.get(group) {
+                cache.clear();
+            }
+        }
+    }
+}
+
+/// The middleware which serves and fills the page cache, and invalidates it on writes
+pub async fn page_cache_layer<B>(State(state): State<Arc<GlobalState>>, cookies: Cookies, request: Request<B>, next: Next<B>) -> Response 
+{
+    let cache = &state.page_cache;
+    let path = request.uri().path().to_string();
+
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        let response = next.run(request).await;
+        if response.status().is_success() || response.status().is_redirection() {
+            cache.invalidate(invalidated_groups(&path));
+        }
+        return response;
+    }
+
+    let group_cache = match route_group(&path).and_then(|group| cache.groups.get(group)) {
+        Some(group_cache) if request.method() == Method::GET && cookies.get(SESSIONCOOKIE).is_none() => group_cache,
+        _ => return next.run(request).await
+    };
+
+    //Logged out users can still pick a theme etc, so their settings are part of what makes a page unique
+    let key = format!("{}\n{}", request.uri(), cookies.get(SETTINGSCOOKIE).map(|c| c.value().to_string()).unwrap_or_default());
+
+    let nonce = request.extensions().get::<CspNonce>().map(|n| n.0.clone()).unwrap_or_default();
+
+    if let Some(page) = group_cache.get(&key) {
+        let mut response = (page.status, page.headers, page.body.join(nonce.as_bytes())).into_response();
+        response.headers_mut().insert("x-page-cache", HeaderValue::from_static("hit"));
+        return response;
+    }
+
+    let generation = cache.generation.load(Ordering::SeqCst);
+    let response = next.run(request).await;
+
+    //Only plain successful pages are kept; anything setting cookies is specific to whoever asked for it
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::SET_COOKIE) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            println!("ERROR: couldn't read response body for page cache: {}", error);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Couldn't read response").into_response();
+        }
+    };
+
+    if cache.generation.load(Ordering::SeqCst) == generation {
+        group_cache.insert(key, CachedPage { status: parts.status, headers: parts.headers.clone(), body: split_nonce(&body, &nonce) });
+    }
+
+    parts.headers.insert("x-page-cache", HeaderValue::from_static("miss"));
+    Response::from_parts(parts, axum::body::boxed(Full::from(body)))
+}
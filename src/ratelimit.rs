@@ -0,0 +1,61 @@
+//! Which requests the rate limiter applies to and who they're from. The limiting itself is in 
+//! common::ratelimit; this is checked in the RequestContext extractor, since that's where we find out 
+//! who the user is (and have what's needed to render the "slow down" page).
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{extract::ConnectInfo, http::{Method, request::Parts}};
+
+use crate::Config;
+
+/// Which limit group a request falls under, if any. Only writes are limited; reading is what the page cache is for
+pub fn limit_group(method: &Method, path: &str, query: Option<&str>) -> Option<&'static str> {
+    if method == Method::GET || method == Method::HEAD {
+        return None;
+    }
+
+    //The multi-routes pick what they do from a query flag (see qflag)
+    let flag = |name: &str| query.map(|q| q.split('&').any(|p| p.split('=').next() == Some(name))).unwrap_or(false);
+
+    if path == "/login" {
+        Some(if flag("recover") { "email" } else { "login" })
+    }
+    else if path == "/recover" {
+        Some("login")
+    }
+    else if path == "/register" {
+        Some("register")
+    }
+    else if path == "/register/confirm" {
+        Some(if flag("resend") { "email" } else { "register" })
+    }
+    else if path.starts_with("/forum/edit/") || path.starts_with("/forum/delete/") || 
+            path == "/page/edit" || path.starts_with("/page/delete/") {
+        Some("post")
+    }
+    else if path.starts_with("/widget/votes/") {
+        Some("vote")
+    }
+    else {
+        None
+    }
+}
+
+/// The address of whoever made the request. Behind a trusted proxy that's the last address in X-Forwarded-For
+/// that isn't another one of our proxies; anything further left was written by the client and can't be trusted
+pub fn client_ip(parts: &Parts, config: &Config) -> Option<IpAddr> {
+    let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>()?.0.ip();
+    let trusted = |ip: &IpAddr| config.trusted_proxies.iter().any(|t| t.parse::<IpAddr>().ok().as_ref() == Some(ip));
+
+    if !trusted(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded = parts.headers.get_all("x-forwarded-for").iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    Some(forwarded.into_iter().rev().find(|ip| !trusted(ip)).unwrap_or(peer))
+}
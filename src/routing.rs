@@ -2,11 +2,13 @@ use std::sync::Arc;
 
 use axum::{
     routing::{get, post},
-    Router, extract::{DefaultBodyLimit, Query, FromRequestParts, Path}, async_trait, Form, http::StatusCode, response::IntoResponse, 
+    Router, extract::{DefaultBodyLimit, Query, RawQuery, FromRequestParts, Path, State}, async_trait, Form, response::IntoResponse, 
+    http::{StatusCode, HeaderValue, Request, header}, middleware::Next
 };
 
 use tower_cookies::{CookieManagerLayer, Cookies, Cookie, cookie::{time::Duration, SameSite}};
-use tower_http::{services::{ServeDir, ServeFile}, limit::RequestBodyLimitLayer};
+use tower::ServiceBuilder;
+use tower_http::{services::{ServeDir, ServeFile}, limit::RequestBodyLimitLayer, compression::CompressionLayer, set_header::SetResponseHeaderLayer};
 
 use crate::state::{RequestContext, GlobalState};
 use crate::srender;
@@ -19,8 +21,8 @@ pub mod registerconfirm;
 pub mod forum;
 pub mod page;
 
-static SESSIONCOOKIE: &str = "sbs-rust-contentapi-session";
-static SETTINGSCOOKIE: &str = "sbs-rust-contentapi-settings";
+pub static SESSIONCOOKIE: &str = "sbs-rust-contentapi-session";
+pub static SETTINGSCOOKIE: &str = "sbs-rust-contentapi-settings";
 
 type StdResponse = Result<common::response::Response, common::response::Error>;
 
@@ -33,6 +35,17 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
     #[derive(serde::Deserialize, Default)]
     struct QrParam { high_density: Option<bool> }
 
+    let static_dir = std::path::Path::new(&gstate.config.static_dir);
+
+    //Crawlers need the full url to the sitemap, which depends on where we're hosted. A missing robots.txt
+    //isn't worth not starting over, crawlers still get pointed at the sitemap
+    let robots = std::fs::read_to_string(static_dir.join("robots.txt"))
+        .unwrap_or_else(|e| {
+            println!("WARN: couldn't read robots.txt, using a bare one: {}", e);
+            String::from("User-agent: *\nDisallow: /widget/\nDisallow: /admin\nSitemap: {{sitemap}}\n")
+        })
+        .replace("{{sitemap}}", &gstate.link_config.absolute(&gstate.link_config.sitemap()));
+
     // build our application with a route
     let app = Router::new()
         .route("/", 
@@ -47,8 +60,49 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
             get(|context: RequestContext, Query(search): Query<pages::activity::ActivityQuery>|
                 srender!(pages::activity::get_render(context.page_context, search, context.global_state.config.default_activity_count))))
         .route("/search",
-            get(|context: RequestContext, Query(search): Query<common::forms::PageSearch>|
-                srender!(pages::search::get_render(context.page_context, search, context.global_state.config.default_display_pages))))
+            get(|context: RequestContext, RawQuery(query): RawQuery| async move {
+                //Systems and categories are multi-select, which plain Query can't handle
+                let search = common::parse_query_compound::<common::forms::PageSearch>(query.as_deref().unwrap_or(""))
+                    .map_err(|e| common::response::Error::User(format!("Bad search: {}", e)))?;
+                pages::search::get_render(context.page_context, search, context.global_state.config.default_display_pages).await
+            }))
+        .route("/activity/feed",
+            get(|context: RequestContext|
+                srender!(pages::activity::get_feed_render(context.page_context, context.global_state.config.default_feed_count))))
+        .route("/search/feed",
+            get(|context: RequestContext, RawQuery(query): RawQuery| async move {
+                let search = common::parse_query_compound::<common::forms::PageSearch>(query.as_deref().unwrap_or(""))
+                    .map_err(|e| common::response::Error::User(format!("Bad search: {}", e)))?;
+                pages::search::get_feed_render(context.page_context, search, context.global_state.config.default_feed_count).await
+            }))
+        .route("/sitemap.xml",
+            get(|context: RequestContext|
+                srender!(pages::sitemap::get_render(context.page_context, &context.global_state.sitemap_cache))))
+        .route("/sitemap/:page",
+            get(|State(state): State<Arc<GlobalState>>, Path(page): Path<usize>|
+                srender!(pages::sitemap::get_page_render(&state.sitemap_cache, page))))
+        .route("/search/save",
+            post(|context: RequestContext, Form(form): Form<common::forms::SaveSearchForm>|
+                srender!(pages::search::post_save_render(context.page_context, form))))
+        .route("/search/saved/:key",
+            post(|context: RequestContext, Path(key): Path<String>|
+                srender!(pages::search::post_saved_redirect(context.page_context, &key))))
+        .route("/tags",
+            get(|context: RequestContext|
+                srender!(pages::tags::get_render(context.page_context, context.global_state.config.default_tagcloud_count))))
+        .route("/tags/:keyword",
+            get(|context: RequestContext, Path(keyword): Path<String>, Query(query): Query<pages::tags::TagQuery>|
+                srender!(pages::tags::get_tag_render(context.page_context, keyword, query, context.global_state.config.default_display_pages))))
+        .route("/collections",
+            get(|context: RequestContext| srender!(pages::collections::get_render(context.page_context)))
+            .post(|context: RequestContext, Form(form): Form<common::forms::CollectionForm>|
+                srender!(pages::collections::post_render(context.page_context, form))))
+        .route("/user/:username/feed",
+            get(|context: RequestContext, Path(username): Path<String>|
+                srender!(pages::user::get_feed_render(context.page_context, username, context.global_state.config.default_feed_count))))
+        .route("/user/:username/collection/:key",
+            get(|context: RequestContext, Path((username, key)): Path<(String, String)>|
+                srender!(pages::collections::get_collection_render(context.page_context, username, key))))
         .route("/allsearch", 
             get(|context: RequestContext, Query(search): Query<pages::searchall::SearchAllForm>| 
                 srender!(pages::searchall::get_render(context.page_context, search))))
@@ -101,12 +155,20 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
         .route("/forum/category/:hash", 
             get(|context: RequestContext, Path(hash): Path<String>, Query(page): Query<SimplePage>|
                 srender!(pages::forum_category::get_hash_render(context.page_context, hash, context.global_state.config.default_display_threads, page.page))))
+        .route("/forum/category/:hash/feed",
+            get(|context: RequestContext, Path(hash): Path<String>|
+                srender!(pages::forum_category::get_feed_render(context.page_context, hash, context.global_state.config.default_feed_count))))
+        .route("/forum/thread/:hash/feed",
+            get(|context: RequestContext, Path(hash): Path<String>|
+                srender!(pages::forum_thread::get_feed_render(context.page_context, hash, context.global_state.config.default_feed_count))))
         .route("/forum/thread/:hash", 
             get(|context: RequestContext, Path(hash): Path<String>, Query(page): Query<SimplePage>|
-                srender!(pages::forum_thread::get_hash_render(context.page_context, hash, context.global_state.config.default_display_posts, page.page))))
+                srender!(pages::forum_thread::get_hash_render(context.page_context, hash, context.global_state.config.default_display_posts, page.page,
+                    &context.global_state.related_cache))))
         .route("/forum/thread/:hash/:post", 
             get(|context: RequestContext, Path((hash,post)): Path<(String,i64)>|
-                srender!(pages::forum_thread::get_hash_postid_render(context.page_context, hash, post, context.global_state.config.default_display_posts))))
+                srender!(pages::forum_thread::get_hash_postid_render(context.page_context, hash, post, context.global_state.config.default_display_posts,
+                    &context.global_state.related_cache))))
         .route("/forum/delete/thread/:id",
             post(|context: RequestContext, Path(id): Path<i64>|
                 srender!(pages::forum_edit_thread::delete_render(context.page_context, id))))
@@ -131,6 +193,12 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
                 srender!(pages::page_edit::get_render(context.page_context, query.mode, query.page)))
             .post(|context: RequestContext, Form(form): Form<common::forms::PageForm>|
                 srender!(pages::page_edit::post_render(context.page_context, form))))
+        .route("/page/:hash/ptc",
+            get(|context: RequestContext, Path(hash): Path<String>|
+                srender!(pages::page_ptc::get_zip(context.page_context, &hash))))
+        .route("/page/:hash/ptc/:index",
+            get(|context: RequestContext, Path((hash, index)): Path<(String, usize)>|
+                srender!(pages::page_ptc::get_file(context.page_context, &hash, index))))
         .route("/page/delete/:id",
             post(|context: RequestContext, Path(id): Path<i64>|
                 srender!(pages::page_edit::delete_render(context.page_context, id))))
@@ -152,6 +220,17 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
                 srender!(pages::widget_votes::get_render(context.page_context, id)))
             .post(|context: RequestContext, Path(id): Path<i64>, Form(form): Form<common::forms::VoteForm>|
                 srender!(pages::widget_votes::post_render(context.page_context, id, form))))
+        .route("/widget/collections/:id",
+            get(|context: RequestContext, Path(id): Path<i64>|
+                srender!(pages::widget_collections::get_render(context.page_context, id)))
+            .post(|context: RequestContext, Path(id): Path<i64>, Form(form): Form<common::forms::CollectionWidgetForm>|
+                srender!(pages::widget_collections::post_render(context.page_context, id, form))))
+        .route("/widget/card/:hash",
+            get(|context: RequestContext, Path(hash): Path<String>|
+                srender!(pages::widget_card::get_render(context.page_context, &hash))))
+        .route("/oembed",
+            get(|context: RequestContext, Query(query): Query<pages::oembed::OEmbedQuery>|
+                srender!(pages::oembed::get_render(context.page_context, query))))
         .route("/widget/recentactivity", 
             get(|context: RequestContext, Query(query): Query<pages::widget_recentactivity::RecentActivityConfig>| 
                 srender!(pages::widget_recentactivity::get_render(context.page_context, query))))
@@ -159,20 +238,59 @@ pub fn get_all_routes(gstate: Arc<GlobalState>) -> Router
             get(|context: RequestContext, Path(hash): Path<String>, Query(query): Query<QrParam>| 
                 srender!(pages::widget_qr::get_render(context.page_context, &hash, 
                     if let Some(hd) = query.high_density { hd } else { false }))))
-        .nest_service("/static", ServeDir::new("static"))
-        .nest_service("/favicon.ico", ServeFile::new("static/resources/favicon.ico"))
-        .nest_service("/robots.txt", ServeFile::new("static/robots.txt"))
+        .nest_service("/static", ServiceBuilder::new()
+            .layer(axum::middleware::from_fn_with_state(gstate.clone(), static_cache_layer))
+            .service(ServeDir::new(static_dir).precompressed_br().precompressed_gzip()))
+        .nest_service("/favicon.ico", ServeFile::new(static_dir.join("resources/favicon.ico")))
+        .route("/robots.txt",
+            get(|| async move { ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], robots) }))
         .with_state(gstate.clone())
+        .layer(axum::middleware::from_fn_with_state(gstate.clone(), crate::unavailable::backend_unavailable_layer))
+        .layer(axum::middleware::from_fn_with_state(gstate.clone(), crate::pagecache::page_cache_layer))
+        .layer(axum::middleware::from_fn(crate::conditional::not_modified_layer))
+        //Outside the page cache so it keeps the plain bodies; files with a .br/.gz next to them already come
+        //out encoded and are left alone
+        .layer(CompressionLayer::new()
+            .gzip(gstate.config.compress_responses)
+            .br(gstate.config.compress_responses))
+        //The compression layer doesn't say the body depends on the encoding, so proxies need telling
+        .layer(SetResponseHeaderLayer::appending(header::VARY, HeaderValue::from_static("accept-encoding")))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(
             gstate.config.body_maxsize as usize
         ))
+        //Out here so it gets the plain body to read the token from (and can put it back)
+        .layer(axum::middleware::from_fn_with_state(gstate.clone(), crate::csrf::csrf_layer))
         .layer(CookieManagerLayer::new())
+        .layer(axum::middleware::from_fn_with_state(gstate.clone(), crate::security::security_headers_layer))
     ;
 
     app
 }
 
+/// Static files are linked with the cache bust as the query (see LinkConfig::style/script), so a url with
+/// the current one can never change and browsers can keep it forever. Everything else (resources, direct 
+/// links, old or made up cache busts) only gets kept for static_max_age
+async fn static_cache_layer<B>(State(state): State<Arc<GlobalState>>, request: Request<B>, next: Next<B>) -> axum::response::Response
+{
+    let busted = request.uri().query() == Some(state.link_config.cache_bust.as_str());
+    let mut response = next.run(request).await;
+
+    if response.status().is_success() {
+        let value = if busted {
+            String::from("public, max-age=31536000, immutable")
+        }
+        else {
+            format!("public, max-age={}", state.config.static_max_age)
+        };
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    response
+}
+
 //Generate a new login cookie with all the bits and bobs set appropriately
 fn get_new_login_cookie(token: String, expire_seconds : i64) -> Cookie<'static> {
     Cookie::build(SESSIONCOOKIE, token)
@@ -248,23 +366,53 @@ macro_rules! parseform {
     };
 }
 
+/// The "slow down" page, with how long to wait in the header as well
+fn throttled_response(data: common::MainLayoutData, wait: std::time::Duration) -> axum::response::Response {
+    let mut response = common::response::Response::RenderWithStatus(pages::ratelimited::render(data, wait), 429).into_response();
+    if let Ok(value) = HeaderValue::from_str(&wait.as_secs().to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
 #[async_trait]
 impl FromRequestParts<Arc<GlobalState>> for RequestContext
 {
-    type Rejection = common::response::Error;
+    type Rejection = axum::response::Response;
 
     async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &Arc<GlobalState>) -> Result<Self, Self::Rejection>
     {
         use axum::RequestPartsExt;
         let cookies = parts.extract::<Cookies>()
             .await
-            .map_err(|err| Self::Rejection::Other(err.1.to_string()))?;
+            .map_err(|err| common::response::Error::Other(err.1.to_string()).into_response())?;
         let full_uri = parts.extract::<axum::http::Uri>()
             .await.unwrap(); //Infallible?
         let path = full_uri.path();
 
         let token = cookies.get(SESSIONCOOKIE).and_then(|t| Some(t.value().to_string()));
         let config_raw = cookies.get(SETTINGSCOOKIE).and_then(|c| Some(c.value().to_string()));
-        RequestContext::generate(state.clone(), path, token, config_raw).await
+        let nonce = parts.extensions.get::<crate::security::CspNonce>().map(|n| n.0.clone()).unwrap_or_default();
+        //Limited by both where it's from and who it's from, so neither a new account nor a new network gets around it.
+        //Where it's from is checked before asking the backend anything, so a flood of requests costs it nothing;
+        //the page they get can't show who's logged in, but they're not getting anything done anyway
+        let group = crate::ratelimit::limit_group(&parts.method, path, full_uri.query());
+        if let (Some(group), Some(ip)) = (group, crate::ratelimit::client_ip(parts, &state.config)) {
+            if let Err(wait) = state.rate_limiter.check(group, &[format!("ip:{}", ip)]) {
+                let data = RequestContext::offline_layout_data(state, path, config_raw, nonce);
+                return Err(throttled_response(data, wait));
+            }
+        }
+
+        let context = RequestContext::generate(state.clone(), path, token, config_raw, nonce).await
+            .map_err(|err| err.into_response())?;
+
+        if let (Some(group), Some(user)) = (group, &context.page_context.layout_data.user) {
+            if let Err(wait) = state.rate_limiter.check(group, &[format!("user:{}", user.id)]) {
+                return Err(throttled_response(context.page_context.layout_data, wait));
+            }
+        }
+
+        Ok(context)
     }
 }
@@ -7,9 +7,12 @@ use super::{StdResponse, missing_type_response};
 //Admin is a multi-route, meaning multiple things can be posted to it.
 pub enum AdminPost {
     RegistrationConfig(contentapi::forms::RegistrationConfig),
-    Frontpage(common::forms::BasicPage),
-    DocsCustom(common::forms::BasicPage),
-    Alert(common::forms::BasicPage),
+    Frontpage(common::forms::SystemHtmlForm),
+    DocsCustom(common::forms::SystemHtmlForm),
+    Alert(common::forms::SystemHtmlForm),
+    Category(common::forms::CategoryForm),
+    Retag(common::forms::RetagForm),
+    ForumCategory(common::forms::ForumCategoryForm),
 }
 
 #[async_trait]
@@ -17,8 +20,11 @@ impl<B, S> FromRequest<S, B> for AdminPost
 where 
     B: Send + 'static,
     S: Send + Sync,
-    Form<common::forms::BasicPage>: FromRequest<(), B>,
+    Form<common::forms::SystemHtmlForm>: FromRequest<(), B>,
     Form<contentapi::forms::RegistrationConfig>: FromRequest<(), B>,
+    Form<common::forms::CategoryForm>: FromRequest<(), B>,
+    Form<common::forms::RetagForm>: FromRequest<(), B>,
+    Form<common::forms::ForumCategoryForm>: FromRequest<(), B>,
 {
     type Rejection = axum::response::Response;
 
@@ -28,13 +34,22 @@ where
             parseform!(AdminPost::RegistrationConfig, contentapi::forms::RegistrationConfig, req)
         }
         else if  qflag!(frontpage, req) {
-            parseform!(AdminPost::Frontpage, common::forms::BasicPage, req)
+            parseform!(AdminPost::Frontpage, common::forms::SystemHtmlForm, req)
         }
         else if  qflag!(docscustom, req) {
-            parseform!(AdminPost::DocsCustom, common::forms::BasicPage, req)
+            parseform!(AdminPost::DocsCustom, common::forms::SystemHtmlForm, req)
         }
         else if  qflag!(alert, req) {
-            parseform!(AdminPost::Alert, common::forms::BasicPage, req)
+            parseform!(AdminPost::Alert, common::forms::SystemHtmlForm, req)
+        }
+        else if  qflag!(category, req) {
+            parseform!(AdminPost::Category, common::forms::CategoryForm, req)
+        }
+        else if  qflag!(retag, req) {
+            parseform!(AdminPost::Retag, common::forms::RetagForm, req)
+        }
+        else if  qflag!(forumcategory, req) {
+            parseform!(AdminPost::ForumCategory, common::forms::ForumCategoryForm, req)
         }
         else {
             Err(missing_type_response())
@@ -57,5 +72,14 @@ pub async fn admin_post(context: RequestContext, post: AdminPost) -> StdResponse
         AdminPost::Alert(form) => {
             pages::admin::post_alert(context.page_context, form).await
         },
+        AdminPost::Category(form) => {
+            pages::admin::post_category(context.page_context, form).await
+        },
+        AdminPost::Retag(form) => {
+            pages::admin::post_retag(context.page_context, form).await
+        },
+        AdminPost::ForumCategory(form) => {
+            pages::admin::post_forumcategory(context.page_context, form).await
+        },
     }
 }
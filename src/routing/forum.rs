@@ -18,10 +18,11 @@ pub async fn forum_get(context: RequestContext, Query(query): Query<ForumFullQue
     //Order goes from most precise to least
     if let Some(fpid) = query.fpid {
         pages::forum_thread::get_fpid_render(context.page_context, fpid, 
-            context.global_state.config.default_display_posts).await
+            context.global_state.config.default_display_posts, &context.global_state.related_cache).await
     }
     else if let Some(ftid) = query.ftid {
-        pages::forum_thread::get_ftid_render(context.page_context, ftid, context.global_state.config.default_display_posts, query.page).await
+        pages::forum_thread::get_ftid_render(context.page_context, ftid, context.global_state.config.default_display_posts, query.page,
+            &context.global_state.related_cache).await
     }
     else if let Some(fcid) = query.fcid {
         //Err(common::response::Error::NotFound(String::from("FCID is disabled right now")))
@@ -9,6 +9,7 @@ pub enum UserhomePost {
     UserUpdate(common::forms::UserUpdate),
     BioUpdate(common::forms::BasicPage),
     SensitiveUpdate(contentapi::forms::UserSensitive),
+    SavedSearchUpdate(common::forms::SavedSearchForm),
 }
 
 #[async_trait]
@@ -19,6 +20,7 @@ where
     Form<common::forms::UserUpdate>: FromRequest<(), B>,
     Form<common::forms::BasicPage>: FromRequest<(), B>,
     Form<contentapi::forms::UserSensitive>: FromRequest<(), B>,
+    Form<common::forms::SavedSearchForm>: FromRequest<(), B>,
 {
     type Rejection = axum::response::Response;
 
@@ -31,6 +33,9 @@ where
         else if  qflag!(sensitive, req) {
             parseform!(UserhomePost::SensitiveUpdate, contentapi::forms::UserSensitive, req)
         }
+        else if qflag!(savedsearch, req) {
+            parseform!(UserhomePost::SavedSearchUpdate, common::forms::SavedSearchForm, req)
+        }
         else {
             parseform!(UserhomePost::UserUpdate, common::forms::UserUpdate, req)
         }
@@ -49,5 +54,8 @@ pub async fn userhome_post(context: RequestContext, post: UserhomePost) -> StdRe
         UserhomePost::SensitiveUpdate(form) => {
             pages::userhome::post_sensitive_render(context.page_context, form).await
         },
+        UserhomePost::SavedSearchUpdate(form) => {
+            pages::userhome::post_savedsearch_render(context.page_context, form).await
+        },
     }
 }
@@ -0,0 +1,122 @@
+//! Security headers for everything we send. The interesting one is the Content-Security-Policy: scripts and
+//! styles have to come from us, and the few inline blocks in the layout carry a nonce that's made fresh for
+//! every request. That's what keeps raw html (admin alerts, the front page) and anything that slips through
+//! markup from running script. Pages served out of the page cache get the nonce swapped for the current one.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    extract::State,
+    http::{HeaderValue, Request, StatusCode, header},
+    middleware::Next,
+    response::Response
+};
+
+use crate::state::GlobalState;
+
+/// The nonce for the current request. The security layer puts it in the request extensions for the
+/// page to render with
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+/// Nonces only have to be unguessable, so a secret keyed hash of a counter does the job
+pub struct NonceSource {
+    key: RandomState,
+    counter: AtomicU64
+}
+
+impl NonceSource {
+    pub fn new() -> Self {
+        Self { key: RandomState::new(), counter: AtomicU64::new(0) }
+    }
+
+    pub fn next(&self) -> CspNonce {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        let part = |n: u64| {
+            let mut hasher = self.key.build_hasher();
+            hasher.write_u64(count);
+            hasher.write_u64(n);
+            hasher.finish()
+        };
+        CspNonce(format!("{:016x}{:016x}", part(0), part(1)))
+    }
+}
+
+/// Just the scheme and host of a url, which is what csp sources want
+fn origin(url: &str) -> Option<&str> {
+    let start = url.find("://")? + 3;
+    match url[start..].find('/') {
+        Some(end) => Some(&url[..start + end]),
+        None => Some(url)
+    }
+}
+
+/// Pages other sites are allowed to put in an iframe (see oembed). Everything else only goes in our own pages
+fn embeddable(path: &str) -> bool {
+    path.starts_with("/widget/card/")
+}
+
+fn policy(state: &GlobalState, nonce: &str, path: &str) -> String {
+    //The api hosts the images and takes the uploads from the image browser directly
+    let mut api = [origin(&state.config.api_endpoint), origin(&state.config.api_fileraw)]
+        .into_iter().flatten().collect::<Vec<_>>();
+    api.dedup();
+    let api = api.join(" ");
+
+    [
+        String::from("default-src 'self'"),
+        format!("script-src 'self' 'nonce-{}'", nonce),
+        format!("style-src 'self' 'nonce-{}'", nonce),
+        //Plenty of elements get sized and hidden with a style attribute, which is harmless
+        String::from("style-src-attr 'unsafe-inline'"),
+        //Posts link images and media from wherever
+        String::from("img-src * data: blob:"),
+        String::from("media-src *"),
+        format!("connect-src 'self' {}", api),
+        format!("form-action 'self' {}", api),
+        String::from("frame-src 'self'"),
+        String::from(if embeddable(path) { "frame-ancestors *" } else { "frame-ancestors 'self'" }),
+        String::from("object-src 'none'"),
+        String::from("base-uri 'self'"),
+    ].join("; ")
+}
+
+pub async fn security_headers_layer<B>(State(state): State<Arc<GlobalState>>, mut request: Request<B>, next: Next<B>) -> Response
+{
+    let nonce = state.nonce_source.next();
+    let path = request.uri().path().to_string();
+    request.extensions_mut().insert(nonce.clone());
+
+    let mut response = next.run(request).await;
+
+    //A 304 keeps the body the browser already has, which goes with the policy it already has
+    if response.status() != StatusCode::NOT_MODIFIED {
+        let name = if state.config.csp_report_only { 
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY 
+        } else { 
+            header::CONTENT_SECURITY_POLICY 
+        };
+        if let Ok(value) = HeaderValue::from_str(&policy(&state, &nonce.0, &path)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    let headers = response.headers_mut();
+    if !embeddable(&path) {
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+    }
+    headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("strict-origin-when-cross-origin"));
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+
+    //Only makes sense (and is only obeyed) when we're behind https, which we can't tell from in here
+    if state.config.hsts_seconds > 0 {
+        if let Ok(value) = HeaderValue::from_str(&format!("max-age={}", state.config.hsts_seconds)) {
+            headers.insert(header::STRICT_TRANSPORT_SECURITY, value);
+        }
+    }
+
+    response
+}
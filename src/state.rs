@@ -2,10 +2,17 @@ use std::sync::Arc;
 
 use bbscope::BBCode;
 use contentapi::endpoints::ApiContext;
+use contentapi::client::ApiClient;
 use common::{LinkConfig, MainLayoutData, UserConfig, PageContext};
+use common::prefab::{RelatedCache, SitemapCache};
+use common::cache::RenderCache;
+use common::ratelimit::RateLimiter;
 // use warp::path::FullPath;
 
 use crate::Config;
+use crate::pagecache::PageCache;
+use crate::csrf::CsrfKey;
+use crate::security::NonceSource;
 
 
 /// The unchanging configuration for the current runtime. Mostly values read from 
@@ -13,7 +20,15 @@ use crate::Config;
 pub struct GlobalState {
     pub link_config: LinkConfig,
     pub bbcode: BBCode,
-    pub config: Config
+    pub config: Config,
+    pub related_cache: RelatedCache,
+    pub sitemap_cache: SitemapCache,
+    pub render_cache: Arc<RenderCache>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub api_client: Arc<ApiClient>,
+    pub page_cache: PageCache,
+    pub csrf_key: CsrfKey,
+    pub nonce_source: NonceSource
 }
 
 /// A context generated for each request. Even if the request doesn't need all the data,
@@ -28,7 +43,8 @@ pub struct RequestContext {
 }
 
 impl RequestContext {
-    pub async fn generate(state: Arc<GlobalState>, path: &str, token: Option<String>, config_raw: Option<String>) -> 
+    pub async fn generate(state: Arc<GlobalState>, path: &str, token: Option<String>, config_raw: Option<String>, 
+        csp_nonce: String) -> 
         Result<Self, common::response::Error> 
     {
         #[cfg(feature = "profiling")]
@@ -36,14 +52,14 @@ impl RequestContext {
 
         #[cfg(feature = "profiling")]
         let mut context = ApiContext::new_with_profiler(
-            state.config.api_endpoint.clone(), 
+            state.api_client.clone(), 
             token.clone(),
             profiler.clone()
         );
 
         #[cfg(not(feature = "profiling"))]
         let context = ApiContext::new(
-            state.config.api_endpoint.clone(), 
+            state.api_client.clone(), 
             token.clone()
         );
 
@@ -54,6 +70,8 @@ impl RequestContext {
             UserConfig::default()
         };
 
+        let csrf_token = token.as_ref().map(|t| state.csrf_key.token(t));
+
         let layout_data = MainLayoutData 
         {
             links: state.link_config.clone(),
@@ -62,6 +80,8 @@ impl RequestContext {
             override_nav_path: None,
             user: context.get_me_safe().await,
             user_token: token,
+            csrf_token,
+            csp_nonce,
             about_api: context.get_about().await?,
             raw_alert: (common::prefab::get_system_alert(&mut context).await?).and_then(|x| x.text),
 
@@ -76,6 +96,8 @@ impl RequestContext {
                 layout_data,
                 api_context: context,
                 bbcode: BBCode { matchers: state.bbcode.matchers.clone(), profiler: profiler.clone() },
+                render_cache: state.render_cache.clone(),
+                rate_limiter: state.rate_limiter.clone()
             },
             //Custom construct bbcode so we copy the matchers but NOT the profiler!
             global_state: state,
@@ -92,4 +114,30 @@ impl RequestContext {
         });
     }
 
+    /// Layout data for when the backend can't be reached, so only what we know without asking it. Whoever
+    /// is logged in shows as logged out, since there's no way to know who that is
+    pub fn offline_layout_data(state: &GlobalState, path: &str, config_raw: Option<String>, csp_nonce: String) -> MainLayoutData
+    {
+        MainLayoutData 
+        {
+            links: state.link_config.clone(),
+            user_config: config_raw.and_then(|c| serde_json::from_str::<UserConfig>(&c).ok()).unwrap_or_default(),
+            current_path: String::from(path),
+            override_nav_path: None,
+            user: None,
+            user_token: None,
+            csrf_token: None,
+            csp_nonce,
+            about_api: contentapi::About {
+                version: String::from("?"),
+                environment: String::from("unavailable"),
+                runtime: String::new(),
+                contact: String::new()
+            },
+            raw_alert: None,
+
+            #[cfg(feature = "profiling")]
+            profiler: onestop::OneList::<onestop::OneDuration>::new()
+        }
+    }
 }
@@ -0,0 +1,46 @@
+//! The friendly side of the api client's timeouts and circuit breaker (see contentapi::client). Whenever a
+//! page fails because the backend couldn't be reached, the bare error it produced is swapped here for a
+//! proper "we'll be right back" page. It has to be built without the backend, so it can't go through
+//! RequestContext::generate like the other error pages do.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{HeaderValue, Request, header},
+    middleware::Next,
+    response::{Html, IntoResponse, Response}
+};
+use common::response::BackendUnavailable;
+use tower_cookies::Cookies;
+
+use crate::state::{GlobalState, RequestContext};
+use crate::routing::SETTINGSCOOKIE;
+use crate::security::CspNonce;
+
+pub async fn backend_unavailable_layer<B>(State(state): State<Arc<GlobalState>>, cookies: Cookies, request: Request<B>, next: Next<B>) -> Response
+{
+    let path = request.uri().path().to_string();
+    let nonce = request.extensions().get::<CspNonce>().map(|n| n.0.clone()).unwrap_or_default();
+
+    let response = next.run(request).await;
+
+    if response.extensions().get::<BackendUnavailable>().is_none() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let reason = hyper::body::to_bytes(body).await.unwrap_or_default();
+    println!("Backend unavailable for {}: {}", path, String::from_utf8_lossy(&reason));
+
+    let config_raw = cookies.get(SETTINGSCOOKIE).map(|c| c.value().to_string());
+    let data = RequestContext::offline_layout_data(&state, &path, config_raw, nonce);
+
+    let mut response = (parts.status, Html(pages::unavailable::render(data))).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if let Ok(value) = HeaderValue::from_str(&state.api_client.options.breaker_cooldown.as_secs().max(1).to_string()) {
+        headers.insert(header::RETRY_AFTER, value);
+    }
+    response
+}
@@ -1,27 +1,27 @@
 function fix_searchform()
 {
     var searchType = document.getElementById("search-type");
-    var searchCategory = document.getElementById("search-category");
+    var searchCategories = document.getElementById("search-categories");
 
-    //This makes sure you can only see categories for the type you selected
-    var refresh_categories = function() {
-        var options = searchCategory.querySelectorAll("option");
-        searchCategory.value = 0;
-        for(var i = 0; i < options.length; i++)
+    //This makes sure you can only see categories for the type you selected. Hidden categories
+    //are unchecked so they don't silently stay in the search
+    var refresh_categories = function(uncheck) {
+        var labels = searchCategories.querySelectorAll("label[data-for]");
+        for(var i = 0; i < labels.length; i++)
         {
-            var attr = options[i].getAttribute("data-for");
-            if(attr)
-            {
-                if(!searchType.value || attr === searchType.value)
-                    options[i].removeAttribute("hidden");
-                else
-                    options[i].setAttribute("hidden", "");
+            var attr = labels[i].getAttribute("data-for");
+            if(!searchType.value || attr === searchType.value) {
+                labels[i].removeAttribute("hidden");
+            }
+            else {
+                labels[i].setAttribute("hidden", "");
+                if(uncheck) labels[i].querySelector("input").checked = false;
             }
         }
     }
-    refresh_categories();
-    searchType.oninput = refresh_categories;
+    refresh_categories(false);
+    searchType.oninput = function() { refresh_categories(true); };
 }
 
 
-fix_searchform();
\ No newline at end of file
+fix_searchform();
@@ -64,6 +64,9 @@ function upgrade_markupeditors()
             var formData = new URLSearchParams(); //FormData();
             formData.append("text", rawtext.value);
             if(markup) formData.append("markup", markup.value);
+            //Logged in posts need the csrf token, which is in whatever form the editor is part of
+            var csrf = rawtext.form && rawtext.form.querySelector('input[name="csrf"]');
+            if(csrf) formData.append("csrf", csrf.value);
 
             fetch(SBSBASEURL + "/widget/contentpreview", {
                 method: "POST",
@@ -111,9 +114,10 @@ function upgrade_markup(element)
 function upgrade_code(element)
 {
     element = element || document;
-    var codes = element.querySelectorAll(".content .code");
+    //Anything the server already highlighted is marked with data-highlighted
+    var codes = element.querySelectorAll(".content .code:not([data-highlighted])");
     upgrade_code_general(codes);
-    codes = element.querySelectorAll(".Markup pre"); //This is what 12y considers code (ugh don't use just pre!!)
+    codes = element.querySelectorAll(".Markup pre:not([data-highlighted])"); //This is what 12y considers code (ugh don't use just pre!!)
     upgrade_code_general(codes);
 }
 